@@ -0,0 +1,685 @@
+//! Checks that a [`Backend`] behaves the way [`crate::CrawlMaster`] assumes it
+//! does. Backend authors should run these against their own backend:
+//! ```ignore
+//! #[test]
+//! fn conformance_test() {
+//!     conformance::block_on(conformance::run_all(|wave| async move {
+//!         MyBackend::init(my_config(), &wave).await
+//!     }))
+//!     .unwrap();
+//! }
+//! ```
+//! Every check runs on a wave of its own, which is removed afterwards. Checks
+//! involving two waves get a second backend, for another wave, from the same
+//! `new_backend`: backends it creates must share their storage. Checks of
+//! optional methods pass trivially for backends not implementing them.
+
+use anyhow::{ensure, Context};
+use std::future::Future;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::*;
+
+/// Runs all checks, each on a backend freshly created for a new wave by
+/// `new_backend`. Stops at the first failure.
+pub async fn run_all<B, F, Fut>(mut new_backend: F) -> Result<(), anyhow::Error>
+where
+    B: Backend,
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<B, anyhow::Error>>,
+{
+    macro_rules! run_checks {
+        ($($check:ident),* $(,)?) => {
+            $(
+                let wave = format!("conformance-{}-{}", stringify!($check), unique());
+                let mut backend = new_backend(wave)
+                    .await
+                    .context("could not create backend")?;
+                let outcome = $check(&mut backend).await;
+                backend.remove().await?;
+                outcome.with_context(|| {
+                    format!("conformance check `{}` failed", stringify!($check))
+                })?;
+            )*
+        };
+    }
+
+    run_checks!(
+        seeding_is_idempotent,
+        fetch_takes_pages,
+        fetch_respects_max_depth,
+        fetch_prefers_shallow_pages,
+        exists_taken_tracks_taken_pages,
+        reset_queue_reopens_unfinished_pages,
+        explored_is_idempotent,
+        analyses_are_idempotent,
+        linkage_is_between_closed_pages,
+        worker_factory_is_serializable,
+        wave_status_counts_pages,
+        remove_forgets_wave,
+    );
+
+    macro_rules! run_checks_with_other_wave {
+        ($($check:ident),* $(,)?) => {
+            $(
+                let wave = format!("conformance-{}-{}", stringify!($check), unique());
+                let other_wave = format!("{}-other", wave);
+                let mut backend = new_backend(wave)
+                    .await
+                    .context("could not create backend")?;
+                let mut other = new_backend(other_wave.clone())
+                    .await
+                    .context("could not create backend")?;
+                let outcome = $check(&mut backend, &mut other, &other_wave).await;
+                backend.remove().await?;
+                other.remove().await?;
+                outcome.with_context(|| {
+                    format!("conformance check `{}` failed", stringify!($check))
+                })?;
+            )*
+        };
+    }
+
+    run_checks_with_other_wave!(
+        list_waves_lists_created_waves,
+        waves_are_compared_side_by_side,
+        seeding_from_other_wave_keeps_depths,
+    );
+
+    Ok(())
+}
+
+/// Runs a future to completion in a new single-threaded runtime, since
+/// backends need not be `Send`.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("can build runtime")
+        .block_on(future)
+}
+
+fn unique() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_nanos()
+}
+
+/// The outcome of an optional method of a backend, or `None` if the backend
+/// does not implement it.
+fn supported<T>(outcome: Result<T, anyhow::Error>) -> Result<Option<T>, anyhow::Error> {
+    match outcome {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.is::<Unsupported>() => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn url(path: &str) -> Url {
+    format!("https://conformance.example{path}")
+        .parse()
+        .expect("valid url")
+}
+
+fn sorted(mut batch: Vec<(Url, u16)>) -> Vec<(Url, u16)> {
+    batch.sort();
+    batch
+}
+
+async fn worker<B: Backend>(
+    backend: &mut B,
+    master: &mut Box<dyn MasterBackend>,
+) -> Result<Box<dyn WorkerBackend>, anyhow::Error> {
+    backend.build_worker_factory(master.wave_id()).build().await
+}
+
+/// Seeds are open at depth 0 and seeding twice changes nothing.
+pub async fn seeding_is_idempotent<B: Backend>(backend: &mut B) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    master.ensure_seeded(&[url("/"), url("/seed")]).await?;
+    master.ensure_seeded(&[url("/")]).await?;
+
+    let batch = sorted(master.fetch(10, 0).await?);
+    ensure!(
+        batch == vec![(url("/"), 0), (url("/seed"), 0)],
+        "seeds should be fetched once, at depth 0; got {batch:?}"
+    );
+
+    Ok(())
+}
+
+/// Fetching takes at most `batch_size` pages and never hands the same page
+/// out twice.
+pub async fn fetch_takes_pages<B: Backend>(backend: &mut B) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    master
+        .ensure_seeded(&[url("/1"), url("/2"), url("/3")])
+        .await?;
+
+    let first = master.fetch(2, 0).await?;
+    ensure!(first.len() == 2, "expected 2 pages; got {first:?}");
+
+    let second = master.fetch(10, 0).await?;
+    ensure!(second.len() == 1, "expected 1 page; got {second:?}");
+    ensure!(
+        !first.contains(&second[0]),
+        "{:?} was fetched twice",
+        second[0]
+    );
+
+    let third = master.fetch(10, 0).await?;
+    ensure!(third.is_empty(), "expected no pages; got {third:?}");
+
+    Ok(())
+}
+
+/// Pages deeper than `max_depth` are not fetched.
+pub async fn fetch_respects_max_depth<B: Backend>(backend: &mut B) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    let worker = worker(backend, &mut master).await?;
+    master.ensure_seeded(&[url("/")]).await?;
+    master.fetch(10, 0).await?;
+    worker
+        .ensure_explored(
+            &url("/"),
+            StatusCode::OK,
+            1,
+            vec![(Reason::Ahref, url("/a"))],
+        )
+        .await?;
+
+    let batch = master.fetch(10, 0).await?;
+    ensure!(batch.is_empty(), "expected no pages; got {batch:?}");
+
+    let batch = master.fetch(10, 1).await?;
+    ensure!(
+        batch == vec![(url("/a"), 1)],
+        "expected `/a` at depth 1; got {batch:?}"
+    );
+
+    Ok(())
+}
+
+/// Among pages of the same host, shallower ones are fetched first.
+pub async fn fetch_prefers_shallow_pages<B: Backend>(backend: &mut B) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    let worker = worker(backend, &mut master).await?;
+    master.ensure_seeded(&[url("/"), url("/x")]).await?;
+    master.fetch(10, 0).await?;
+    worker
+        .ensure_explored(
+            &url("/x"),
+            StatusCode::OK,
+            2,
+            vec![(Reason::Ahref, url("/b"))],
+        )
+        .await?;
+    worker
+        .ensure_explored(
+            &url("/"),
+            StatusCode::OK,
+            1,
+            vec![(Reason::Ahref, url("/a"))],
+        )
+        .await?;
+
+    let batch = master.fetch(1, 5).await?;
+    ensure!(
+        batch == vec![(url("/a"), 1)],
+        "expected `/a` at depth 1; got {batch:?}"
+    );
+
+    Ok(())
+}
+
+/// A page is taken from when it is fetched until it is explored.
+pub async fn exists_taken_tracks_taken_pages<B: Backend>(
+    backend: &mut B,
+) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    let worker = worker(backend, &mut master).await?;
+    master.ensure_seeded(&[url("/")]).await?;
+    ensure!(!master.exists_taken().await?, "taken before fetching");
+
+    master.fetch(10, 0).await?;
+    ensure!(master.exists_taken().await?, "not taken after fetching");
+
+    worker
+        .ensure_explored(&url("/"), StatusCode::OK, 1, vec![])
+        .await?;
+    ensure!(!master.exists_taken().await?, "taken after exploring");
+
+    Ok(())
+}
+
+/// Resetting the queue reopens taken and errored pages, but not closed ones.
+pub async fn reset_queue_reopens_unfinished_pages<B: Backend>(
+    backend: &mut B,
+) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    let worker = worker(backend, &mut master).await?;
+    master
+        .ensure_seeded(&[url("/closed"), url("/error"), url("/taken")])
+        .await?;
+    master.fetch(10, 0).await?;
+    worker
+        .ensure_explored(&url("/closed"), StatusCode::OK, 1, vec![])
+        .await?;
+    worker.ensure_error(&url("/error")).await?;
+
+    master.reset_queue().await?;
+    ensure!(!master.exists_taken().await?, "taken after reset");
+
+    let batch = sorted(master.fetch(10, 0).await?);
+    ensure!(
+        batch == vec![(url("/error"), 0), (url("/taken"), 0)],
+        "expected `/error` and `/taken`; got {batch:?}"
+    );
+
+    Ok(())
+}
+
+/// Exploring a page twice closes it once and does not reopen pages that are
+/// linked to and were already seen.
+pub async fn explored_is_idempotent<B: Backend>(backend: &mut B) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    let worker = worker(backend, &mut master).await?;
+    master.ensure_seeded(&[url("/")]).await?;
+    master.fetch(10, 0).await?;
+
+    let links = vec![
+        (Reason::Ahref, url("/")),
+        (Reason::Ahref, url("/a")),
+        (Reason::Ahref, url("/b")),
+    ];
+    for _ in 0..2 {
+        worker
+            .ensure_explored(&url("/"), StatusCode::OK, 1, links.clone())
+            .await?;
+    }
+
+    let crawled = master.count_crawled().await?;
+    ensure!(crawled == 1, "expected 1 page crawled; got {crawled}");
+
+    let batch = sorted(master.fetch(10, 5).await?);
+    ensure!(
+        batch == vec![(url("/a"), 1), (url("/b"), 1)],
+        "expected `/a` and `/b` once, at depth 1; got {batch:?}"
+    );
+
+    Ok(())
+}
+
+/// Declaring analyses and storing results may be done more than once. Results
+/// already stored for a page are kept, which is only checked for backends
+/// implementing [`Backend::export`].
+pub async fn analyses_are_idempotent<B: Backend>(backend: &mut B) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    let worker = worker(backend, &mut master).await?;
+    let analyses = [("title".to_owned(), Type::String)];
+    master.create_analyses(&analyses).await?;
+    master.create_analyses(&analyses).await?;
+    master.ensure_seeded(&[url("/")]).await?;
+    master.fetch(10, 0).await?;
+
+    for title in ["home", "home", "changed"] {
+        worker
+            .ensure_analyzed(&url("/"), vec![("title".to_owned(), Value::from(title))])
+            .await?;
+    }
+    worker
+        .ensure_explored(&url("/"), StatusCode::OK, 1, vec![])
+        .await?;
+    drop((master, worker));
+
+    let export = match supported(backend.export().await)? {
+        Some(export) => export.context("wave does not exist")?,
+        None => return Ok(()),
+    };
+    ensure!(
+        export.analyses == analyses,
+        "expected analyses {analyses:?}; got {:?}",
+        export.analyses
+    );
+
+    let pages = export.pages.try_collect::<Vec<_>>().await?;
+    let expected = BTreeMap::from([("title".to_owned(), Value::from("home"))]);
+    ensure!(
+        pages.len() == 1 && pages[0].analyses == expected,
+        "expected `/` analyzed as {expected:?}; got {pages:?}"
+    );
+
+    Ok(())
+}
+
+/// The page rank only sees `ahref` links between closed pages.
+pub async fn linkage_is_between_closed_pages<B: Backend>(
+    backend: &mut B,
+) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    let worker = worker(backend, &mut master).await?;
+    master.ensure_seeded(&[url("/")]).await?;
+    master.fetch(10, 0).await?;
+    worker
+        .ensure_explored(
+            &url("/"),
+            StatusCode::OK,
+            1,
+            vec![
+                (Reason::Ahref, url("/a")),
+                (Reason::Ahref, url("/open")),
+                (Reason::Canonical, url("/b")),
+            ],
+        )
+        .await?;
+    master.fetch(10, 1).await?;
+    worker
+        .ensure_explored(
+            &url("/a"),
+            StatusCode::OK,
+            2,
+            vec![(Reason::Ahref, url("/"))],
+        )
+        .await?;
+    worker
+        .ensure_explored(&url("/b"), StatusCode::OK, 2, vec![])
+        .await?;
+    master.reset_queue().await?;
+
+    let mut ranker = backend.build_ranker(master.wave_id()).await?;
+    let n_edges = ranker.linkage().await?.count();
+    ensure!(n_edges == 2, "expected 2 edges; got {n_edges}");
+    ranker.page_rank().await?;
+
+    Ok(())
+}
+
+/// Worker backend factories survive a round trip through serialization, as
+/// they do when sent to remote workers.
+pub async fn worker_factory_is_serializable<B: Backend>(
+    backend: &mut B,
+) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    let factory = backend.build_worker_factory(master.wave_id());
+    let serialized = serde_json::to_string(&factory)?;
+    let factory: Box<dyn WorkerBackendFactory> = serde_json::from_str(&serialized)?;
+
+    master.ensure_seeded(&[url("/")]).await?;
+    master.fetch(10, 0).await?;
+    factory.build().await?.ensure_error(&url("/")).await?;
+
+    master.reset_queue().await?;
+    let batch = master.fetch(10, 0).await?;
+    ensure!(
+        batch == vec![(url("/"), 0)],
+        "expected `/` to be reopened; got {batch:?}"
+    );
+
+    Ok(())
+}
+
+/// The status of a wave counts its pages by search status, status code and
+/// depth, once the wave exists. Backends not implementing
+/// [`Backend::wave_status`] pass trivially.
+pub async fn wave_status_counts_pages<B: Backend>(backend: &mut B) -> Result<(), anyhow::Error> {
+    let status = match supported(backend.wave_status().await)? {
+        Some(status) => status,
+        None => return Ok(()),
+    };
+    ensure!(
+        status.is_none(),
+        "expected no status before creating the wave; got {status:?}"
+    );
+
+    let mut master = backend.build_master().await?;
+    let worker = worker(backend, &mut master).await?;
+    master
+        .ensure_seeded(&[url("/closed"), url("/error"), url("/taken")])
+        .await?;
+    master.fetch(10, 0).await?;
+    worker
+        .ensure_explored(
+            &url("/closed"),
+            StatusCode::OK,
+            1,
+            vec![(Reason::Ahref, url("/open"))],
+        )
+        .await?;
+    worker.ensure_error(&url("/error")).await?;
+    drop((master, worker));
+
+    let status = backend
+        .wave_status()
+        .await?
+        .context("expected a status for the wave")?;
+    ensure!(
+        (
+            status.n_open,
+            status.n_taken,
+            status.n_closed,
+            status.n_error
+        ) == (1, 1, 1, 1),
+        "expected one open, taken, closed and error page; got {status:?}"
+    );
+    ensure!(
+        status.status_codes == BTreeMap::from([(200, 1)])
+            && status.depths == BTreeMap::from([(0, 3), (1, 1)])
+            && !status.has_page_rank,
+        "expected one page with status code 200, three at depth 0, one at depth 1 and no page \
+            rank; got {status:?}"
+    );
+
+    Ok(())
+}
+
+/// Waves are listed once created, whichever wave the backend is for. Backends
+/// not implementing [`Backend::list_waves`] pass trivially.
+pub async fn list_waves_lists_created_waves<B: Backend>(
+    backend: &mut B,
+    other: &mut B,
+    other_wave: &str,
+) -> Result<(), anyhow::Error> {
+    let waves = match supported(backend.list_waves().await)? {
+        Some(waves) => waves,
+        None => return Ok(()),
+    };
+    ensure!(
+        waves.iter().all(|wave| wave.name != other_wave),
+        "`{other_wave}` listed before being created; got {waves:?}"
+    );
+
+    other.build_master().await?;
+    backend.build_master().await?;
+
+    let waves = backend.list_waves().await?;
+    ensure!(
+        waves.iter().filter(|wave| wave.name == other_wave).count() == 1,
+        "expected `{other_wave}` listed once; got {waves:?}"
+    );
+
+    Ok(())
+}
+
+/// Comparing pairs the pages crawled in either wave by URL, and fails while
+/// the other wave does not exist. Backends not implementing
+/// [`Backend::compare`] pass trivially.
+pub async fn waves_are_compared_side_by_side<B: Backend>(
+    backend: &mut B,
+    other: &mut B,
+    other_wave: &str,
+) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    match backend.compare(other_wave).await {
+        Err(err) if err.is::<Unsupported>() => return Ok(()),
+        Err(_) => {}
+        Ok(_) => anyhow::bail!("compared with `{other_wave}` before it was created"),
+    }
+
+    let this_worker = worker(backend, &mut master).await?;
+    master.ensure_seeded(&[url("/"), url("/same")]).await?;
+    master.fetch(10, 0).await?;
+    // A page redirects to one place, so backends may keep either redirect:
+    this_worker
+        .ensure_explored(
+            &url("/"),
+            StatusCode::MOVED_PERMANENTLY,
+            1,
+            vec![(Reason::Redirect, url("/a")), (Reason::Redirect, url("/b"))],
+        )
+        .await?;
+    this_worker
+        .ensure_explored(&url("/same"), StatusCode::OK, 1, vec![])
+        .await?;
+
+    let mut other_master = other.build_master().await?;
+    let other_worker = worker(other, &mut other_master).await?;
+    other_master
+        .ensure_seeded(&[url("/same"), url("/other")])
+        .await?;
+    other_master.fetch(10, 0).await?;
+    other_worker
+        .ensure_explored(&url("/same"), StatusCode::OK, 1, vec![])
+        .await?;
+    other_worker
+        .ensure_explored(&url("/other"), StatusCode::NOT_FOUND, 1, vec![])
+        .await?;
+    drop((master, this_worker, other_master, other_worker));
+
+    let mut pages = backend
+        .compare(other_wave)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    pages.sort_by(|x, y| x.url.cmp(&y.url));
+
+    let snapshot = |status_code, redirect| PageSnapshot {
+        status_code,
+        redirect,
+        analyses: BTreeMap::new(),
+    };
+    let redirected = pages
+        .first()
+        .and_then(|page| page.a.as_ref())
+        .and_then(|snapshot| snapshot.redirect.clone());
+    ensure!(
+        redirected == Some(url("/a")) || redirected == Some(url("/b")),
+        "expected `/` to redirect to `/a` or `/b`; got {pages:?}"
+    );
+
+    let expected = vec![
+        ComparedPage {
+            url: url("/"),
+            a: Some(snapshot(301, redirected)),
+            b: None,
+        },
+        ComparedPage {
+            url: url("/other"),
+            a: None,
+            b: Some(snapshot(404, None)),
+        },
+        ComparedPage {
+            url: url("/same"),
+            a: Some(snapshot(200, None)),
+            b: Some(snapshot(200, None)),
+        },
+    ];
+    ensure!(pages == expected, "expected {expected:?}; got {pages:?}");
+
+    Ok(())
+}
+
+/// Seeding from another wave opens the pages crawled there, at the depths they
+/// had, and counts those that are new to the wave. Nothing is seeded from a
+/// wave that does not exist. Backends not implementing
+/// [`MasterBackend::ensure_seeded_from`] pass trivially.
+pub async fn seeding_from_other_wave_keeps_depths<B: Backend>(
+    backend: &mut B,
+    other: &mut B,
+    other_wave: &str,
+) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    let seeded = match supported(master.ensure_seeded_from(other_wave, None).await)? {
+        Some(seeded) => seeded,
+        None => return Ok(()),
+    };
+    ensure!(
+        seeded.is_none(),
+        "seeded from `{other_wave}` before it was created; got {seeded:?}"
+    );
+
+    let mut other_master = other.build_master().await?;
+    let other_worker = worker(other, &mut other_master).await?;
+    other_master
+        .ensure_seeded(&[url("/"), url("/open")])
+        .await?;
+    other_master.fetch(1, 0).await?;
+    other_worker
+        .ensure_explored(
+            &url("/"),
+            StatusCode::OK,
+            1,
+            vec![(Reason::Ahref, url("/a"))],
+        )
+        .await?;
+    other_master.fetch(10, 1).await?;
+    other_worker
+        .ensure_explored(&url("/a"), StatusCode::OK, 2, vec![])
+        .await?;
+    drop((other_master, other_worker));
+
+    master.ensure_seeded(&[url("/")]).await?;
+    for expected in [1, 0] {
+        let seeded = master.ensure_seeded_from(other_wave, None).await?;
+        ensure!(
+            seeded == Some(expected),
+            "expected {expected} pages seeded from `{other_wave}`; got {seeded:?}"
+        );
+    }
+
+    let batch = sorted(master.fetch(10, 5).await?);
+    ensure!(
+        batch == vec![(url("/"), 0), (url("/a"), 1)],
+        "expected `/` at depth 0 and `/a` at depth 1; got {batch:?}"
+    );
+
+    Ok(())
+}
+
+/// A removed wave starts over from scratch. Backends not implementing
+/// [`Backend::remove`] pass trivially.
+pub async fn remove_forgets_wave<B: Backend>(backend: &mut B) -> Result<(), anyhow::Error> {
+    let mut master = backend.build_master().await?;
+    let worker = worker(backend, &mut master).await?;
+    master.ensure_seeded(&[url("/")]).await?;
+    master.fetch(10, 0).await?;
+    worker
+        .ensure_explored(&url("/"), StatusCode::OK, 1, vec![])
+        .await?;
+    drop((master, worker));
+
+    if !backend.remove().await?.was_removed() {
+        return Ok(());
+    }
+
+    ensure!(
+        !backend.remove().await?.was_removed(),
+        "wave was removed twice"
+    );
+
+    let crawled = backend.build_master().await?.count_crawled().await?;
+    ensure!(crawled == 0, "expected nothing crawled; got {crawled}");
+
+    Ok(())
+}
+
+#[test]
+fn memory_backend_conformance_test() {
+    let store = MemoryStore::default();
+
+    block_on(run_all(|wave| {
+        let store = store.clone();
+        async move { Ok(MemoryBackend::with_store(store, &wave)) }
+    }))
+    .unwrap();
+}
@@ -0,0 +1,1083 @@
+//! A backend that keeps everything in memory, for tests and small crawls.
+//!
+//! Nothing survives the process. The store can be inspected after the crawl
+//! through the [`MemoryStore`] handle obtained with [`MemoryBackend::store`]:
+//! ```ignore
+//! let backend = MemoryBackend::new("my-wave");
+//! let store = backend.store();
+//! CrawlMaster::new(configuration, backend, LocalHandlerFactory)
+//!     .start(profile, false, None, None)
+//!     .await?;
+//! let wave = store.wave("my-wave").expect("wave exists");
+//! ```
+
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use super::*;
+
+lazy_static! {
+    /// All live stores in this process, so that worker backend factories can
+    /// be (de)serialized as a mere id.
+    static ref STORES: Mutex<HashMap<u64, Weak<Mutex<Waves>>>> = Mutex::default();
+}
+
+static NEXT_STORE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Where a page is in the crawl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchStatus {
+    Open,
+    Taken,
+    Closed,
+    Error,
+}
+
+/// What is known about a page in a wave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PageStatus {
+    /// Only set when the page is [`SearchStatus::Closed`].
+    pub status_code: Option<u16>,
+    pub search_status: SearchStatus,
+    pub depth: u16,
+}
+
+/// Everything stored for a single wave.
+#[derive(Debug, Clone)]
+pub struct MemoryWave {
+    name: String,
+    started_at: NaiveDateTime,
+    configuration: Option<WaveConfiguration>,
+    status: BTreeMap<Url, PageStatus>,
+    linkage: BTreeSet<(Url, Url, Reason)>,
+    analyses: BTreeMap<String, Type>,
+    analysis_results: BTreeMap<Url, BTreeMap<String, Value>>,
+    page_ranks: BTreeMap<Url, f64>,
+    /// Pages seeded from a previous wave with a priority.
+    priorities: BTreeMap<Url, f64>,
+}
+
+impl MemoryWave {
+    fn new(name: &str) -> MemoryWave {
+        MemoryWave {
+            name: name.to_owned(),
+            started_at: chrono::Utc::now().naive_utc(),
+            configuration: None,
+            status: BTreeMap::new(),
+            linkage: BTreeSet::new(),
+            analyses: BTreeMap::new(),
+            analysis_results: BTreeMap::new(),
+            page_ranks: BTreeMap::new(),
+            priorities: BTreeMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn started_at(&self) -> NaiveDateTime {
+        self.started_at
+    }
+
+    /// The configuration the wave was created with, if it was crawled.
+    pub fn configuration(&self) -> Option<&WaveConfiguration> {
+        self.configuration.as_ref()
+    }
+
+    /// All pages known in this wave, crawled or not.
+    pub fn pages(&self) -> impl Iterator<Item = (&Url, &PageStatus)> {
+        self.status.iter()
+    }
+
+    pub fn status(&self, url: &Url) -> Option<&PageStatus> {
+        self.status.get(url)
+    }
+
+    /// The pages that were downloaded and analyzed.
+    pub fn crawled(&self) -> impl Iterator<Item = &Url> {
+        self.status
+            .iter()
+            .filter(|(_, status)| status.search_status == SearchStatus::Closed)
+            .map(|(url, _)| url)
+    }
+
+    /// All links found, as `(from, to, reason)`.
+    pub fn links(&self) -> impl Iterator<Item = &(Url, Url, Reason)> {
+        self.linkage.iter()
+    }
+
+    /// The analyses declared for this wave and their result types.
+    pub fn analysis_types(&self) -> &BTreeMap<String, Type> {
+        &self.analyses
+    }
+
+    /// The results of the analyses on a given page, if analyzed.
+    pub fn analyses(&self, url: &Url) -> Option<&BTreeMap<String, Value>> {
+        self.analysis_results.get(url)
+    }
+
+    pub fn page_rank(&self, url: &Url) -> Option<f64> {
+        self.page_ranks.get(url).copied()
+    }
+
+    pub fn page_ranks(&self) -> &BTreeMap<Url, f64> {
+        &self.page_ranks
+    }
+
+    /// What a crawled page looked like, or `None` if it was not crawled.
+    fn snapshot(&self, url: &Url) -> Option<PageSnapshot> {
+        let status = self.status.get(url)?;
+        if status.search_status != SearchStatus::Closed {
+            return None;
+        }
+
+        Some(PageSnapshot {
+            status_code: status.status_code.unwrap_or_default(),
+            redirect: self
+                .linkage
+                .iter()
+                .find(|(from, _, reason)| from == url && reason.is_redirect())
+                .map(|(_, to, _)| to.clone()),
+            analyses: self.analyses(url).cloned().unwrap_or_default(),
+        })
+    }
+
+    fn ensure_status(&mut self, url: &Url, depth: u16) {
+        self.status.entry(url.clone()).or_insert(PageStatus {
+            status_code: None,
+            search_status: SearchStatus::Open,
+            depth,
+        });
+    }
+
+    fn set_search_status(&mut self, url: &Url, search_status: SearchStatus) {
+        if let Some(status) = self.status.get_mut(url) {
+            status.search_status = search_status;
+        }
+    }
+
+    fn count(&self, search_status: SearchStatus) -> usize {
+        self.status
+            .values()
+            .filter(|status| status.search_status == search_status)
+            .count()
+    }
+
+    fn wave_status(&self) -> WaveStatus {
+        let mut status_codes = BTreeMap::new();
+        let mut depths = BTreeMap::new();
+
+        for status in self.status.values() {
+            if let Some(status_code) = status.status_code {
+                *status_codes.entry(status_code).or_default() += 1;
+            }
+            *depths.entry(status.depth).or_default() += 1;
+        }
+
+        WaveStatus {
+            name: self.name.clone(),
+            started_at: self.started_at,
+            n_open: self.count(SearchStatus::Open),
+            n_taken: self.count(SearchStatus::Taken),
+            n_closed: self.count(SearchStatus::Closed),
+            n_error: self.count(SearchStatus::Error),
+            status_codes,
+            depths,
+            has_page_rank: !self.page_ranks.is_empty(),
+        }
+    }
+
+    /// Open pages with depth up to `max_depth`, favoring hosts with fewer
+    /// pages in the batch and then prioritized and shallower pages. This
+    /// ensures a plurality of domains in each batch.
+    fn fetch(&mut self, batch_size: usize, max_depth: u16) -> Vec<(Url, u16)> {
+        let mut open = self
+            .status
+            .iter()
+            .filter(|(_, status)| {
+                status.search_status == SearchStatus::Open && status.depth <= max_depth
+            })
+            .map(|(url, status)| (url.clone(), status.depth, self.priorities.get(url).copied()))
+            .collect::<Vec<_>>();
+        open.sort_by(|(_, a_depth, a_priority), (_, b_depth, b_priority)| {
+            queue_order((*a_priority, *a_depth), (*b_priority, *b_depth))
+        });
+
+        let mut per_host = HashMap::new();
+        let mut numbered = open
+            .into_iter()
+            .map(|(url, depth, priority)| {
+                let count = per_host
+                    .entry(url.host_str().map(str::to_owned))
+                    .or_insert(0);
+                *count += 1;
+                (*count, url, depth, priority)
+            })
+            .collect::<Vec<_>>();
+        numbered.sort_by(
+            |(a_count, _, a_depth, a_priority), (b_count, _, b_depth, b_priority)| {
+                a_count
+                    .cmp(b_count)
+                    .then_with(|| queue_order((*a_priority, *a_depth), (*b_priority, *b_depth)))
+            },
+        );
+
+        numbered
+            .into_iter()
+            .take(batch_size)
+            .map(|(_, url, depth, _)| {
+                self.set_search_status(&url, SearchStatus::Taken);
+                (url, depth)
+            })
+            .collect()
+    }
+}
+
+/// Orders `(priority, depth)` pairs: prioritized pages first, highest
+/// priority first, and then shallower pages first.
+fn queue_order(a: (Option<f64>, u16), b: (Option<f64>, u16)) -> cmp::Ordering {
+    let by_priority = match (a.0, b.0) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (Some(_), None) => cmp::Ordering::Less,
+        (None, Some(_)) => cmp::Ordering::Greater,
+        (None, None) => cmp::Ordering::Equal,
+    };
+
+    by_priority.then(a.1.cmp(&b.1))
+}
+
+#[test]
+fn fetch_test() {
+    let mut wave = MemoryWave::new("test");
+    let url = |s: &str| s.parse::<Url>().unwrap();
+
+    wave.ensure_status(&url("https://a.foo/1"), 0);
+    wave.ensure_status(&url("https://a.foo/2"), 1);
+    wave.ensure_status(&url("https://a.foo/3"), 1);
+    wave.ensure_status(&url("https://b.foo/1"), 2);
+    wave.ensure_status(&url("https://b.foo/2"), 9);
+
+    let batch = wave.fetch(3, 5);
+    assert_eq!(
+        batch,
+        vec![
+            (url("https://a.foo/1"), 0),
+            (url("https://b.foo/1"), 2),
+            (url("https://a.foo/2"), 1),
+        ]
+    );
+    assert_eq!(wave.count(SearchStatus::Taken), 3);
+
+    // Too deep pages are never fetched:
+    assert_eq!(wave.fetch(3, 5), vec![(url("https://a.foo/3"), 1)]);
+    assert!(wave.fetch(3, 5).is_empty());
+
+    // Prioritized pages come first, whatever their depth:
+    wave.ensure_status(&url("https://a.foo/4"), 0);
+    wave.ensure_status(&url("https://a.foo/5"), 4);
+    wave.ensure_status(&url("https://a.foo/6"), 3);
+    wave.priorities.insert(url("https://a.foo/5"), 0.1);
+    wave.priorities.insert(url("https://a.foo/6"), 0.2);
+    assert_eq!(
+        wave.fetch(3, 5),
+        vec![
+            (url("https://a.foo/6"), 3),
+            (url("https://a.foo/5"), 4),
+            (url("https://a.foo/4"), 0),
+        ]
+    );
+}
+
+#[derive(Debug, Default)]
+struct Waves {
+    next_wave_id: i32,
+    by_id: BTreeMap<i32, MemoryWave>,
+}
+
+impl Waves {
+    fn ensure_wave(&mut self, name: &str) -> i32 {
+        if let Some((&wave_id, _)) = self.by_id.iter().find(|(_, wave)| wave.name == name) {
+            return wave_id;
+        }
+
+        self.next_wave_id += 1;
+        self.by_id.insert(self.next_wave_id, MemoryWave::new(name));
+
+        self.next_wave_id
+    }
+
+    /// The pages crawled in the wave `name`, with their depths and
+    /// priorities, or `None` if the wave does not exist.
+    fn seeds_from(
+        &self,
+        name: &str,
+        priority: Option<SeedPriority>,
+    ) -> Option<Vec<(Url, u16, Option<f64>)>> {
+        let wave = self.by_id.values().find(|wave| wave.name == name)?;
+        let priority_of = |url: &Url| match priority? {
+            SeedPriority::PageRank => wave.page_rank(url),
+            SeedPriority::ChangeFrequency => {
+                let versions = self
+                    .by_id
+                    .values()
+                    .filter_map(|wave| wave.snapshot(url))
+                    .map(|snapshot| {
+                        serde_json::to_string(&(snapshot.status_code, snapshot.analyses))
+                            .expect("can serialize")
+                    })
+                    .collect::<BTreeSet<_>>();
+                Some(versions.len() as f64)
+            }
+        };
+
+        Some(
+            wave.crawled()
+                .map(|url| (url.clone(), wave.status[url].depth, priority_of(url)))
+                .collect(),
+        )
+    }
+
+    fn wave_mut(&mut self, wave_id: i32) -> Result<&mut MemoryWave, anyhow::Error> {
+        self.by_id
+            .get_mut(&wave_id)
+            .ok_or_else(|| anyhow::anyhow!("wave {wave_id} does not exist in memory store"))
+    }
+}
+
+/// A handle to the contents of a [`MemoryBackend`], which outlives the
+/// backend itself.
+#[derive(Debug, Clone)]
+pub struct MemoryStore {
+    id: u64,
+    waves: Arc<Mutex<Waves>>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> MemoryStore {
+        let id = NEXT_STORE_ID.fetch_add(1, Ordering::Relaxed);
+        let waves = Arc::new(Mutex::default());
+
+        let mut stores = STORES.lock().expect("poisoned");
+        stores.retain(|_, store| store.strong_count() > 0);
+        stores.insert(id, Arc::downgrade(&waves));
+
+        MemoryStore { id, waves }
+    }
+}
+
+impl MemoryStore {
+    fn by_id(id: u64) -> Option<MemoryStore> {
+        let waves = STORES.lock().expect("poisoned").get(&id)?.upgrade()?;
+        Some(MemoryStore { id, waves })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Waves> {
+        self.waves.lock().expect("poisoned")
+    }
+
+    fn with_wave<F, T>(&self, wave_id: i32, f: F) -> Result<T, anyhow::Error>
+    where
+        F: FnOnce(&mut MemoryWave) -> T,
+    {
+        Ok(f(self.lock().wave_mut(wave_id)?))
+    }
+
+    /// The names of all waves in this store.
+    pub fn wave_names(&self) -> Vec<String> {
+        self.lock()
+            .by_id
+            .values()
+            .map(|wave| wave.name.clone())
+            .collect()
+    }
+
+    /// A snapshot of the current state of a wave.
+    pub fn wave(&self, name: &str) -> Option<MemoryWave> {
+        self.lock()
+            .by_id
+            .values()
+            .find(|wave| wave.name == name)
+            .cloned()
+    }
+}
+
+/// The in-memory backend has nothing to configure.
+#[derive(Debug, Default, Clone, Copy, StructOpt)]
+pub struct MemoryConfig {}
+
+/// A backend that keeps everything in memory. Workers must run in the same
+/// process, so this cannot be used with `lopez serve`.
+#[derive(Debug, Clone)]
+pub struct MemoryBackend {
+    store: MemoryStore,
+    wave: String,
+}
+
+impl MemoryBackend {
+    /// A backend over a new, empty store.
+    pub fn new(wave: &str) -> MemoryBackend {
+        MemoryBackend::with_store(MemoryStore::default(), wave)
+    }
+
+    /// A backend over an existing store, e.g. to run a second crawl on it.
+    pub fn with_store(store: MemoryStore, wave: &str) -> MemoryBackend {
+        MemoryBackend {
+            store,
+            wave: wave.to_owned(),
+        }
+    }
+
+    /// A handle to the data of this backend, to inspect the crawl results.
+    pub fn store(&self) -> MemoryStore {
+        self.store.clone()
+    }
+}
+
+#[async_trait(?Send)]
+impl Backend for MemoryBackend {
+    type Config = MemoryConfig;
+    type Ranker = MemoryPageRanker;
+
+    async fn init(_config: Self::Config, wave: &str) -> Result<Self, anyhow::Error> {
+        Ok(MemoryBackend::new(wave))
+    }
+
+    async fn build_master(&mut self) -> Result<Box<dyn MasterBackend>, anyhow::Error> {
+        let wave_id = self.store.lock().ensure_wave(&self.wave);
+        Ok(Box::new(MemoryMasterBackend {
+            store: self.store.clone(),
+            wave_id,
+        }))
+    }
+
+    fn build_worker_factory(&mut self, wave_id: i32) -> Box<dyn WorkerBackendFactory> {
+        Box::new(MemoryWorkerBackendFactory {
+            store_id: self.store.id,
+            wave_id,
+        })
+    }
+
+    async fn build_ranker(&mut self, wave_id: i32) -> Result<Self::Ranker, anyhow::Error> {
+        Ok(MemoryPageRanker {
+            store: self.store.clone(),
+            wave_id,
+        })
+    }
+
+    async fn remove(&mut self) -> Result<WaveRemoveReport, anyhow::Error> {
+        let mut waves = self.store.lock();
+        let found = waves
+            .by_id
+            .iter()
+            .find(|(_, wave)| wave.name == self.wave)
+            .map(|(&wave_id, _)| wave_id);
+
+        let report = if let Some(wave) = found.and_then(|wave_id| waves.by_id.remove(&wave_id)) {
+            WaveRemoveReport::removed(wave.count(SearchStatus::Closed))
+        } else {
+            WaveRemoveReport::not_removed()
+        };
+
+        Ok(report)
+    }
+
+    async fn list_waves(&mut self) -> Result<Vec<WaveSummary>, anyhow::Error> {
+        Ok(self
+            .store
+            .lock()
+            .by_id
+            .values()
+            .map(|wave| WaveSummary {
+                name: wave.name.clone(),
+                started_at: wave.started_at,
+            })
+            .collect())
+    }
+
+    async fn wave_status(&mut self) -> Result<Option<WaveStatus>, anyhow::Error> {
+        Ok(self
+            .store
+            .lock()
+            .by_id
+            .values()
+            .find(|wave| wave.name == self.wave)
+            .map(MemoryWave::wave_status))
+    }
+
+    async fn wave_configuration(&mut self) -> Result<Option<WaveConfiguration>, anyhow::Error> {
+        Ok(self
+            .store
+            .wave(&self.wave)
+            .and_then(|wave| wave.configuration))
+    }
+
+    async fn compare(
+        &mut self,
+        other_wave: &str,
+    ) -> Result<LocalBoxStream<'static, Result<ComparedPage, anyhow::Error>>, anyhow::Error> {
+        let find = |name: &str| {
+            self.store
+                .wave(name)
+                .ok_or_else(|| anyhow::anyhow!("wave `{}` does not exist", name))
+        };
+        let (a, b) = (find(&self.wave)?, find(other_wave)?);
+
+        let pages = a
+            .crawled()
+            .chain(b.crawled())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|url| {
+                Ok(ComparedPage {
+                    url: url.clone(),
+                    a: a.snapshot(url),
+                    b: b.snapshot(url),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(stream::iter(pages).boxed_local())
+    }
+
+    async fn export(&mut self) -> Result<Option<WaveExport>, anyhow::Error> {
+        let wave = if let Some(wave) = self.store.wave(&self.wave) {
+            wave
+        } else {
+            return Ok(None);
+        };
+
+        let pages = wave
+            .crawled()
+            .map(|url| {
+                let status = wave.status[url];
+                Ok(ExportedPage {
+                    url: url.clone(),
+                    status_code: status.status_code.unwrap_or_default(),
+                    depth: status.depth,
+                    rank: wave.page_rank(url),
+                    analyses: wave.analyses(url).cloned().unwrap_or_default(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Some(WaveExport {
+            analyses: wave.analyses.into_iter().collect(),
+            pages: stream::iter(pages).boxed_local(),
+        }))
+    }
+}
+
+pub struct MemoryMasterBackend {
+    store: MemoryStore,
+    wave_id: i32,
+}
+
+#[async_trait(?Send)]
+impl MasterBackend for MemoryMasterBackend {
+    fn wave_id(&mut self) -> i32 {
+        self.wave_id
+    }
+
+    async fn ensure_seeded(&mut self, seeds: &[Url]) -> Result<(), anyhow::Error> {
+        self.store.with_wave(self.wave_id, |wave| {
+            for seed in seeds {
+                wave.ensure_status(seed, 0);
+            }
+        })
+    }
+
+    async fn ensure_seeded_from(
+        &mut self,
+        wave: &str,
+        priority: Option<SeedPriority>,
+    ) -> Result<Option<usize>, anyhow::Error> {
+        let mut waves = self.store.lock();
+        let seeds = if let Some(seeds) = waves.seeds_from(wave, priority) {
+            seeds
+        } else {
+            return Ok(None);
+        };
+
+        let this = waves.wave_mut(self.wave_id)?;
+        let mut n_seeded = 0;
+        for (url, depth, priority) in seeds {
+            if this.status.contains_key(&url) {
+                continue;
+            }
+
+            this.ensure_status(&url, depth);
+            if let Some(priority) = priority {
+                this.priorities.insert(url, priority);
+            }
+            n_seeded += 1;
+        }
+
+        Ok(Some(n_seeded))
+    }
+
+    async fn create_analyses(&mut self, analyses: &[(String, Type)]) -> Result<(), anyhow::Error> {
+        self.store.with_wave(self.wave_id, |wave| {
+            for (name, typ) in analyses {
+                wave.analyses
+                    .entry(name.clone())
+                    .or_insert_with(|| typ.clone());
+            }
+        })
+    }
+
+    async fn count_crawled(&mut self) -> Result<usize, anyhow::Error> {
+        self.store
+            .with_wave(self.wave_id, |wave| wave.count(SearchStatus::Closed))
+    }
+
+    async fn reset_queue(&mut self) -> Result<(), anyhow::Error> {
+        self.store.with_wave(self.wave_id, |wave| {
+            for status in wave.status.values_mut() {
+                if let SearchStatus::Taken | SearchStatus::Error = status.search_status {
+                    status.search_status = SearchStatus::Open;
+                }
+            }
+        })
+    }
+
+    async fn exists_taken(&mut self) -> Result<bool, anyhow::Error> {
+        self.store
+            .with_wave(self.wave_id, |wave| wave.count(SearchStatus::Taken) > 0)
+    }
+
+    async fn fetch(
+        &mut self,
+        batch_size: i64,
+        max_depth: i16,
+    ) -> Result<Vec<(Url, u16)>, anyhow::Error> {
+        self.store.with_wave(self.wave_id, |wave| {
+            wave.fetch(batch_size.max(0) as usize, max_depth.max(0) as u16)
+        })
+    }
+
+    async fn ensure_configuration(
+        &mut self,
+        configuration: &WaveConfiguration,
+    ) -> Result<Option<WaveConfiguration>, anyhow::Error> {
+        self.store.with_wave(self.wave_id, |wave| {
+            Some(
+                wave.configuration
+                    .get_or_insert_with(|| configuration.clone())
+                    .clone(),
+            )
+        })
+    }
+}
+
+/// Finds its store by id, so only works within the process that created it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MemoryWorkerBackendFactory {
+    store_id: u64,
+    wave_id: i32,
+}
+
+#[typetag::serde]
+#[async_trait(?Send)]
+impl WorkerBackendFactory for MemoryWorkerBackendFactory {
+    async fn build(&self) -> Result<Box<dyn WorkerBackend>, anyhow::Error> {
+        let store = MemoryStore::by_id(self.store_id).ok_or_else(|| {
+            anyhow::anyhow!(
+                "in-memory store {} not found (is this worker in another process?)",
+                self.store_id
+            )
+        })?;
+
+        Ok(Box::new(MemoryWorkerBackend {
+            store,
+            wave_id: self.wave_id,
+        }))
+    }
+}
+
+pub struct MemoryWorkerBackend {
+    store: MemoryStore,
+    wave_id: i32,
+}
+
+#[async_trait(?Send)]
+impl WorkerBackend for MemoryWorkerBackend {
+    async fn ensure_active(&self, _url: &Url) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    async fn ensure_analyzed(
+        &self,
+        url: &Url,
+        analyses: Vec<(String, Value)>,
+    ) -> Result<(), anyhow::Error> {
+        self.store.with_wave(self.wave_id, |wave| {
+            let results = wave.analysis_results.entry(url.clone()).or_default();
+            for (name, result) in analyses {
+                if wave.analyses.contains_key(&name) {
+                    results.entry(name).or_insert(result);
+                }
+            }
+        })
+    }
+
+    async fn ensure_explored(
+        &self,
+        from_url: &Url,
+        status_code: StatusCode,
+        link_depth: u16,
+        links: Vec<(Reason, Url)>,
+    ) -> Result<(), anyhow::Error> {
+        self.store.with_wave(self.wave_id, |wave| {
+            for (reason, to_url) in links {
+                wave.ensure_status(&to_url, link_depth);
+                wave.linkage.insert((from_url.clone(), to_url, reason));
+            }
+
+            if let Some(status) = wave.status.get_mut(from_url) {
+                status.status_code = Some(status_code.as_u16());
+                status.search_status = SearchStatus::Closed;
+            }
+        })
+    }
+
+    async fn ensure_error(&self, url: &Url) -> Result<(), anyhow::Error> {
+        self.store.with_wave(self.wave_id, |wave| {
+            wave.set_search_status(url, SearchStatus::Error)
+        })
+    }
+}
+
+pub struct MemoryPageRanker {
+    store: MemoryStore,
+    wave_id: i32,
+}
+
+#[async_trait(?Send)]
+impl PageRanker for MemoryPageRanker {
+    type PageId = Url;
+
+    async fn linkage(
+        &mut self,
+    ) -> Result<Box<dyn Iterator<Item = (Self::PageId, Self::PageId)>>, anyhow::Error> {
+        let edges = self.store.with_wave(self.wave_id, |wave| {
+            let is_closed = |url: &Url| {
+                wave.status(url).map(|status| status.search_status) == Some(SearchStatus::Closed)
+            };
+
+            wave.linkage
+                .iter()
+                .filter(|(from, to, reason)| {
+                    *reason == Reason::Ahref && is_closed(from) && is_closed(to)
+                })
+                .map(|(from, to, _)| (from.clone(), to.clone()))
+                .collect::<Vec<_>>()
+        })?;
+
+        Ok(Box::new(edges.into_iter()))
+    }
+
+    async fn push_page_ranks(
+        &mut self,
+        ranked: &[(Self::PageId, f64)],
+    ) -> Result<(), anyhow::Error> {
+        self.store.with_wave(self.wave_id, |wave| {
+            for (url, rank) in ranked {
+                wave.page_ranks.entry(url.clone()).or_insert(*rank);
+            }
+        })
+    }
+}
+
+#[test]
+fn wave_status_test() {
+    conformance::block_on(async {
+        let mut backend = MemoryBackend::new("test");
+        let url = |s: &str| s.parse::<Url>().unwrap();
+
+        assert!(backend.wave_status().await.unwrap().is_none());
+
+        let mut master = backend.build_master().await.unwrap();
+        let worker = backend
+            .build_worker_factory(master.wave_id())
+            .build()
+            .await
+            .unwrap();
+        master
+            .ensure_seeded(&[url("https://a.foo/"), url("https://a.foo/gone")])
+            .await
+            .unwrap();
+        master.fetch(2, 0).await.unwrap();
+        worker
+            .ensure_explored(
+                &url("https://a.foo/"),
+                StatusCode::OK,
+                1,
+                vec![(Reason::Ahref, url("https://a.foo/1"))],
+            )
+            .await
+            .unwrap();
+        worker
+            .ensure_error(&url("https://a.foo/gone"))
+            .await
+            .unwrap();
+
+        let waves = backend.list_waves().await.unwrap();
+        assert_eq!(waves.len(), 1);
+        assert_eq!(waves[0].name, "test");
+
+        let status = backend.wave_status().await.unwrap().unwrap();
+        assert_eq!(status.started_at, waves[0].started_at);
+        assert_eq!(
+            (
+                status.n_open,
+                status.n_taken,
+                status.n_closed,
+                status.n_error
+            ),
+            (1, 0, 1, 1)
+        );
+        assert_eq!(status.status_codes, BTreeMap::from([(200, 1)]));
+        assert_eq!(status.depths, BTreeMap::from([(0, 2), (1, 1)]));
+        assert!(!status.has_page_rank);
+    });
+}
+
+/// Crawls a tiny site, served on a random port, into the given backend with
+/// a single `heading` analysis and page rank enabled. Returns the base URL of
+/// the site and the outcome of the crawl.
+///
+/// The site has `/` linking to `/a` and `/b`, and `/b` linking back to `/`.
+#[cfg(test)]
+pub(super) fn crawl_tiny_site<B: Backend>(backend: B) -> (String, Result<(), anyhow::Error>) {
+    crawl_tiny_site_based_on(backend, None)
+}
+
+/// Crawls a tiny site like [`crawl_tiny_site`], seeding it from a previous
+/// wave, if any.
+#[cfg(test)]
+pub(super) fn crawl_tiny_site_based_on<B: Backend>(
+    backend: B,
+    based_on: Option<&str>,
+) -> (String, Result<(), anyhow::Error>) {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    use crate::cli::Profile;
+    use crate::{CrawlMaster, DirectivesConfiguration, LocalHandlerFactory};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request_line = String::new();
+            BufReader::new(&stream)
+                .read_line(&mut request_line)
+                .unwrap();
+            let body = match request_line.split(' ').nth(1).unwrap_or("") {
+                "/" => "<h1>home</h1><a href=\"/a\">a</a><a href=\"/b\">b</a>",
+                "/a" => "<h1>a</h1>",
+                "/b" => "<h1>b</h1><a href=\"/\">home</a>",
+                "/robots.txt" => "User-agent: *\nAllow: /\n",
+                _ => "",
+            };
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+    });
+
+    let source = format!(
+        "allow \"^http://localhost:{port}/\";\n\
+        seed \"http://localhost:{port}/\";\n\
+        set enable_page_rank = true;\n\
+        select h1 {{ heading: first(text); }}\n"
+    );
+    let (directives, _dir) = crate::directives::load_test_directives(&[("main.lcd", &source)]);
+
+    let profile = Arc::new(Profile {
+        do_not_log_stats: true,
+        ..Profile::default()
+    });
+    let configuration = DirectivesConfiguration::new(directives, profile.clone());
+
+    let outcome = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(
+            CrawlMaster::new(configuration, backend, LocalHandlerFactory)
+                .start(profile, false, based_on, None),
+        );
+
+    (format!("http://localhost:{port}"), outcome)
+}
+
+#[test]
+fn memory_crawl_test() {
+    let backend = MemoryBackend::new("test");
+    let store = backend.store();
+    let (base, outcome) = crawl_tiny_site(backend);
+    outcome.unwrap();
+
+    let wave = store.wave("test").unwrap();
+    let url = |path: &str| format!("{base}{path}").parse::<Url>().unwrap();
+
+    assert_eq!(wave.crawled().count(), 3);
+    assert_eq!(wave.status(&url("/a")).unwrap().depth, 1);
+    assert_eq!(wave.status(&url("/a")).unwrap().status_code, Some(200));
+    assert_eq!(wave.links().count(), 3);
+    assert_eq!(
+        wave.analyses(&url("/b")).unwrap()["heading"],
+        Value::from("b")
+    );
+    assert!(wave.page_rank(&url("/")).unwrap() > wave.page_rank(&url("/a")).unwrap());
+    assert_eq!(
+        wave.configuration().unwrap().configuration["type"],
+        "DirectivesConfiguration"
+    );
+}
+
+#[test]
+fn configuration_change_test() {
+    let store = MemoryStore::default();
+    let (_, outcome) = crawl_tiny_site(MemoryBackend::with_store(store.clone(), "test"));
+    outcome.unwrap();
+    let configuration = store.wave("test").unwrap().configuration;
+
+    // Every tiny site has a port of its own, so this is another configuration:
+    let (_, outcome) = crawl_tiny_site(MemoryBackend::with_store(store.clone(), "test"));
+    let err = outcome.unwrap_err().to_string();
+    assert!(err.contains("configuration changed"), "{err}");
+    assert_eq!(store.wave("test").unwrap().configuration, configuration);
+}
+
+#[test]
+fn compare_test() {
+    let store = MemoryStore::default();
+    let (base, outcome) = crawl_tiny_site(MemoryBackend::with_store(store.clone(), "a"));
+    outcome.unwrap();
+
+    conformance::block_on(async {
+        let mut backend = MemoryBackend::with_store(store.clone(), "a");
+        let err = backend.compare("b").await.err().unwrap().to_string();
+        assert_eq!(err, "wave `b` does not exist");
+
+        // A second wave, where `/a` went missing and `/b` redirects to `/`:
+        let mut master = MemoryBackend::with_store(store.clone(), "b")
+            .build_master()
+            .await
+            .unwrap();
+        let crawled = store.wave("a").unwrap().status;
+        store
+            .with_wave(master.wave_id(), |wave| {
+                for (url, status) in crawled {
+                    if !url.path().ends_with("/a") {
+                        wave.status.insert(url, status);
+                    }
+                }
+                let b = format!("{base}/b").parse::<Url>().unwrap();
+                let home = format!("{base}/").parse::<Url>().unwrap();
+                wave.status.get_mut(&b).unwrap().status_code = Some(301);
+                wave.linkage.insert((b, home, Reason::Redirect));
+            })
+            .unwrap();
+
+        let pages = backend
+            .compare("b")
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(pages.len(), 3);
+
+        let page = |path: &str| {
+            pages
+                .iter()
+                .find(|page| page.url.as_str() == format!("{base}{path}"))
+                .unwrap()
+        };
+        assert_eq!(page("/").a.as_ref().unwrap().analyses["heading"], "home");
+        assert!(page("/").b.as_ref().unwrap().analyses.is_empty());
+        assert!(page("/a").b.is_none());
+        assert_eq!(page("/b").b.as_ref().unwrap().status_code, 301);
+        assert_eq!(
+            page("/b")
+                .b
+                .as_ref()
+                .unwrap()
+                .redirect
+                .as_ref()
+                .unwrap()
+                .path(),
+            "/"
+        );
+        assert_eq!(page("/b").a.as_ref().unwrap().redirect, None);
+    });
+}
+
+#[test]
+fn seeded_from_test() {
+    let store = MemoryStore::default();
+    let (base, outcome) = crawl_tiny_site(MemoryBackend::with_store(store.clone(), "a"));
+    outcome.unwrap();
+    let url = |path: &str| format!("{base}{path}").parse::<Url>().unwrap();
+
+    conformance::block_on(async {
+        let mut master = MemoryBackend::with_store(store.clone(), "b")
+            .build_master()
+            .await
+            .unwrap();
+        assert_eq!(master.ensure_seeded_from("c", None).await.unwrap(), None);
+        assert_eq!(
+            master
+                .ensure_seeded_from("a", Some(SeedPriority::PageRank))
+                .await
+                .unwrap(),
+            Some(3)
+        );
+        // Pages already in the wave are left alone:
+        assert_eq!(master.ensure_seeded_from("a", None).await.unwrap(), Some(0));
+
+        // `/` has the highest rank, and `/a` and `/b` keep their depths:
+        let batch = master.fetch(3, 5).await.unwrap();
+        assert_eq!(batch[0], (url("/"), 0));
+        assert!(batch.contains(&(url("/a"), 1)));
+        assert!(batch.contains(&(url("/b"), 1)));
+    });
+
+    let rank = store.wave("a").unwrap().page_rank(&url("/"));
+    assert_eq!(
+        store.wave("b").unwrap().priorities.get(&url("/")),
+        rank.as_ref()
+    );
+}
+
+#[test]
+fn based_on_test() {
+    let store = MemoryStore::default();
+
+    let (_, outcome) =
+        crawl_tiny_site_based_on(MemoryBackend::with_store(store.clone(), "b"), Some("a"));
+    let err = outcome.unwrap_err().to_string();
+    assert_eq!(err, "wave `a` does not exist");
+    assert!(store.wave("b").is_none());
+
+    let (_, outcome) = crawl_tiny_site(MemoryBackend::with_store(store.clone(), "a"));
+    outcome.unwrap();
+    let (_, outcome) =
+        crawl_tiny_site_based_on(MemoryBackend::with_store(store.clone(), "b"), Some("a"));
+    outcome.unwrap();
+    assert!(store.wave("b").is_some());
+}
@@ -1,6 +1,11 @@
+pub mod conformance;
 mod dummy;
+mod memory;
+mod sink;
 
 pub use async_trait::async_trait;
+pub use chrono::NaiveDateTime;
+pub use futures::stream::{self, LocalBoxStream, StreamExt, TryStreamExt};
 pub use hyper::StatusCode;
 pub use serde_json::Value;
 pub use structopt::StructOpt;
@@ -12,12 +17,31 @@ pub use crate::crawler::Reason;
 pub use crate::Type;
 
 pub use self::dummy::DummyBackend;
+pub use self::memory::{
+    MemoryBackend, MemoryConfig, MemoryStore, MemoryWave, PageStatus, SearchStatus,
+};
+pub use self::sink::{SinkBackend, SinkConfig};
 
-use serde_derive::Serialize;
-use std::fmt::Debug;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug};
+use std::str::FromStr;
 
 use crate::page_rank::power_iteration;
 
+/// The error of the optional methods of a backend that it does not
+/// implement.
+#[derive(Debug)]
+pub struct Unsupported(pub &'static str);
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
 #[derive(Debug, Serialize)]
 pub struct WaveRemoveReport {
     was_removed: bool,
@@ -44,6 +68,120 @@ impl WaveRemoveReport {
     }
 }
 
+/// A crawled page of a wave, with everything that is known about it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExportedPage {
+    pub url: Url,
+    pub status_code: u16,
+    pub depth: u16,
+    /// Only set if page rank was run on the wave.
+    pub rank: Option<f64>,
+    /// Analyses that yielded no result for this page may be missing.
+    pub analyses: BTreeMap<String, Value>,
+}
+
+/// All crawled pages of a wave, streamed from the backend.
+pub struct WaveExport {
+    /// The analyses declared for the wave, with their result types.
+    pub analyses: Vec<(String, Type)>,
+    pub pages: LocalBoxStream<'static, Result<ExportedPage, anyhow::Error>>,
+}
+
+/// What a page looked like when crawled in a wave.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageSnapshot {
+    pub status_code: u16,
+    /// Where the page redirected to, if it did.
+    pub redirect: Option<Url>,
+    pub analyses: BTreeMap<String, Value>,
+}
+
+/// A page crawled in at least one of two waves being compared.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComparedPage {
+    pub url: Url,
+    /// `None` if the page was not crawled in the first wave.
+    pub a: Option<PageSnapshot>,
+    /// `None` if the page was not crawled in the second wave.
+    pub b: Option<PageSnapshot>,
+}
+
+/// A wave, as listed by `lopez ls`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WaveSummary {
+    pub name: String,
+    pub started_at: NaiveDateTime,
+}
+
+/// How far a wave has gone, as shown by `lopez status`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WaveStatus {
+    pub name: String,
+    pub started_at: NaiveDateTime,
+    pub n_open: usize,
+    pub n_taken: usize,
+    pub n_closed: usize,
+    pub n_error: usize,
+    /// The number of closed pages for each status code.
+    pub status_codes: BTreeMap<u16, usize>,
+    /// The number of pages found at each depth, crawled or not.
+    pub depths: BTreeMap<u16, usize>,
+    pub has_page_rank: bool,
+}
+
+/// The configuration a wave was created with.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WaveConfiguration {
+    /// Changes with what the wave crawls, but not with the profile.
+    pub content_hash: String,
+    /// The serialized configuration, profile included.
+    pub configuration: Value,
+}
+
+/// Which pages of a previous wave are crawled first when seeding a new wave
+/// from it. Pages of the new wave are otherwise crawled by depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SeedPriority {
+    /// Pages with the highest page rank in the previous wave come first.
+    PageRank,
+    /// Pages that were seen in the most different versions (status code and
+    /// analyses) across all waves come first.
+    ChangeFrequency,
+}
+
+impl SeedPriority {
+    /// The name of the priority, as passed to queries.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SeedPriority::PageRank => "page_rank",
+            SeedPriority::ChangeFrequency => "change_frequency",
+        }
+    }
+}
+
+impl FromStr for SeedPriority {
+    type Err = String;
+    fn from_str(s: &str) -> Result<SeedPriority, String> {
+        match s {
+            "page-rank" => Ok(SeedPriority::PageRank),
+            "change-frequency" => Ok(SeedPriority::ChangeFrequency),
+            _ => Err(format!(
+                "unknown seed priority `{s}` (expected `page-rank` or `change-frequency`)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SeedPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeedPriority::PageRank => write!(f, "page-rank"),
+            SeedPriority::ChangeFrequency => write!(f, "change-frequency"),
+        }
+    }
+}
+
 #[async_trait(?Send)]
 pub trait Backend: Sized {
     type Config: StructOpt;
@@ -54,10 +192,52 @@ pub trait Backend: Sized {
     fn build_worker_factory(&mut self, wave_id: i32) -> Box<dyn WorkerBackendFactory>;
     async fn build_ranker(&mut self, wave_id: i32) -> Result<Self::Ranker, anyhow::Error>;
 
+    /// Whether crawl results are written to the standard output, in which
+    /// case nothing else should be printed there.
+    fn writes_to_stdout(&self) -> bool {
+        false
+    }
+
     /// This may become a mandatory method in future releases.
     async fn remove(&mut self) -> Result<WaveRemoveReport, anyhow::Error> {
         Ok(WaveRemoveReport::not_removed())
     }
+
+    /// Lists all waves in the backend, whatever the wave of this backend.
+    /// Backends that cannot list waves fail with [`Unsupported`].
+    async fn list_waves(&mut self) -> Result<Vec<WaveSummary>, anyhow::Error> {
+        Err(Unsupported("this backend does not support listing waves").into())
+    }
+
+    /// Counts the pages of the wave, or returns `None` if the wave does not
+    /// exist. Backends that cannot count them fail with [`Unsupported`].
+    async fn wave_status(&mut self) -> Result<Option<WaveStatus>, anyhow::Error> {
+        Err(Unsupported("this backend does not support showing the status of waves").into())
+    }
+
+    /// The configuration the wave was created with, or `None` if the wave
+    /// does not exist or was created before configurations were stored.
+    /// Backends that do not store configurations fail with [`Unsupported`].
+    async fn wave_configuration(&mut self) -> Result<Option<WaveConfiguration>, anyhow::Error> {
+        Err(Unsupported("this backend does not store the configuration of waves").into())
+    }
+
+    /// Streams all pages crawled in this wave or in `other_wave`, side by
+    /// side. Fails if either wave does not exist. Backends that cannot compare
+    /// waves fail with [`Unsupported`].
+    async fn compare(
+        &mut self,
+        _other_wave: &str,
+    ) -> Result<LocalBoxStream<'static, Result<ComparedPage, anyhow::Error>>, anyhow::Error> {
+        Err(Unsupported("this backend does not support comparing waves").into())
+    }
+
+    /// Streams the crawled pages of the wave, or returns `None` if the wave
+    /// does not exist. Backends that cannot export waves fail with
+    /// [`Unsupported`].
+    async fn export(&mut self) -> Result<Option<WaveExport>, anyhow::Error> {
+        Err(Unsupported("this backend does not support exporting waves").into())
+    }
 }
 
 #[async_trait(?Send)]
@@ -73,6 +253,29 @@ pub trait MasterBackend {
         batch_size: i64,
         max_depth: i16,
     ) -> Result<Vec<(Url, u16)>, anyhow::Error>;
+
+    /// Stores the configuration of the wave, unless one is stored already,
+    /// and returns the stored one. Backends that do not store configurations
+    /// return `None`, which is the default.
+    async fn ensure_configuration(
+        &mut self,
+        _configuration: &WaveConfiguration,
+    ) -> Result<Option<WaveConfiguration>, anyhow::Error> {
+        Ok(None)
+    }
+
+    /// Seeds the wave with all pages crawled in `wave`, at the depths they
+    /// had there, and returns how many were not in the wave yet, or `None` if
+    /// `wave` does not exist. With a priority, these pages are fetched before
+    /// the others of the same site, highest priority first. Backends that
+    /// cannot read other waves fail with [`Unsupported`].
+    async fn ensure_seeded_from(
+        &mut self,
+        _wave: &str,
+        _priority: Option<SeedPriority>,
+    ) -> Result<Option<usize>, anyhow::Error> {
+        Err(Unsupported("this backend does not support seeding a wave from another").into())
+    }
 }
 
 #[typetag::serde(tag = "type")]
@@ -0,0 +1,371 @@
+//! A backend that streams crawl results out as newline-delimited JSON instead
+//! of storing them.
+//!
+//! The queue lives in a [`MemoryStore`], which only keeps the status of each
+//! page and the links between them (for page rank). Everything else is written
+//! to the sink as soon as it is known, one record per line, tagged by a
+//! `record` field:
+//! ```text
+//! {"record":"analyses","wave":"w","url":"https://a.foo/","analyses":{"title":"A"}}
+//! {"record":"page","wave":"w","url":"https://a.foo/","depth":0,"status_code":200,"links":[{"reason":"ahref","url":"https://a.foo/b"}]}
+//! {"record":"error","wave":"w","url":"https://a.foo/c"}
+//! {"record":"page_rank","wave":"w","url":"https://a.foo/","rank":0.5}
+//! ```
+
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::export::create_output;
+
+use super::memory::MemoryPageRanker;
+use super::*;
+
+type Writer = BufWriter<Box<dyn Write + Send>>;
+
+lazy_static! {
+    /// All open sinks in this process, so that worker backend factories can
+    /// be (de)serialized as a mere id.
+    static ref SINKS: Mutex<HashMap<u64, Weak<Mutex<Writer>>>> = Mutex::default();
+}
+
+static NEXT_SINK_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize)]
+struct Link<'a> {
+    reason: String,
+    url: &'a Url,
+}
+
+/// A line in the sink.
+#[derive(Serialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum Record<'a> {
+    Analyses {
+        wave: &'a str,
+        url: &'a Url,
+        analyses: BTreeMap<String, Value>,
+    },
+    Page {
+        wave: &'a str,
+        url: &'a Url,
+        depth: u16,
+        status_code: u16,
+        links: Vec<Link<'a>>,
+    },
+    Error {
+        wave: &'a str,
+        url: &'a Url,
+    },
+    PageRank {
+        wave: &'a str,
+        url: &'a Url,
+        rank: f64,
+    },
+}
+
+/// Where records go, shared by all workers in the process.
+#[derive(Clone)]
+struct Sink {
+    id: u64,
+    writer: Arc<Mutex<Writer>>,
+}
+
+impl Sink {
+    fn create(path: &Path) -> Result<Sink, anyhow::Error> {
+        let id = NEXT_SINK_ID.fetch_add(1, Ordering::Relaxed);
+        let writer = Arc::new(Mutex::new(BufWriter::new(create_output(path)?)));
+
+        let mut sinks = SINKS.lock().expect("poisoned");
+        sinks.retain(|_, sink| sink.strong_count() > 0);
+        sinks.insert(id, Arc::downgrade(&writer));
+
+        Ok(Sink { id, writer })
+    }
+
+    fn by_id(id: u64) -> Option<Sink> {
+        let writer = SINKS.lock().expect("poisoned").get(&id)?.upgrade()?;
+        Some(Sink { id, writer })
+    }
+
+    /// Writes a whole line and flushes it, so that readers downstream see
+    /// records as they come (and nothing is lost on exit).
+    fn write(&self, record: &Record) -> Result<(), anyhow::Error> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut writer = self.writer.lock().expect("poisoned");
+        writer.write_all(&line)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, StructOpt)]
+pub struct SinkConfig {
+    /// The file to which records are written, one JSON object per line. Use
+    /// `-` for the standard output.
+    #[structopt(long, env = "SINK_PATH", default_value = "-")]
+    sink_path: PathBuf,
+}
+
+/// A backend that writes results to a file or to the standard output. Like
+/// [`MemoryBackend`], workers must run in the same process, so this cannot be
+/// used with `lopez serve`; and waves cannot be resumed.
+pub struct SinkBackend {
+    memory: MemoryBackend,
+    sink_path: PathBuf,
+    /// Only created when the crawl starts, so that other commands do not
+    /// truncate the file.
+    sink: Option<Sink>,
+    wave: String,
+}
+
+#[async_trait(?Send)]
+impl Backend for SinkBackend {
+    type Config = SinkConfig;
+    type Ranker = SinkPageRanker;
+
+    async fn init(config: Self::Config, wave: &str) -> Result<Self, anyhow::Error> {
+        Ok(SinkBackend {
+            memory: MemoryBackend::new(wave),
+            sink_path: config.sink_path,
+            sink: None,
+            wave: wave.to_owned(),
+        })
+    }
+
+    fn writes_to_stdout(&self) -> bool {
+        self.sink_path == Path::new("-")
+    }
+
+    async fn build_master(&mut self) -> Result<Box<dyn MasterBackend>, anyhow::Error> {
+        if self.sink.is_none() {
+            self.sink = Some(Sink::create(&self.sink_path)?);
+        }
+
+        self.memory.build_master().await
+    }
+
+    fn build_worker_factory(&mut self, wave_id: i32) -> Box<dyn WorkerBackendFactory> {
+        Box::new(SinkWorkerBackendFactory {
+            memory: self.memory.build_worker_factory(wave_id),
+            sink_id: self.sink.as_ref().map(|sink| sink.id),
+            wave: self.wave.clone(),
+        })
+    }
+
+    async fn build_ranker(&mut self, wave_id: i32) -> Result<Self::Ranker, anyhow::Error> {
+        let sink = if let Some(sink) = &self.sink {
+            sink.clone()
+        } else {
+            anyhow::bail!("nothing was crawled in this process to be ranked")
+        };
+
+        Ok(SinkPageRanker {
+            memory: self.memory.build_ranker(wave_id).await?,
+            sink,
+            wave: self.wave.clone(),
+        })
+    }
+}
+
+/// Finds its sink by id, so only works within the process that created it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SinkWorkerBackendFactory {
+    memory: Box<dyn WorkerBackendFactory>,
+    /// Only `None` if the factory was built before the master.
+    sink_id: Option<u64>,
+    wave: String,
+}
+
+#[typetag::serde]
+#[async_trait(?Send)]
+impl WorkerBackendFactory for SinkWorkerBackendFactory {
+    async fn build(&self) -> Result<Box<dyn WorkerBackend>, anyhow::Error> {
+        let sink_id = self
+            .sink_id
+            .ok_or_else(|| anyhow::anyhow!("no sink was created before building workers"))?;
+        let sink = Sink::by_id(sink_id).ok_or_else(|| {
+            anyhow::anyhow!(
+                "sink {} not found (is this worker in another process?)",
+                sink_id
+            )
+        })?;
+
+        Ok(Box::new(SinkWorkerBackend {
+            memory: self.memory.build().await?,
+            sink,
+            wave: self.wave.clone(),
+        }))
+    }
+}
+
+pub struct SinkWorkerBackend {
+    memory: Box<dyn WorkerBackend>,
+    sink: Sink,
+    wave: String,
+}
+
+#[async_trait(?Send)]
+impl WorkerBackend for SinkWorkerBackend {
+    async fn ensure_active(&self, url: &Url) -> Result<(), anyhow::Error> {
+        self.memory.ensure_active(url).await
+    }
+
+    async fn ensure_analyzed(
+        &self,
+        url: &Url,
+        analyses: Vec<(String, Value)>,
+    ) -> Result<(), anyhow::Error> {
+        // Analyses are not kept: this is what keeps the store small.
+        self.sink.write(&Record::Analyses {
+            wave: &self.wave,
+            url,
+            analyses: analyses.into_iter().collect(),
+        })
+    }
+
+    async fn ensure_explored(
+        &self,
+        from_url: &Url,
+        status_code: StatusCode,
+        link_depth: u16,
+        links: Vec<(Reason, Url)>,
+    ) -> Result<(), anyhow::Error> {
+        let record = Record::Page {
+            wave: &self.wave,
+            url: from_url,
+            // Links are always one level deeper than the page.
+            depth: link_depth.saturating_sub(1),
+            status_code: status_code.as_u16(),
+            links: links
+                .iter()
+                .map(|(reason, url)| Link {
+                    reason: reason.to_string(),
+                    url,
+                })
+                .collect(),
+        };
+        self.sink.write(&record)?;
+
+        self.memory
+            .ensure_explored(from_url, status_code, link_depth, links)
+            .await
+    }
+
+    async fn ensure_error(&self, url: &Url) -> Result<(), anyhow::Error> {
+        self.sink.write(&Record::Error {
+            wave: &self.wave,
+            url,
+        })?;
+
+        self.memory.ensure_error(url).await
+    }
+}
+
+pub struct SinkPageRanker {
+    memory: MemoryPageRanker,
+    sink: Sink,
+    wave: String,
+}
+
+#[async_trait(?Send)]
+impl PageRanker for SinkPageRanker {
+    type PageId = Url;
+
+    async fn linkage(
+        &mut self,
+    ) -> Result<Box<dyn Iterator<Item = (Self::PageId, Self::PageId)>>, anyhow::Error> {
+        self.memory.linkage().await
+    }
+
+    async fn push_page_ranks(
+        &mut self,
+        ranked: &[(Self::PageId, f64)],
+    ) -> Result<(), anyhow::Error> {
+        for (url, rank) in ranked {
+            self.sink.write(&Record::PageRank {
+                wave: &self.wave,
+                url,
+                rank: *rank,
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn sink_crawl_test() {
+    use super::memory::crawl_tiny_site;
+
+    let path = std::env::temp_dir().join(format!("lopez-sink-{}.jsonl", std::process::id()));
+    let backend = conformance::block_on(SinkBackend::init(
+        SinkConfig {
+            sink_path: path.clone(),
+        },
+        "test",
+    ))
+    .unwrap();
+    assert!(!backend.writes_to_stdout());
+    let (base, outcome) = crawl_tiny_site(backend);
+    outcome.unwrap();
+
+    let records = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    std::fs::remove_file(&path).unwrap();
+
+    let of_kind = |kind: &str| {
+        records
+            .iter()
+            .filter(|record| record["record"] == kind)
+            .collect::<Vec<_>>()
+    };
+    let find = |kind: &str, path: &str| {
+        of_kind(kind)
+            .into_iter()
+            .find(|record| record["url"] == format!("{base}{path}"))
+            .unwrap()
+    };
+
+    assert_eq!(of_kind("page").len(), 3);
+    assert_eq!(of_kind("analyses").len(), 3);
+    assert_eq!(of_kind("page_rank").len(), 3);
+    assert!(of_kind("error").is_empty());
+
+    let home = find("page", "/");
+    assert_eq!(home["wave"], "test");
+    assert_eq!(home["depth"], 0);
+    assert_eq!(home["status_code"], 200);
+    assert_eq!(home["links"][0]["reason"], "ahref");
+    assert_eq!(home["links"].as_array().unwrap().len(), 2);
+    assert_eq!(find("page", "/a")["depth"], 1);
+    assert_eq!(find("analyses", "/b")["analyses"]["heading"], "b");
+    assert!(
+        find("page_rank", "/")["rank"].as_f64().unwrap()
+            > find("page_rank", "/a")["rank"].as_f64().unwrap()
+    );
+}
+
+#[test]
+fn sink_worker_before_master_test() {
+    let mut backend = conformance::block_on(SinkBackend::init(
+        SinkConfig {
+            sink_path: "-".into(),
+        },
+        "test",
+    ))
+    .unwrap();
+    let factory = backend.build_worker_factory(0);
+
+    assert!(conformance::block_on(factory.build()).is_err());
+}
@@ -32,6 +32,20 @@ macro_rules! cli_impl {
                 /// corresponding crawl is resumed.
                 #[structopt(env)]
                 wave_name: String,
+                /// Resumes a wave even if its configuration changed since the wave was
+                /// created, instead of refusing to. Results of both configurations will
+                /// be mixed in the wave.
+                #[structopt(long)]
+                allow_config_change: bool,
+                /// Seeds the wave with all pages crawled in a previous wave, at the
+                /// depths they had there, besides the seeds of the configuration. This
+                /// reaches deep pages that would be hard to find again.
+                #[structopt(long)]
+                based_on: Option<String>,
+                /// Crawls the pages of the `--based-on` wave first, either by
+                /// `page-rank` or by `change-frequency`.
+                #[structopt(long, requires = "based-on")]
+                prioritize_by: Option<$crate::backend::SeedPriority>,
                 #[structopt(flatten)]
                 profile: Profile,
                 #[structopt(flatten)]
@@ -60,11 +74,39 @@ macro_rules! cli_impl {
                 #[structopt(env)]
                 source: PathBuf,
                 /// The URL to be used for testing.
-                #[structopt(env)]
-                test_url: String,
+                #[structopt(env, required_unless = "from-file")]
+                test_url: Option<String>,
+                /// Tests the page saved in this file instead of downloading it
+                /// (use `-` to read from stdin). No requests are made and the
+                /// page is assumed to have been served with `200 OK`.
+                #[structopt(long, requires = "as-url", conflicts_with = "test-url")]
+                from_file: Option<PathBuf>,
+                /// The URL from which the page in `--from-file` is supposed to
+                /// have been downloaded.
+                #[structopt(long, requires = "from-file")]
+                as_url: Option<String>,
                 #[structopt(flatten)]
                 profile: Profile,
             },
+            /// Runs the tests declared in a crawl configuration (and in all its
+            /// imports) against their fixtures.
+            TestDirectives {
+                /// The name of the `.lcd` file to be used for the crawl configuration
+                #[structopt(env)]
+                source: PathBuf,
+            },
+            /// Formats a crawl configuration file in place, keeping its comments.
+            Fmt {
+                /// The name of the `.lcd` file to be formatted
+                #[structopt(env)]
+                source: PathBuf,
+                /// Only checks whether the file is formatted, without changing it.
+                #[structopt(long)]
+                check: bool,
+            },
+            /// Runs a language server for crawl configurations over stdio, for
+            /// editor integration.
+            Lsp,
             /// Runs the page rank algorithm on the supplied wave.
             PageRank {
                 /// The name of this crawl wave. You can still use this command even if you
@@ -85,6 +127,53 @@ macro_rules! cli_impl {
                 #[structopt(flatten)]
                 config: <$backend_ty as Backend>::Config,
             },
+            /// Lists all waves in the backend.
+            Ls {
+                #[structopt(flatten)]
+                config: <$backend_ty as Backend>::Config,
+            },
+            /// Shows how far a wave has gone: pages by search status, status
+            /// code and depth.
+            Status {
+                /// The name of the wave to be inspected.
+                #[structopt(env)]
+                wave_name: String,
+                #[structopt(flatten)]
+                config: <$backend_ty as Backend>::Config,
+            },
+            /// Shows the configuration with which a wave was created.
+            ShowConfig {
+                /// The name of the wave.
+                #[structopt(env)]
+                wave_name: String,
+                #[structopt(flatten)]
+                config: <$backend_ty as Backend>::Config,
+            },
+            /// Shows what changed from one wave to another: new and removed
+            /// pages, and changes in status codes, redirects and analyses.
+            Diff {
+                /// The name of the older wave.
+                wave_a: String,
+                /// The name of the newer wave.
+                wave_b: String,
+                #[structopt(flatten)]
+                config: <$backend_ty as Backend>::Config,
+            },
+            /// Exports the crawled pages of a wave, one row per page, with
+            /// their analyses.
+            Export {
+                /// The name of the wave to be exported.
+                #[structopt(env)]
+                wave_name: String,
+                /// The output format: `jsonl`, `csv` or `parquet`.
+                #[structopt(long, default_value = "jsonl")]
+                format: $crate::ExportFormat,
+                /// The file to write to, or `-` for the standard output.
+                #[structopt(long, default_value = "-")]
+                out: PathBuf,
+                #[structopt(flatten)]
+                config: <$backend_ty as Backend>::Config,
+            },
         }
     };
 }
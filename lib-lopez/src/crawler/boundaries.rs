@@ -1,3 +1,6 @@
+use serde_derive::Serialize;
+use std::fmt;
+use std::path::PathBuf;
 use url::Url;
 
 use super::Reason;
@@ -37,6 +40,67 @@ fn checked_join(base_url: &Url, raw: &str) -> Result<Url, anyhow::Error> {
     }
 }
 
+/// Where a boundary rule was declared.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleOrigin {
+    /// The rule, as it would be written in the directives.
+    pub rule: String,
+    pub file: PathBuf,
+    pub line: usize,
+}
+
+impl fmt::Display for RuleOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` at {}:{}",
+            self.rule,
+            self.file.display(),
+            self.line
+        )
+    }
+}
+
+/// A query parameter that was stripped from a URL.
+#[derive(Debug, Serialize)]
+pub struct StrippedParam {
+    pub name: String,
+    /// The rule that explicitly ignores this parameter. If `None`, the
+    /// parameter was stripped just because no rule says to use it.
+    pub ignored_by: Option<RuleOrigin>,
+}
+
+/// Explains why a URL is (or is not) allowed by some boundaries.
+#[derive(Debug, Default, Serialize)]
+pub struct BoundariesExplanation {
+    pub allowed_by: Vec<RuleOrigin>,
+    pub disallowed_by: Vec<RuleOrigin>,
+    pub frontier_by: Vec<RuleOrigin>,
+    pub stripped_params: Vec<StrippedParam>,
+}
+
+impl BoundariesExplanation {
+    /// Lists the reasons for which the explained URL cannot be used as a
+    /// seed. Returns an empty list if it can.
+    pub fn seed_issues(&self) -> Vec<String> {
+        let mut issues = vec![];
+
+        if self.allowed_by.is_empty() {
+            issues.push("matches no `allow` rule".to_owned());
+        }
+
+        for origin in &self.disallowed_by {
+            issues.push(format!("disallowed by {origin}"));
+        }
+
+        for origin in &self.frontier_by {
+            issues.push(format!("on the frontier by {origin}"));
+        }
+
+        issues
+    }
+}
+
 pub trait Boundaries: 'static + Send {
     /// Returns `true` if the page can be downloaded.
     fn is_allowed(&self, url: &Url) -> bool;
@@ -46,6 +110,12 @@ pub trait Boundaries: 'static + Send {
     /// implementation-specific policy. This is meant to create a "canonical"
     /// representation of a URL.
     fn clean_query_params(&self, url: Url) -> Url;
+    /// Explains which rules decide the fate of a URL (before its query
+    /// parameters are cleaned). Implementations that cannot tell return an
+    /// empty explanation.
+    fn explain(&self, _url: &Url) -> BoundariesExplanation {
+        BoundariesExplanation::default()
+    }
 
     fn clean_links(&self, page_url: &Url, links: &[(Reason, String)]) -> Vec<(Reason, Url)> {
         if self.is_frontier(page_url) {
@@ -3,7 +3,9 @@ use std::sync::Arc;
 use tokio::time::{self, Duration};
 use url::Url;
 
-use crate::backend::{Backend, PageRanker, WorkerBackendFactory};
+use crate::backend::{
+    Backend, PageRanker, SeedPriority, Unsupported, WaveConfiguration, WorkerBackendFactory,
+};
 use crate::cli::Profile;
 
 // use super::diagnostics::log_stats;
@@ -34,18 +36,62 @@ where
     }
 
     /// TODO ned refactoring
-    pub async fn start(mut self, profile: Arc<Profile>) -> Result<(), anyhow::Error> {
+    pub async fn start(
+        mut self,
+        profile: Arc<Profile>,
+        allow_config_change: bool,
+        based_on: Option<&str>,
+        prioritize_by: Option<SeedPriority>,
+    ) -> Result<(), anyhow::Error> {
         // Set panics to be logged:
         crate::panic::log_panics();
 
         let parameters = self.configuration.parameters();
 
+        // Do not leave a wave behind if there is nothing to base it on:
+        if let Some(based_on) = based_on {
+            match self.backend.list_waves().await {
+                Ok(waves) if !waves.iter().any(|wave| wave.name == based_on) => {
+                    return Err(anyhow::anyhow!("wave `{}` does not exist", based_on));
+                }
+                // Backends that cannot list waves are left to `ensure_seeded_from`.
+                Err(err) if !err.is::<Unsupported>() => return Err(err),
+                _ => {}
+            }
+        }
+
         // Load data model:
         let mut master_model = self.backend.build_master().await?;
         let wave_id = master_model.wave_id();
         let worker_backend_factory: Arc<dyn WorkerBackendFactory> =
             self.backend.build_worker_factory(wave_id).into();
 
+        // Make sure that a resumed wave crawls the same thing as before:
+        let configuration = WaveConfiguration {
+            content_hash: self.configuration.content_hash(),
+            configuration: serde_json::to_value(&self.configuration)?,
+        };
+        if let Some(stored) = master_model.ensure_configuration(&configuration).await? {
+            if stored.content_hash != configuration.content_hash {
+                if allow_config_change {
+                    log::warn!(
+                        "configuration changed since the wave was created ({} != {}). \
+                        Results will be mixed",
+                        configuration.content_hash,
+                        stored.content_hash
+                    );
+                } else {
+                    return Err(anyhow::anyhow!(
+                        "configuration changed since the wave was created ({} != {}); \
+                        see `show-config` for the original one or pass \
+                        `--allow-config-change` to resume anyway",
+                        configuration.content_hash,
+                        stored.content_hash
+                    ));
+                }
+            }
+        }
+
         // Calculation of how many pages to crawl, after all:
         let consumed = master_model.count_crawled().await?;
         let crawl_quota = parameters.quota;
@@ -94,6 +140,17 @@ where
             .ensure_seeded(&self.configuration.seeds())
             .await?;
 
+        // And from the previous wave, if any:
+        if let Some(based_on) = based_on {
+            match master_model
+                .ensure_seeded_from(based_on, prioritize_by)
+                .await?
+            {
+                Some(n_seeded) => log::info!("seeded {} pages from wave `{}`", n_seeded, based_on),
+                None => return Err(anyhow::anyhow!("wave `{}` does not exist", based_on)),
+            }
+        }
+
         // Ensure that all analysis names exist:
         master_model
             .create_analyses(&self.configuration.analyzes())
@@ -238,4 +295,27 @@ where
             .test_url(url)
             .await
     }
+
+    /// Tests a local page content, as if it were downloaded from a given URL,
+    /// and says what is happening.
+    pub async fn test_content(
+        mut self,
+        profile: Arc<Profile>,
+        url: Url,
+        content: Vec<u8>,
+    ) -> TestRunReport {
+        // Load dummy data model:
+        let mut master_model = self
+            .backend
+            .build_master()
+            .await
+            .expect("failed to build master backend");
+        let worker_backend_factory: Arc<_> = self
+            .backend
+            .build_worker_factory(master_model.wave_id())
+            .into();
+
+        CrawlWorker::new(self.configuration.as_ref(), worker_backend_factory, profile)
+            .test_content(url, content)
+    }
 }
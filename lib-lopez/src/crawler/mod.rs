@@ -10,13 +10,15 @@ mod robots;
 mod worker;
 // mod diagnostics;
 
-pub use self::boundaries::{Boundaries, DummyBoundaries};
+pub use self::boundaries::{
+    Boundaries, BoundariesExplanation, DummyBoundaries, RuleOrigin, StrippedParam,
+};
 // pub use self::counter::Counter;
 pub use self::downloader::{
     Downloaded, Downloader, DummyDownloader, SimpleDownloader, WebDriverDownloader,
 };
 pub use self::master::CrawlMaster;
-pub use self::parser::{DummyParser, Parsed, Parser};
+pub use self::parser::{DummyParser, PageInfo, Parsed, Parser};
 pub use self::reason::Reason;
 pub use self::worker::LocalHandlerFactory;
 pub(crate) use self::worker::{
@@ -47,6 +49,12 @@ pub trait Configuration: Debug + Send + Sync {
     fn seeds(&self) -> Vec<Url>;
     fn analyzes(&self) -> Vec<(String, Type)>;
     fn parameters(&self) -> Parameters;
+    /// Identifies what the configuration crawls, so that a wave is not
+    /// resumed with something else. Settings that only affect how the crawl
+    /// runs (e.g., the profile) should not change it.
+    fn content_hash(&self) -> String;
+    /// The configuration the way its author wrote it, for `show-config`.
+    fn source(&self) -> String;
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -77,4 +85,12 @@ impl Configuration for DummyConfiguration {
     fn parameters(&self) -> Parameters {
         panic!("cannot use DummyConfiguration")
     }
+
+    fn content_hash(&self) -> String {
+        panic!("cannot use DummyConfiguration")
+    }
+
+    fn source(&self) -> String {
+        panic!("cannot use DummyConfiguration")
+    }
 }
@@ -1,7 +1,16 @@
+use http::StatusCode;
 use url::Url;
 
 use super::Reason;
 
+/// What is known of a page before its content gets parsed.
+#[derive(Debug, Clone, Copy)]
+pub struct PageInfo<'a> {
+    pub url: &'a Url,
+    pub depth: u16,
+    pub status_code: StatusCode,
+}
+
 #[allow(unused)]
 pub enum Parsed {
     /// This parser does not parse this content.
@@ -14,13 +23,13 @@ pub enum Parsed {
 }
 
 pub trait Parser: 'static + Send {
-    fn parse(&self, page_url: &Url, content: &[u8]) -> Parsed;
+    fn parse(&self, page: PageInfo, content: &[u8]) -> Parsed;
 }
 
 pub struct DummyParser;
 
 impl Parser for DummyParser {
-    fn parse(&self, _page_url: &Url, _content: &[u8]) -> Parsed {
+    fn parse(&self, _page: PageInfo, _content: &[u8]) -> Parsed {
         panic!("cannot use DummyParser")
     }
 }
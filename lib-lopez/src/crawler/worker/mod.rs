@@ -14,9 +14,9 @@ use crate::backend::{WorkerBackend, WorkerBackendFactory};
 use crate::cancel::{spawn_onto_thread, Canceler};
 use crate::cli::Profile;
 
-use super::boundaries::Boundaries;
+use super::boundaries::{Boundaries, BoundariesExplanation};
 use super::downloader::{Downloaded, Downloader};
-use super::parser::{Parsed, Parser};
+use super::parser::{PageInfo, Parsed, Parser};
 use super::Configuration;
 // use super::Counter;
 use super::Parameters;
@@ -154,6 +154,8 @@ pub(crate) enum Crawled {
 #[derive(Debug, Serialize)]
 pub struct TestRunReport {
     pub(crate) actual_url: Url,
+    /// Which boundary rules apply to the tested URL.
+    pub(crate) boundaries: BoundariesExplanation,
     pub(crate) report: ReportType,
 }
 
@@ -197,18 +199,21 @@ impl CrawlWorker {
         Origins::new(self.parameters.max_hits_per_sec)
     }
 
-    async fn crawl(&self, page_url: &Url) -> Crawled {
-        // Now, download, but be quick.
-        let crawl = time::timeout(
-            Duration::from_secs_f64(self.parameters.request_timeout),
-            self.downloader.download(page_url),
-        );
-
-        let crawled = match crawl.await {
-            Ok(Ok(Downloaded::Page {
+    /// Interprets what was downloaded from a page, parsing its content and
+    /// cleaning the links found according to the boundaries.
+    fn interpret(&self, page_url: &Url, depth: u16, downloaded: Downloaded) -> Crawled {
+        match downloaded {
+            Downloaded::Page {
                 content,
                 status_code,
-            })) => match self.parser.parse(page_url, &content) {
+            } => match self.parser.parse(
+                PageInfo {
+                    url: page_url,
+                    depth,
+                    status_code,
+                },
+                &content,
+            ) {
                 Parsed::Accepted { links, analyses } => Crawled::Success {
                     status_code,
                     links: self.boundaries.clean_links(page_url, &links),
@@ -220,14 +225,26 @@ impl CrawlWorker {
                     analyses: vec![],
                 },
             },
-            Ok(Ok(Downloaded::BadStatus { status_code, .. })) => Crawled::BadStatus { status_code },
-            Ok(Ok(Downloaded::Redirect {
+            Downloaded::BadStatus { status_code, .. } => Crawled::BadStatus { status_code },
+            Downloaded::Redirect {
                 location,
                 status_code,
-            })) => Crawled::Redirect {
+            } => Crawled::Redirect {
                 status_code,
                 location,
             },
+        }
+    }
+
+    async fn crawl(&self, page_url: &Url, depth: u16) -> Crawled {
+        // Now, download, but be quick.
+        let crawl = time::timeout(
+            Duration::from_secs_f64(self.parameters.request_timeout),
+            self.downloader.download(page_url),
+        );
+
+        let crawled = match crawl.await {
+            Ok(Ok(downloaded)) => self.interpret(page_url, depth, downloaded),
             Ok(Err(error)) => Crawled::Error(error),
             Err(_) => Crawled::TimedOut,
         };
@@ -320,7 +337,7 @@ impl CrawlWorker {
         origin.block().await;
 
         // Then, you crawl:
-        let crawled = self.crawl(page_url).await;
+        let crawled = self.crawl(page_url, depth).await;
 
         // Finally, you store!
         self.store(worker_backend, page_url, depth, crawled).await?;
@@ -385,11 +402,13 @@ impl CrawlWorker {
     }
 
     pub async fn test_url(self, url: Url) -> TestRunReport {
+        let boundaries = self.boundaries.explain(&url);
         let actual_url = self.boundaries.clean_query_params(url);
 
         if !self.boundaries.is_allowed(&actual_url) {
             return TestRunReport {
                 actual_url,
+                boundaries,
                 report: ReportType::DisallowedByDirectives,
             };
         }
@@ -404,15 +423,152 @@ impl CrawlWorker {
         if !origin.allows(&actual_url) {
             return TestRunReport {
                 actual_url,
+                boundaries,
                 report: ReportType::DisallowedByOrigin,
             };
         }
 
-        let crawled = self.crawl(&actual_url).await;
+        let crawled = self.crawl(&actual_url, 0).await;
+
+        TestRunReport {
+            actual_url,
+            boundaries,
+            report: ReportType::Crawled(crawled),
+        }
+    }
+
+    /// Tests a page content as if it had been downloaded from `url`. No
+    /// request is made: `robots.txt` is not checked and the page is assumed to
+    /// have been served with `200 OK`.
+    pub fn test_content(self, url: Url, content: Vec<u8>) -> TestRunReport {
+        let boundaries = self.boundaries.explain(&url);
+        let actual_url = self.boundaries.clean_query_params(url);
+
+        if !self.boundaries.is_allowed(&actual_url) {
+            return TestRunReport {
+                actual_url,
+                boundaries,
+                report: ReportType::DisallowedByDirectives,
+            };
+        }
+
+        let crawled = self.interpret(
+            &actual_url,
+            0,
+            Downloaded::Page {
+                content,
+                status_code: StatusCode::OK,
+            },
+        );
 
         TestRunReport {
             actual_url,
+            boundaries,
             report: ReportType::Crawled(crawled),
         }
     }
 }
+
+#[cfg(test)]
+fn test_worker() -> CrawlWorker {
+    use crate::backend::{Backend, MemoryBackend};
+    use crate::directives::load_test_directives;
+    use crate::DirectivesConfiguration;
+
+    let (directives, _dir) = load_test_directives(&[(
+        "main.lcd",
+        "allow \"^https://example.com/\";\n\
+        disallow \"/private\";\n\
+        select h1 { heading: first(text); }\n",
+    )]);
+
+    let profile = Arc::new(Profile::default());
+    let configuration = DirectivesConfiguration::new(directives, profile.clone());
+    let worker_backend_factory = MemoryBackend::new("test").build_worker_factory(0);
+
+    CrawlWorker::new(&configuration, worker_backend_factory.into(), profile)
+}
+
+#[test]
+fn interpret_test() {
+    let worker = test_worker();
+    let page_url = Url::parse("https://example.com/a").unwrap();
+
+    let crawled = worker.interpret(
+        &page_url,
+        1,
+        Downloaded::Page {
+            content: br#"<h1>Hello</h1>
+                <a href="/b">b</a>
+                <a href="/private/c">c</a>
+                <a href="https://elsewhere.com/">d</a>"#
+                .to_vec(),
+            status_code: StatusCode::OK,
+        },
+    );
+    match crawled {
+        Crawled::Success {
+            status_code,
+            links,
+            analyses,
+        } => {
+            assert_eq!(status_code, StatusCode::OK);
+            assert!(links
+                .iter()
+                .any(|(_, url)| url.as_str() == "https://example.com/b"));
+            assert_eq!(
+                analyses,
+                vec![("heading".to_owned(), serde_json::json!("Hello"))]
+            );
+        }
+        crawled => panic!("expected success, got {crawled:?}"),
+    }
+
+    let crawled = worker.interpret(
+        &page_url,
+        1,
+        Downloaded::BadStatus {
+            status_code: StatusCode::NOT_FOUND,
+        },
+    );
+    assert!(matches!(
+        crawled,
+        Crawled::BadStatus {
+            status_code: StatusCode::NOT_FOUND
+        }
+    ));
+
+    let crawled = worker.interpret(
+        &page_url,
+        1,
+        Downloaded::Redirect {
+            location: "https://example.com/b".to_owned(),
+            status_code: StatusCode::MOVED_PERMANENTLY,
+        },
+    );
+    assert!(
+        matches!(crawled, Crawled::Redirect { location, .. } if location == "https://example.com/b")
+    );
+}
+
+#[test]
+fn test_content_test() {
+    let report = test_worker().test_content(
+        Url::parse("https://example.com/a").unwrap(),
+        b"<h1>Hello</h1>".to_vec(),
+    );
+    assert_eq!(report.actual_url.as_str(), "https://example.com/a");
+    match report.report {
+        ReportType::Crawled(Crawled::Success { analyses, .. }) => assert_eq!(
+            analyses,
+            vec![("heading".to_owned(), serde_json::json!("Hello"))]
+        ),
+        report => panic!("expected a crawled page, got {report:?}"),
+    }
+
+    let report = test_worker().test_content(
+        Url::parse("https://example.com/private/a").unwrap(),
+        b"<h1>Hello</h1>".to_vec(),
+    );
+    assert!(matches!(report.report, ReportType::DisallowedByDirectives));
+}
@@ -0,0 +1,200 @@
+//! What changed between two crawls of the same sites.
+
+use futures::prelude::*;
+use serde_derive::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use url::Url;
+
+use crate::backend::{ComparedPage, LocalBoxStream};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusCodeChange {
+    pub url: Url,
+    pub from: u16,
+    pub to: u16,
+}
+
+/// A page that started or stopped redirecting, or that redirects elsewhere.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RedirectChange {
+    pub url: Url,
+    pub from: Option<Url>,
+    pub to: Option<Url>,
+}
+
+/// An analysis whose result changed. A missing result is `None`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnalysisChange {
+    pub url: Url,
+    pub analysis: String,
+    pub from: Option<Value>,
+    pub to: Option<Value>,
+}
+
+/// Everything that changed from wave `a` to wave `b`, page by page.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WaveDiff {
+    pub wave_a: String,
+    pub wave_b: String,
+    /// Crawled in `b` but not in `a`.
+    pub new_pages: Vec<Url>,
+    /// Crawled in `a` but not in `b`.
+    pub removed_pages: Vec<Url>,
+    pub status_code_changes: Vec<StatusCodeChange>,
+    pub redirect_changes: Vec<RedirectChange>,
+    pub analysis_changes: Vec<AnalysisChange>,
+}
+
+impl WaveDiff {
+    pub fn is_empty(&self) -> bool {
+        self.new_pages.is_empty()
+            && self.removed_pages.is_empty()
+            && self.status_code_changes.is_empty()
+            && self.redirect_changes.is_empty()
+            && self.analysis_changes.is_empty()
+    }
+}
+
+/// Sorts the pages compared by [`crate::backend::Backend::compare`] into what
+/// changed from `wave_a` to `wave_b`.
+pub async fn diff_waves(
+    wave_a: &str,
+    wave_b: &str,
+    mut pages: LocalBoxStream<'static, Result<ComparedPage, anyhow::Error>>,
+) -> Result<WaveDiff, anyhow::Error> {
+    let mut diff = WaveDiff {
+        wave_a: wave_a.to_owned(),
+        wave_b: wave_b.to_owned(),
+        new_pages: vec![],
+        removed_pages: vec![],
+        status_code_changes: vec![],
+        redirect_changes: vec![],
+        analysis_changes: vec![],
+    };
+
+    while let Some(ComparedPage { url, a, b }) = pages.try_next().await? {
+        let (mut a, mut b) = match (a, b) {
+            (Some(a), Some(b)) => (a, b),
+            (None, Some(_)) => {
+                diff.new_pages.push(url);
+                continue;
+            }
+            (Some(_), None) => {
+                diff.removed_pages.push(url);
+                continue;
+            }
+            (None, None) => continue,
+        };
+
+        if a.status_code != b.status_code {
+            diff.status_code_changes.push(StatusCodeChange {
+                url: url.clone(),
+                from: a.status_code,
+                to: b.status_code,
+            });
+        }
+
+        if a.redirect != b.redirect {
+            diff.redirect_changes.push(RedirectChange {
+                url: url.clone(),
+                from: a.redirect,
+                to: b.redirect,
+            });
+        }
+
+        let analyses = a
+            .analyses
+            .keys()
+            .chain(b.analyses.keys())
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        for analysis in analyses {
+            let from = a.analyses.remove(&analysis);
+            let to = b.analyses.remove(&analysis);
+            if from != to {
+                diff.analysis_changes.push(AnalysisChange {
+                    url: url.clone(),
+                    analysis,
+                    from,
+                    to,
+                });
+            }
+        }
+    }
+
+    Ok(diff)
+}
+
+#[test]
+fn diff_waves_test() {
+    use serde_json::json;
+
+    use crate::backend::{stream, PageSnapshot, StreamExt};
+
+    let url = |path: &str| format!("https://a.foo{path}").parse::<Url>().unwrap();
+    let snapshot = |status_code, redirect: Option<&str>, heading: &str| PageSnapshot {
+        status_code,
+        redirect: redirect.map(url),
+        analyses: vec![("heading".to_owned(), json!(heading))]
+            .into_iter()
+            .collect(),
+    };
+    let pages = vec![
+        ComparedPage {
+            url: url("/"),
+            a: Some(snapshot(200, None, "home")),
+            b: Some(snapshot(200, None, "home")),
+        },
+        ComparedPage {
+            url: url("/new"),
+            a: None,
+            b: Some(snapshot(200, None, "new")),
+        },
+        ComparedPage {
+            url: url("/old"),
+            a: Some(snapshot(200, None, "old")),
+            b: None,
+        },
+        ComparedPage {
+            url: url("/moved"),
+            a: Some(snapshot(200, None, "moved")),
+            b: Some(snapshot(301, Some("/new"), "")),
+        },
+    ];
+
+    let diff = futures::executor::block_on(diff_waves(
+        "a",
+        "b",
+        stream::iter(pages.into_iter().map(Ok)).boxed_local(),
+    ))
+    .unwrap();
+
+    assert_eq!(diff.new_pages, vec![url("/new")]);
+    assert_eq!(diff.removed_pages, vec![url("/old")]);
+    assert_eq!(
+        diff.status_code_changes,
+        vec![StatusCodeChange {
+            url: url("/moved"),
+            from: 200,
+            to: 301
+        }]
+    );
+    assert_eq!(
+        diff.redirect_changes,
+        vec![RedirectChange {
+            url: url("/moved"),
+            from: None,
+            to: Some(url("/new"))
+        }]
+    );
+    assert_eq!(
+        diff.analysis_changes,
+        vec![AnalysisChange {
+            url: url("/moved"),
+            analysis: "heading".to_owned(),
+            from: Some(json!("moved")),
+            to: Some(json!("")),
+        }]
+    );
+}
@@ -0,0 +1,242 @@
+//! Conditions on the page that gate rule sets, as in
+//! `select when status-code == 200 .price { ... }`.
+
+use scraper::Html;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+use crate::Type;
+
+use super::expressions::{force_f64, Error, Extractable, ExtractorExpression, Typed};
+use super::extractor::{Extractor, Page, PageElement};
+use super::parse_common::quote;
+use super::ElementSelector;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Lesser,
+    LesserOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Comparison::Equal => write!(f, "=="),
+            Comparison::NotEqual => write!(f, "!="),
+            Comparison::Lesser => write!(f, "<"),
+            Comparison::LesserOrEqual => write!(f, "<="),
+            Comparison::Greater => write!(f, ">"),
+            Comparison::GreaterOrEqual => write!(f, ">="),
+        }
+    }
+}
+
+impl Comparison {
+    fn is_ordering(self) -> bool {
+        !matches!(self, Comparison::Equal | Comparison::NotEqual)
+    }
+
+    fn compare(self, lhs: &Value, rhs: &Value) -> bool {
+        match (lhs, rhs) {
+            (Value::Number(lhs), Value::Number(rhs)) => {
+                let (lhs, rhs) = (force_f64(lhs), force_f64(rhs));
+                match self {
+                    Comparison::Equal => (lhs - rhs).abs() < f64::EPSILON,
+                    Comparison::NotEqual => (lhs - rhs).abs() >= f64::EPSILON,
+                    Comparison::Lesser => lhs < rhs,
+                    Comparison::LesserOrEqual => lhs <= rhs,
+                    Comparison::Greater => lhs > rhs,
+                    Comparison::GreaterOrEqual => lhs >= rhs,
+                }
+            }
+            // Nothing compares to `null`:
+            (Value::Null, _) => false,
+            (lhs, rhs) => match self {
+                Comparison::Equal => lhs == rhs,
+                Comparison::NotEqual => lhs != rhs,
+                // Only numbers are ordered:
+                _ => false,
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    /// Whether the selector matches anything in the page.
+    Exists(ElementSelector),
+    /// Compares what is extracted from the root of the page with a literal.
+    Compare(ExtractorExpression<Extractor>, Comparison, Value),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Exists(ElementSelector::Css(selector)) => {
+                write!(f, "exists({})", quote(&selector.to_string()))
+            }
+            Condition::Exists(selector) => write!(f, "exists({})", selector),
+            Condition::Compare(extractor_expr, comparison, Value::String(string)) => {
+                write!(f, "{} {} {}", extractor_expr, comparison, quote(string))
+            }
+            Condition::Compare(extractor_expr, comparison, literal) => {
+                write!(f, "{} {} {}", extractor_expr, comparison, literal)
+            }
+            Condition::Not(inner) => match inner.as_ref() {
+                Condition::And(..) | Condition::Or(..) => write!(f, "not ({})", inner),
+                _ => write!(f, "not {}", inner),
+            },
+            Condition::And(lhs, rhs) => {
+                for (i, operand) in [lhs, rhs].into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " and ")?;
+                    }
+
+                    if let Condition::Or(..) = operand.as_ref() {
+                        write!(f, "({})", operand)?;
+                    } else {
+                        write!(f, "{}", operand)?;
+                    }
+                }
+
+                Ok(())
+            }
+            Condition::Or(lhs, rhs) => write!(f, "{} or {}", lhs, rhs),
+        }
+    }
+}
+
+impl Condition {
+    /// Calls `f` on every extractor expression in this condition.
+    pub fn with_extractor_expr_mut<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&mut ExtractorExpression<Extractor>),
+    {
+        match self {
+            Condition::Exists(_) => {}
+            Condition::Compare(extractor_expr, _, _) => f(extractor_expr),
+            Condition::Not(inner) => inner.with_extractor_expr_mut(f),
+            Condition::And(lhs, rhs) | Condition::Or(lhs, rhs) => {
+                lhs.with_extractor_expr_mut(f);
+                rhs.with_extractor_expr_mut(f);
+            }
+        }
+    }
+
+    pub fn type_check(&self) -> Result<(), Error> {
+        match self {
+            Condition::Exists(_) => Ok(()),
+            Condition::Compare(extractor_expr, comparison, literal) => {
+                let typ = extractor_expr.type_of()?;
+                let expected = match literal {
+                    Value::Number(_) => Type::Number,
+                    Value::String(_) => Type::String,
+                    Value::Bool(_) => Type::Bool,
+                    _ => {
+                        return Err(Error::TypeError(self.to_string(), typ));
+                    }
+                };
+
+                if typ != expected {
+                    Err(Error::ExpectedType {
+                        thing: self.to_string(),
+                        expected,
+                        got: typ,
+                    })
+                } else if comparison.is_ordering() && typ != Type::Number {
+                    Err(Error::NotExpectedType {
+                        thing: self.to_string(),
+                        not_expected: typ,
+                    })
+                } else {
+                    Ok(())
+                }
+            }
+            Condition::Not(inner) => inner.type_check(),
+            Condition::And(lhs, rhs) | Condition::Or(lhs, rhs) => {
+                lhs.type_check()?;
+                rhs.type_check()
+            }
+        }
+    }
+
+    /// Whether this condition holds for a page.
+    pub fn holds(&self, page: &Page, html: &Html) -> bool {
+        match self {
+            Condition::Exists(selector) => !selector.select(html).is_empty(),
+            Condition::Compare(extractor_expr, comparison, literal) => {
+                let root = PageElement {
+                    element_ref: html.root_element(),
+                    page,
+                };
+                comparison.compare(&root.extract_with(extractor_expr), literal)
+            }
+            Condition::Not(inner) => !inner.holds(page, html),
+            Condition::And(lhs, rhs) => lhs.holds(page, html) && rhs.holds(page, html),
+            Condition::Or(lhs, rhs) => lhs.holds(page, html) || rhs.holds(page, html),
+        }
+    }
+}
+
+#[test]
+fn holds_test() {
+    use super::parse::condition;
+    use crate::crawler::PageInfo;
+
+    let url = "https://example.foo/p/1".parse().unwrap();
+    let page = Page::new(
+        PageInfo {
+            url: &url,
+            depth: 2,
+            status_code: http::StatusCode::OK,
+        },
+        &[],
+    );
+    let html = Html::parse_document("<html><body><h1 class=\"title\">Hi</h1></body></html>");
+
+    for (source, expected) in [
+        ("page-depth == 2", true),
+        ("page-depth == 3", false),
+        ("page-depth != 3", true),
+        ("page-depth != 2", false),
+        ("page-depth < 3", true),
+        ("page-depth < 2", false),
+        ("page-depth <= 2", true),
+        ("page-depth <= 1", false),
+        ("page-depth > 1", true),
+        ("page-depth > 2", false),
+        ("page-depth >= 2", true),
+        ("page-depth >= 3", false),
+        ("page-url == \"https://example.foo/p/1\"", true),
+        ("page-url == \"https://example.foo/\"", false),
+        ("page-url != \"https://example.foo/\"", true),
+        ("page-url != \"https://example.foo/p/1\"", false),
+        ("page-url matches \"/p/\" == true", true),
+        ("page-url matches \"/q/\" == true", false),
+        ("page-url matches \"/q/\" != true", true),
+        ("page-url matches \"/p/\" != true", false),
+        ("page-url as-number == 1", false),
+        ("page-url as-number != 1", false),
+        ("exists(\".title\")", true),
+        ("exists(xpath \"//h2\")", false),
+        ("not exists(\".title\")", false),
+        ("status-code == 200 and page-depth > 2", false),
+        ("status-code == 200 or page-depth > 2", true),
+    ] {
+        let (rest, parsed) = condition(source).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(parsed.unwrap().holds(&page, &html), expected, "{}", source);
+    }
+
+    // Strings are not ordered, and ordering them is a type error; still:
+    assert!(!Comparison::Lesser.compare(&"a".into(), &"b".into()));
+    assert!(!Comparison::GreaterOrEqual.compare(&true.into(), &false.into()));
+}
@@ -0,0 +1,135 @@
+//! Problems found in the directives, located in their source files.
+
+use ansi_term::Color::{Blue, Red};
+use ansi_term::Style;
+use serde_derive::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::parse_utils::Position;
+
+/// A problem found in the directives, located in a source file.
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    /// The line of the problem, starting from 1.
+    pub line: usize,
+    /// The column of the problem, starting from 1.
+    pub column: usize,
+    pub message: String,
+    /// The source line where the problem is.
+    pub snippet: String,
+}
+
+/// Renders the diagnostic as in compiler output. The alternate form (`{:#}`)
+/// is colored, for the terminal.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (error, accent) = if f.alternate() {
+            (Red.bold(), Blue.bold())
+        } else {
+            (Style::new(), Style::new())
+        };
+        let gutter = self.line.to_string().len();
+        let bar = accent.paint("|");
+
+        writeln!(f, "{}: {}", error.paint("error"), self.message)?;
+        writeln!(
+            f,
+            "{:gutter$}{} {}:{}:{}",
+            "",
+            accent.paint("-->"),
+            self.file.display(),
+            self.line,
+            self.column
+        )?;
+        writeln!(f, "{:gutter$} {}", "", bar)?;
+        writeln!(
+            f,
+            "{} {} {}",
+            accent.paint(self.line.to_string()),
+            bar,
+            self.snippet
+        )?;
+        write!(
+            f,
+            "{:gutter$} {} {}{}",
+            "",
+            bar,
+            " ".repeat(self.column.saturating_sub(1)),
+            error.paint("^")
+        )
+    }
+}
+
+impl Diagnostic {
+    pub(crate) fn new(
+        file: &Path,
+        source: &str,
+        position: Position,
+        message: impl Into<String>,
+    ) -> Diagnostic {
+        Diagnostic {
+            file: file.to_owned(),
+            line: position.line(),
+            column: position.column(),
+            message: message.into(),
+            snippet: source
+                .lines()
+                .nth(position.line() - 1)
+                .unwrap_or_default()
+                .to_owned(),
+        }
+    }
+}
+
+/// All problems found while loading some directives.
+#[derive(Debug, Serialize)]
+#[serde(transparent)]
+pub struct Diagnostics {
+    pub(crate) diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "there are {} issues with your configuration:",
+            self.diagnostics.len()
+        )?;
+
+        for diagnostic in &self.diagnostics {
+            write!(f, "\n{}\n", diagnostic)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+impl Diagnostics {
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+#[test]
+fn diagnostic_display_test() {
+    let source = "seed \"a\";\n  allow \"(\";\n";
+    let diagnostic = Diagnostic::new(
+        Path::new("main.lcd"),
+        source,
+        Position::of(source, "allow \"(\";\n"),
+        "bad regex",
+    );
+
+    assert_eq!(
+        diagnostic.to_string(),
+        "error: bad regex\n --> main.lcd:2:3\n  |\n2 |   allow \"(\";\n  |   ^"
+    );
+}
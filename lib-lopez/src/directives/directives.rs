@@ -2,18 +2,25 @@ use regex::RegexSet;
 use scraper::Html;
 use serde_derive::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use url::Url;
 
+use crate::crawler::{BoundariesExplanation, RuleOrigin, StrippedParam};
 use crate::Type;
 
-use super::expressions::AggregatorExpressionState;
-use super::expressions::Error;
-use super::parse::{Boundary, Item, RuleSet, WebDriver};
+use super::condition::Condition;
+use super::diagnostics::{Diagnostic, Diagnostics};
+use super::expressions::{AggregatorExpressionState, Context, Definition, Error};
+use super::extractor::{Page, PageElement};
+use super::parse::{Boundary, DirectiveTest, Item, RuleSet, WebDriver};
+use super::parse_utils::Position;
+#[cfg(test)]
+use super::test_dir::{load_test_directives, TestDir};
 use super::variable::{SetVariables, Variable};
 
 const SEPARATOR: &str = ".";
@@ -21,9 +28,17 @@ const EXTENSION: &str = "lcd";
 const MODULE_FILE: &str = "module";
 
 /// Reads from a list of possible paths and returns at the first not-not-found
-/// (there might be other errors). Returns not found if none matches.
-fn read_from_many<P: AsRef<Path>>(paths: &[P]) -> Result<(&P, String), io::Error> {
+/// (there might be other errors). Returns not found if none matches. Contents
+/// in `overlays` take precedence over what is on disk.
+fn read_from_many<'a, P: AsRef<Path>>(
+    paths: &'a [P],
+    overlays: &HashMap<PathBuf, String>,
+) -> Result<(&'a P, String), io::Error> {
     for path in paths {
+        if let Some(content) = overlays.get(path.as_ref()) {
+            return Ok((path, content.clone()));
+        }
+
         match fs::read_to_string(path.as_ref()) {
             Ok(content) => return Ok((path, content)),
             Err(err) if err.kind() == io::ErrorKind::NotFound => {}
@@ -34,11 +49,12 @@ fn read_from_many<P: AsRef<Path>>(paths: &[P]) -> Result<(&P, String), io::Error
     Err(io::Error::from(io::ErrorKind::NotFound))
 }
 
-/// Loads items for a given module from a list of possible paths.
-fn load_items_from<'a, P: AsRef<Path>>(
+/// Reads the source of a given module from a list of possible paths.
+fn read_module_from<'a, P: AsRef<Path>>(
     module_name: &str,
     paths: &'a [P],
-) -> Result<(&'a P, Vec<Item>), anyhow::Error> {
+    overlays: &HashMap<PathBuf, String>,
+) -> Result<(&'a P, String), anyhow::Error> {
     let formatted_module_name = if module_name.is_empty() {
         "<main>"
     } else {
@@ -49,22 +65,16 @@ fn load_items_from<'a, P: AsRef<Path>>(
     // need to put these paths in the other map_errs.
     let printable_paths = paths.iter().map(P::as_ref).collect::<Vec<_>>();
 
-    let (path, module_str) = read_from_many(paths).map_err(|err| {
+    read_from_many(paths, overlays).map_err(|err| {
         anyhow::anyhow!(
             "could not open module `{formatted_module_name}` from paths `{printable_paths:?}`: {err}",
         )
-    })?;
-
-    let module = super::parse::entrypoint(&module_str)
-        .map_err(|err| anyhow::anyhow!("failed to parse `{formatted_module_name}`: {err}"))?
-        .map_err(|err| anyhow::anyhow!("failed to interpret `{formatted_module_name}`: {err}"))?;
-
-    Ok((path, module))
+    })
 }
 
 /// Strips `supers` and `roots` from a module path. Returns errors if put into
 /// an impossible position.
-fn canonical_path(path: &str) -> Result<String, anyhow::Error> {
+pub(super) fn canonical_path(path: &str) -> Result<String, anyhow::Error> {
     let mut parts = vec![];
     for part in path.split(SEPARATOR) {
         match part {
@@ -84,7 +94,7 @@ fn canonical_path(path: &str) -> Result<String, anyhow::Error> {
 }
 
 /// Gives the name of a directive, given a name and a prefix.
-fn full_rule_name(prefix: &str, rule_name: &str) -> String {
+pub(super) fn full_rule_name(prefix: &str, rule_name: &str) -> String {
     if prefix != "" {
         prefix.to_owned() + SEPARATOR + rule_name
     } else {
@@ -92,6 +102,31 @@ fn full_rule_name(prefix: &str, rule_name: &str) -> String {
     }
 }
 
+/// Where a definition was declared: the module and the index of the item.
+type DefinitionIndex = HashMap<String, (String, usize)>;
+
+/// How far resolving a definition has gone.
+enum Resolution {
+    InProgress,
+    /// Resolved, unless it had problems.
+    Done(Option<Arc<Definition>>),
+}
+
+/// Finds the resolved definition referred to as `name` from the module
+/// `module_name`.
+fn resolved(
+    module_name: &str,
+    name: &str,
+    resolutions: &HashMap<String, Resolution>,
+) -> Option<Arc<Definition>> {
+    let full_name = canonical_path(&full_rule_name(module_name, name)).ok()?;
+
+    match resolutions.get(&full_name)? {
+        Resolution::Done(definition) => definition.clone(),
+        Resolution::InProgress => None,
+    }
+}
+
 /// Directives for Lopez.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Directives {
@@ -101,36 +136,65 @@ pub struct Directives {
 /// A module of directives.
 #[derive(Debug, Serialize, Deserialize)]
 struct Module {
-    items: Vec<Item>,
+    /// The file from which this module was loaded.
+    path: PathBuf,
+    /// The items of this module, with the position where each was declared.
+    items: Vec<(Position, Item)>,
+    /// The source from which the items were parsed.
+    #[serde(default)]
+    source: String,
 }
 
 impl Module {
     /// Finds duplicates names for scraping rules within this modules.
     fn find_duplicate_rules(
         &self,
-        prefix: String,
+        prefix: &str,
         rule_names: &mut HashSet<String>,
-        duplicates: &mut HashSet<String>,
+        issues: &mut Vec<(Position, String)>,
     ) {
         // Find all rule names:
-        for item in &self.items {
+        for (position, item) in &self.items {
             if let Item::RuleSet(rule_set) = item {
                 for rule_name in rule_set.aggregators.keys() {
-                    let full_name = full_rule_name(&prefix, rule_name);
+                    let full_name = full_rule_name(prefix, rule_name);
                     if !rule_names.insert(full_name.clone()) {
-                        duplicates.insert(full_name);
+                        issues.push((*position, format!("duplicated rule `{full_name}`")));
                     }
                 }
             }
         }
     }
 
+    /// Finds seeds that are outside bounds, together with the reasons why.
+    fn find_invalid_seeds(&self, boundaries: &Boundaries, issues: &mut Vec<(Position, String)>) {
+        for (position, item) in &self.items {
+            if let Item::Seed(seed) = item {
+                let seed_issues = boundaries.explain(seed).seed_issues();
+
+                if !seed_issues.is_empty() {
+                    issues.push((
+                        *position,
+                        format!(
+                            "seed `{}` is on the frontier or outside your boundaries: {}",
+                            boundaries.filter_query_params(seed.clone()),
+                            seed_issues.join("; ")
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
     /// Finds invalide set-variable names within this module.
-    fn find_invalid_set_variables(&self, invalid: &mut HashSet<String>) {
-        for item in &self.items {
+    fn find_invalid_set_variables(&self, issues: &mut Vec<(Position, String)>) {
+        for (position, item) in &self.items {
             if let Item::SetVariable(set_variable) = item {
                 if Variable::try_parse(&set_variable.name).is_none() {
-                    invalid.insert(set_variable.name.clone());
+                    issues.push((
+                        *position,
+                        format!("unknown set-variable `{}`", set_variable.name),
+                    ));
                 }
             }
         }
@@ -140,48 +204,95 @@ impl Module {
     fn find_duplicate_set_variables(
         &self,
         set_variables: &mut HashSet<String>,
-        duplicates: &mut HashSet<String>,
+        issues: &mut Vec<(Position, String)>,
     ) {
-        for item in &self.items {
+        for (position, item) in &self.items {
             if let Item::SetVariable(set_variable) = item {
                 if !set_variables.insert(set_variable.name.clone()) {
-                    duplicates.insert(set_variable.name.clone());
+                    issues.push((
+                        *position,
+                        format!(
+                            "duplicate set-variable `{}` (these definitions are global)",
+                            set_variable.name
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Validates set-variables types. After this, you can always unwrap errors
+    /// on `SetVariable`.
+    fn find_bad_set_variable_values(&self, issues: &mut Vec<(Position, String)>) {
+        for (position, item) in &self.items {
+            if let Item::SetVariable(set_variable) = item {
+                if let Some(variable) = Variable::try_parse(&set_variable.name) {
+                    if let Err(err) = variable.check_value(&set_variable.value) {
+                        issues.push((*position, err.to_string()));
+                    }
                 }
             }
         }
     }
 
     /// Finds type errors:
-    fn find_type_errors(&self, prefix: String, type_errors: &mut BTreeMap<String, Error>) {
-        for item in &self.items {
+    fn find_type_errors(&self, prefix: &str, issues: &mut Vec<(Position, String)>) {
+        for (position, item) in &self.items {
             if let Item::RuleSet(rule_set) = item {
+                if let Some(Err(error)) = rule_set.when.as_ref().map(Condition::type_check) {
+                    issues.push((*position, format!("in condition: {error}")));
+                }
+
                 for (rule_name, rule) in &rule_set.aggregators {
                     if let Err(error) = rule.type_of() {
-                        let full_name = full_rule_name(&prefix, &rule_name);
-                        type_errors.insert(full_name, error);
+                        let full_name = full_rule_name(prefix, rule_name);
+                        issues.push((*position, format!("in rule `{full_name}`: {error}")));
                     }
                 }
             }
         }
     }
 
-    /// Loads a module and its dependencies into a set of modules.
+    /// Loads a module and its dependencies into a set of modules. Problems
+    /// found in the modules are pushed into `diagnostics` and the loading
+    /// goes on; this only fails if the module itself cannot be read.
     fn load<P: AsRef<Path>, Q: AsRef<Path>>(
         roots: &[P],
         module_name: String,
         modules: &mut BTreeMap<String, Module>,
+        diagnostics: &mut Vec<Diagnostic>,
         paths: &[Q],
+        overlays: &HashMap<PathBuf, String>,
     ) -> Result<(), anyhow::Error> {
         if modules.contains_key(&module_name) {
             return Ok(());
         }
 
-        let (_path, items) = load_items_from(&module_name, paths)?;
+        let (path, source) = read_module_from(&module_name, paths, overlays)?;
+        let path = path.as_ref().to_owned();
+        let (items, errors) = super::parse::entrypoint(&source);
+
+        diagnostics.extend(
+            errors
+                .into_iter()
+                .map(|(position, error)| Diagnostic::new(&path, &source, position, error)),
+        );
 
-        for item in &items {
+        for (position, item) in &items {
             if let Item::Module(module) = item {
                 let sub_module_name =
-                    canonical_path(&(module_name.to_owned() + SEPARATOR + &module.path))?;
+                    match canonical_path(&(module_name.to_owned() + SEPARATOR + &module.path)) {
+                        Ok(sub_module_name) => sub_module_name,
+                        Err(err) => {
+                            diagnostics.push(Diagnostic::new(
+                                &path,
+                                &source,
+                                *position,
+                                err.to_string(),
+                            ));
+                            continue;
+                        }
+                    };
 
                 let paths = roots
                     .iter()
@@ -199,198 +310,375 @@ impl Module {
                     })
                     .collect::<Vec<_>>();
 
-                Self::load(roots, sub_module_name, modules, &paths)?;
+                if let Err(err) = Self::load(
+                    roots,
+                    sub_module_name,
+                    modules,
+                    diagnostics,
+                    &paths,
+                    overlays,
+                ) {
+                    diagnostics.push(Diagnostic::new(&path, &source, *position, err.to_string()));
+                }
             }
         }
 
-        modules.insert(module_name, Module { items });
+        modules.insert(
+            module_name,
+            Module {
+                path,
+                items,
+                source,
+            },
+        );
 
         Ok(())
     }
 }
 
 impl Directives {
-    /// Finds duplicates names for scraping rules within all modules.
-    fn find_duplicate_rules(&self) -> HashSet<String> {
-        let mut rule_names = HashSet::new();
-        let mut duplicates = HashSet::new();
+    /// Resolves a definition, after all the definitions it refers to, and
+    /// type checks it.
+    fn resolve_definition(
+        &mut self,
+        full_name: &str,
+        index: &DefinitionIndex,
+        resolutions: &mut HashMap<String, Resolution>,
+        issues: &mut Vec<(String, Position, String)>,
+    ) -> Option<Arc<Definition>> {
+        match resolutions.get(full_name) {
+            Some(Resolution::Done(definition)) => return definition.clone(),
+            Some(Resolution::InProgress) => return None,
+            None => {}
+        }
 
-        for (name, module) in &self.modules {
-            module.find_duplicate_rules(name.to_owned(), &mut rule_names, &mut duplicates);
+        let (module_name, idx) = index.get(full_name)?.clone();
+        let (position, item) = &self.modules[&module_name].items[idx];
+        let position = *position;
+        let references = match item {
+            Item::Define(definition) => definition
+                .expression
+                .references()
+                .into_iter()
+                .map(str::to_owned)
+                .collect::<Vec<_>>(),
+            _ => unreachable!("indexed item is a definition"),
+        };
+
+        resolutions.insert(full_name.to_owned(), Resolution::InProgress);
+
+        let mut dependencies = HashMap::new();
+        let mut is_cyclic = false;
+
+        for reference in references {
+            let full_reference = match canonical_path(&full_rule_name(&module_name, &reference)) {
+                Ok(full_reference) => full_reference,
+                Err(_) => continue,
+            };
+
+            if let Some(Resolution::InProgress) = resolutions.get(&full_reference) {
+                issues.push((
+                    module_name.clone(),
+                    position,
+                    format!("definition `{full_name}` refers to itself through `{reference}`"),
+                ));
+                is_cyclic = true;
+            } else if let Some(dependency) =
+                self.resolve_definition(&full_reference, index, resolutions, issues)
+            {
+                dependencies.insert(reference, dependency);
+            }
         }
 
-        duplicates
-    }
+        let definition = match &mut self.modules.get_mut(&module_name)?.items[idx].1 {
+            Item::Define(definition) => definition,
+            _ => unreachable!("indexed item is a definition"),
+        };
+        let checked = {
+            let definition =
+                Arc::get_mut(definition).expect("definitions are only shared once resolved");
+            definition
+                .expression
+                .resolve_references(&mut |name| dependencies.get(name).cloned());
+            definition.check()
+        };
+        let outcome = match checked {
+            Ok(()) => Some(Arc::clone(definition)),
+            Err(_) if is_cyclic => None,
+            Err(error) => {
+                issues.push((
+                    module_name,
+                    position,
+                    format!("in definition `{full_name}`: {error}"),
+                ));
+                None
+            }
+        };
 
-    /// Finds seeds that are outside bounds.
-    /// TODO: implement "disallowed by regex so-and-so".
-    fn find_invalid_seeds(&self) -> Vec<Url> {
-        // This impl. is dumb, but works:
-        let seeds = self.seeds();
-        let boundaries = self.boundaries();
+        resolutions.insert(full_name.to_owned(), Resolution::Done(outcome.clone()));
 
-        seeds
-            .into_iter()
-            .map(|url| boundaries.filter_query_params(url))
-            .filter(|url| !boundaries.is_allowed(url) || boundaries.is_frontier(url))
-            .collect::<Vec<_>>()
+        outcome
     }
 
-    /// Finds invalid set variables.
-    fn find_invalid_set_variables(&self) -> HashSet<String> {
-        let mut invalid = HashSet::new();
+    /// Points all references to definitions, in definitions and in rules, to
+    /// their definitions. Each definition is type checked once, here. Returns
+    /// the problems found, with the module where each was found.
+    fn resolve_definitions(&mut self) -> Vec<(String, Position, String)> {
+        let mut index = DefinitionIndex::new();
+        let mut issues = vec![];
 
-        for module in self.modules.values() {
-            module.find_invalid_set_variables(&mut invalid);
+        for (module_name, module) in &self.modules {
+            for (idx, (position, item)) in module.items.iter().enumerate() {
+                if let Item::Define(definition) = item {
+                    let full_name = full_rule_name(module_name, &definition.name);
+
+                    match index.entry(full_name) {
+                        Entry::Occupied(entry) => issues.push((
+                            module_name.clone(),
+                            *position,
+                            format!("duplicated definition `{}`", entry.key()),
+                        )),
+                        Entry::Vacant(entry) => {
+                            entry.insert((module_name.clone(), idx));
+                        }
+                    }
+                }
+            }
         }
 
-        invalid
-    }
-
-    /// Finds duplicate set variables.
-    fn find_duplicate_set_variables(&self) -> HashSet<String> {
-        let mut set_variables = HashSet::new();
-        let mut duplicates = HashSet::new();
+        let mut resolutions = HashMap::new();
+        let full_names = index.keys().cloned().collect::<Vec<_>>();
 
-        for module in self.modules.values() {
-            module.find_duplicate_set_variables(&mut set_variables, &mut duplicates);
+        for full_name in full_names {
+            self.resolve_definition(&full_name, &index, &mut resolutions, &mut issues);
         }
 
-        duplicates
-    }
-
-    /// Validates set-variables types. After this, you can always unwrap errors
-    /// on `SetVariable`.
-    fn find_bad_set_variable_values(&self) -> Vec<super::Error> {
-        let variables = self.set_variables();
-        let tests = vec![
-            variables.get_as_str(Variable::UserAgent).err(),
-            variables.get_as_u64(Variable::Quota).err(),
-            variables.get_as_u64(Variable::MaxDepth).err(),
-            variables.get_as_positive_f64(Variable::MaxHitsPerSec).err(),
-            variables
-                .get_as_positive_f64(Variable::RequestTimeout)
-                .err(),
-            variables.get_as_u64(Variable::MaxBodySize).err(),
-            variables.get_as_bool(Variable::EnablePageRank).err(),
-        ];
-
-        tests
-            .into_iter()
-            .filter_map(|maybe_err| maybe_err)
-            .collect()
-    }
-
-    /// Finds type errors:
-    fn find_type_errors(&self) -> BTreeMap<String, Error> {
-        let mut errors = BTreeMap::new();
+        for (module_name, module) in &mut self.modules {
+            let mut resolve = |name: &str| resolved(module_name, name, &resolutions);
+
+            for (_, item) in &mut module.items {
+                if let Item::RuleSet(rule_set) = item {
+                    let rule_set =
+                        Arc::get_mut(rule_set).expect("rule sets are not shared while loading");
+
+                    if let Some(condition) = &mut rule_set.when {
+                        condition.with_extractor_expr_mut(&mut |extractor_expr| {
+                            extractor_expr
+                                .transformer_expression
+                                .resolve_references(&mut resolve);
+                            extractor_expr.extractor.with_transformer_expression_mut(
+                                &mut |expression| expression.resolve_references(&mut resolve),
+                            );
+                        });
+                    }
 
-        for (name, module) in &self.modules {
-            module.find_type_errors(name.to_owned(), &mut errors);
+                    for rule in rule_set.aggregators.values_mut() {
+                        rule.with_transformer_expression_mut(&mut |expression| {
+                            expression.resolve_references(&mut resolve)
+                        });
+                        rule.with_extractor_mut(|extractor| {
+                            extractor.with_transformer_expression_mut(&mut |expression| {
+                                expression.resolve_references(&mut resolve)
+                            })
+                        });
+                    }
+                }
+            }
         }
 
-        errors
+        issues
     }
 
-    /// Validates if all directives "are sound". Returns an error message if
-    /// any error is found.
-    fn validate(&self) -> Result<(), anyhow::Error> {
-        let mut issues = vec![];
-        let duplicates = self.find_duplicate_rules();
-        if !duplicates.is_empty() {
-            issues.push(format!(
-                "There are duplicated rules in directives: \n    {}",
-                duplicates.into_iter().collect::<Vec<_>>().join("\n    ")
-            ));
-        }
-
-        let invalid_seeds = self.find_invalid_seeds();
-        if !invalid_seeds.is_empty() {
-            issues.push(format!(
-                "There are seeds on the frontier or outside your boundaries: \n    {}",
-                invalid_seeds
-                    .into_iter()
-                    .map(|url| url.as_str().to_owned())
-                    .collect::<Vec<_>>()
-                    .join("\n    ")
-            ));
-        }
+    /// Validates if all directives "are sound". Returns all the problems
+    /// found.
+    fn validate(&self) -> Vec<Diagnostic> {
+        let boundaries = self.boundaries();
+        let mut rule_names = HashSet::new();
+        let mut set_variables = HashSet::new();
+        let mut diagnostics = vec![];
 
-        let invalid = self.find_invalid_set_variables();
-        if !invalid.is_empty() {
-            issues.push(format!(
-                "There are invalid set-variable definitions \
-                (these name are not known): \n    {}",
-                invalid.into_iter().collect::<Vec<_>>().join("\n    "),
-            ));
+        for (name, module) in &self.modules {
+            let mut issues = vec![];
+
+            module.find_duplicate_rules(name, &mut rule_names, &mut issues);
+            module.find_invalid_seeds(&boundaries, &mut issues);
+            module.find_invalid_set_variables(&mut issues);
+            module.find_duplicate_set_variables(&mut set_variables, &mut issues);
+            module.find_bad_set_variable_values(&mut issues);
+            module.find_type_errors(name, &mut issues);
+
+            diagnostics.extend(issues.into_iter().map(|(position, issue)| {
+                Diagnostic::new(&module.path, &module.source, position, issue)
+            }));
         }
 
-        let duplicates = self.find_duplicate_set_variables();
-        if !duplicates.is_empty() {
-            issues.push(format!(
-                "There are duplicate set-variable definitions \
-                (these definitions are global): \n    {}",
-                duplicates.into_iter().collect::<Vec<_>>().join("\n   "),
-            ));
-        }
+        diagnostics
+    }
 
-        let bad_values = self.find_bad_set_variable_values();
-        if !bad_values.is_empty() {
-            issues.push(format!(
-                "There are bad values for set-variables: \n    {}",
-                bad_values
-                    .into_iter()
-                    .map(|err| err.to_string())
-                    .collect::<Vec<_>>()
-                    .join("\n    "),
-            ))
-        }
+    /// Loads directives from a given file while also loading all dependencies.
+    /// If any problems are found in any of the modules, all of them are
+    /// returned as [`Diagnostics`].
+    pub fn load<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        imports: Q,
+    ) -> Result<Self, anyhow::Error> {
+        let (directives, diagnostics) =
+            Directives::load_with_diagnostics(path, imports, &HashMap::new())?;
 
-        let type_errors = self.find_type_errors();
-        if !type_errors.is_empty() {
-            issues.push(format!(
-                "There are type errors for these rules: \n    {}",
-                type_errors
-                    .into_iter()
-                    .map(|(name, err)| format!("{}: {}", name, err))
-                    .collect::<Vec<_>>()
-                    .join("\n    ")
-            ))
+        if !diagnostics.is_empty() {
+            return Err(Diagnostics { diagnostics }.into());
         }
 
-        if !issues.is_empty() {
-            Err(anyhow::anyhow!(
-                "There are issues with your configuration: \n{}",
-                issues.join("\n")
-            ))
-        } else {
-            Ok(())
-        }
+        Ok(directives)
     }
 
-    /// Loads directives from a given file while also loading all dependencies.
-    pub fn load<P: AsRef<Path>, Q: AsRef<Path>>(
+    /// Loads directives like [`Directives::load`], but does not give up on
+    /// problems: returns whatever could be loaded together with all problems
+    /// found. Contents in `overlays` take precedence over files on disk. This
+    /// only fails if the main file cannot be read.
+    pub(crate) fn load_with_diagnostics<P: AsRef<Path>, Q: AsRef<Path>>(
         path: P,
         imports: Q,
-    ) -> Result<Self, anyhow::Error> {
+        overlays: &HashMap<PathBuf, String>,
+    ) -> Result<(Self, Vec<Diagnostic>), anyhow::Error> {
         let parent = path
             .as_ref()
             .parent()
             .ok_or_else(|| anyhow::anyhow!("path cannot be root"))?;
         let mut modules = BTreeMap::new();
+        let mut diagnostics = vec![];
 
         Module::load(
             &[parent, imports.as_ref()],
             "".to_owned(),
             &mut modules,
+            &mut diagnostics,
             &[path.as_ref()],
+            overlays,
         )?;
 
-        let directives = Directives { modules };
+        let mut directives = Directives { modules };
 
-        directives.validate()?;
+        for (module_name, position, issue) in directives.resolve_definitions() {
+            let module = &directives.modules[&module_name];
+            diagnostics.push(Diagnostic::new(
+                &module.path,
+                &module.source,
+                position,
+                issue,
+            ));
+        }
 
-        Ok(directives)
+        diagnostics.extend(directives.validate());
+        diagnostics.sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+
+        Ok((directives, diagnostics))
+    }
+
+    /// What these directives crawl, leaving out how they are written and
+    /// where they were loaded from: neither paths, positions, comments nor
+    /// whitespace make it in here. Neither do tests, which crawl nothing.
+    pub(crate) fn content(&self) -> Value {
+        let modules = self
+            .modules
+            .iter()
+            .map(|(module_name, module)| {
+                let items = module
+                    .items
+                    .iter()
+                    .map(|(_, item)| item)
+                    .filter(|item| !matches!(item, Item::Test(_)))
+                    .collect::<Vec<_>>();
+                (module_name, items)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        serde_json::to_value(modules).expect("can serialize directives")
+    }
+
+    /// The formatted source of every module, each headed by a comment with
+    /// the file it was loaded from. This is empty for directives stored
+    /// before sources were kept.
+    pub fn formatted_source(&self) -> String {
+        if self.modules.values().all(|module| module.source.is_empty()) {
+            return String::new();
+        }
+
+        self.modules
+            .values()
+            .map(|module| {
+                let formatted =
+                    super::format::format(&module.source).unwrap_or_else(|_| module.source.clone());
+                format!("// {}\n{}", module.path.display(), formatted)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Returns the name and the file of each module in these directives.
+    pub(crate) fn module_files(&self) -> Vec<(&str, &Path)> {
+        self.modules
+            .iter()
+            .map(|(module_name, module)| (module_name.as_str(), module.path.as_path()))
+            .collect()
+    }
+
+    /// Finds the file of the module imported as `import` from the module
+    /// `module_name`, if it was loaded.
+    pub(crate) fn resolve_import(&self, module_name: &str, import: &str) -> Option<&Path> {
+        let sub_module_name =
+            canonical_path(&(module_name.to_owned() + SEPARATOR + import)).ok()?;
+        self.modules
+            .get(&sub_module_name)
+            .map(|module| module.path.as_path())
+    }
+
+    /// Finds the file of the module declaring a rule, given the path to the
+    /// rule from the module `module_name`. Also returns the rule name within
+    /// the declaring module.
+    pub(crate) fn find_rule<'a>(
+        &'a self,
+        module_name: &str,
+        rule_path: &str,
+    ) -> Option<(&'a Path, &'a str)> {
+        let full_name = canonical_path(&full_rule_name(module_name, rule_path)).ok()?;
+
+        self.modules.iter().find_map(|(module_name, module)| {
+            module.items.iter().find_map(|(_, item)| {
+                if let Item::RuleSet(rule_set) = item {
+                    rule_set
+                        .aggregators
+                        .keys()
+                        .find(|rule_name| full_rule_name(module_name, rule_name) == full_name)
+                        .map(|rule_name| (module.path.as_path(), rule_name.as_str()))
+                } else {
+                    None
+                }
+            })
+        })
+    }
+
+    /// Returns the module name, the rule name and the inferred type of every
+    /// rule, including the ones that fail to type-check.
+    pub(crate) fn rule_types(&self) -> Vec<(&str, &str, Result<Type, Error>)> {
+        self.modules
+            .iter()
+            .flat_map(|(module_name, module)| {
+                module.items.iter().filter_map(move |(_, item)| {
+                    if let Item::RuleSet(rule_set) = item {
+                        Some((module_name, rule_set))
+                    } else {
+                        None
+                    }
+                })
+            })
+            .flat_map(|(module_name, rule_set)| {
+                rule_set
+                    .aggregators
+                    .iter()
+                    .map(move |(name, rule)| (module_name.as_str(), name.as_str(), rule.type_of()))
+            })
+            .collect()
     }
 
     /// Returns all seeds loaded for this directives.
@@ -398,7 +686,7 @@ impl Directives {
         self.modules
             .values()
             .flat_map(|module| &module.items)
-            .filter_map(|item| {
+            .filter_map(|(_, item)| {
                 if let Item::Seed(seed) = item {
                     Some(seed.clone())
                 } else {
@@ -418,28 +706,52 @@ impl Directives {
 
         self.modules
             .values()
-            .flat_map(|module| &module.items)
-            .filter_map(|item| {
-                if let Item::Boundary(boundary) = item {
-                    Some(boundary)
-                } else {
-                    None
-                }
+            .flat_map(|module| {
+                module.items.iter().filter_map(move |(position, item)| {
+                    if let Item::Boundary(boundary) = item {
+                        Some((module, position, boundary))
+                    } else {
+                        None
+                    }
+                })
             })
-            .for_each(|boundary| match boundary {
-                Boundary::Allowed(allowed_rx) => allowed.push(allowed_rx.as_str()),
-                Boundary::Disallowed(disallowed_rx) => disallowed.push(disallowed_rx.as_str()),
-                Boundary::Frontier(frontier_rx) => frontier.push(frontier_rx.as_str()),
-                Boundary::UseParam(param) => use_params.push(param.to_owned()),
-                Boundary::IgnoreParam(param) => ignore_params.push(param.to_owned()),
-                Boundary::UseAllParams => use_all_params = true,
+            .for_each(|(module, position, boundary)| {
+                let origin = |rule: String| RuleOrigin {
+                    rule,
+                    file: module.path.clone(),
+                    line: position.line(),
+                };
+
+                match boundary {
+                    Boundary::Allowed(allowed_rx) => {
+                        allowed.push((allowed_rx.as_str(), origin(boundary.to_string())))
+                    }
+                    Boundary::Disallowed(disallowed_rx) => {
+                        disallowed.push((disallowed_rx.as_str(), origin(boundary.to_string())))
+                    }
+                    Boundary::Frontier(frontier_rx) => {
+                        frontier.push((frontier_rx.as_str(), origin(boundary.to_string())))
+                    }
+                    Boundary::UseParam(param) => use_params.push(param.to_owned()),
+                    Boundary::IgnoreParam(param) => {
+                        ignore_params.push((param.to_owned(), origin(boundary.to_string())))
+                    }
+                    Boundary::UseAllParams => use_all_params = true,
+                }
             });
 
+        let (allowed, allowed_origins): (Vec<_>, Vec<_>) = allowed.into_iter().unzip();
+        let (disallowed, disallowed_origins): (Vec<_>, Vec<_>) = disallowed.into_iter().unzip();
+        let (frontier, frontier_origins): (Vec<_>, Vec<_>) = frontier.into_iter().unzip();
+
         Boundaries {
             allowed: RegexSet::new(allowed).expect("regex's from set have already bee validated"),
+            allowed_origins,
             disallowed: RegexSet::new(disallowed)
                 .expect("regex's from set have already bee validated"),
+            disallowed_origins,
             frontier: RegexSet::new(frontier).expect("regex's from set have already bee validated"),
+            frontier_origins,
             use_params,
             ignore_params,
             use_all_params,
@@ -451,7 +763,7 @@ impl Directives {
         self.modules
             .iter()
             .flat_map(|(module_name, module)| {
-                module.items.iter().filter_map(move |item| {
+                module.items.iter().filter_map(move |(_, item)| {
                     if let Item::RuleSet(rule_set) = item {
                         Some((module_name, rule_set))
                     } else {
@@ -475,7 +787,7 @@ impl Directives {
             .modules
             .iter()
             .flat_map(|(module_name, module)| {
-                module.items.iter().filter_map(move |item| {
+                module.items.iter().filter_map(move |(_, item)| {
                     if let Item::RuleSet(rule_set) = item {
                         Some((module_name.to_owned(), Arc::clone(rule_set)))
                     } else {
@@ -493,7 +805,7 @@ impl Directives {
             .modules
             .iter()
             .flat_map(|(_module_name, module)| {
-                module.items.iter().filter_map(move |item| {
+                module.items.iter().filter_map(move |(_, item)| {
                     if let Item::SetVariable(set_variable) = item {
                         Some((
                             Variable::try_parse(&set_variable.name)?,
@@ -509,12 +821,29 @@ impl Directives {
         SetVariables { set_variables }
     }
 
+    /// Returns all tests declared in the directives, together with the name
+    /// and the file of the module declaring each one.
+    pub fn tests(&self) -> Vec<(&str, &Path, &DirectiveTest)> {
+        self.modules
+            .iter()
+            .flat_map(|(module_name, module)| {
+                module.items.iter().filter_map(move |(_, item)| {
+                    if let Item::Test(test) = item {
+                        Some((module_name.as_str(), module.path.as_path(), test))
+                    } else {
+                        None
+                    }
+                })
+            })
+            .collect()
+    }
+
     pub fn webdriver_selector(&self) -> WebDriverSelector {
         let rules = self
             .modules
             .iter()
             .flat_map(|(_module_name, module)| {
-                module.items.iter().filter_map(move |item| {
+                module.items.iter().filter_map(move |(_, item)| {
                     if let Item::WebDriver(web_driver) = item {
                         Some(web_driver)
                     } else {
@@ -532,11 +861,17 @@ impl Directives {
 #[derive(Debug)]
 pub struct Boundaries {
     allowed: RegexSet,
+    /// Where each regex in `allowed` was declared (in the same order).
+    allowed_origins: Vec<RuleOrigin>,
     disallowed: RegexSet,
+    /// Where each regex in `disallowed` was declared (in the same order).
+    disallowed_origins: Vec<RuleOrigin>,
     frontier: RegexSet,
+    /// Where each regex in `frontier` was declared (in the same order).
+    frontier_origins: Vec<RuleOrigin>,
     /// TODO: use aho-corasick?
     use_params: Vec<String>,
-    ignore_params: Vec<String>,
+    ignore_params: Vec<(String, RuleOrigin)>,
     use_all_params: bool,
 }
 
@@ -554,10 +889,7 @@ impl Boundaries {
             .query_pairs()
             .filter(|(key, _)| {
                 (self.use_all_params || self.use_params.iter().any(|use_params| use_params == key))
-                    && !self
-                        .ignore_params
-                        .iter()
-                        .any(|ignore_param| ignore_param == key)
+                    && self.ignored_by(key).is_none()
             })
             .map(|(key, value)| (key.into_owned(), value.into_owned()))
             .collect::<Vec<_>>();
@@ -571,6 +903,131 @@ impl Boundaries {
 
         url
     }
+
+    /// Finds the rule ignoring a given query parameter, if any.
+    fn ignored_by(&self, param: &str) -> Option<&RuleOrigin> {
+        self.ignore_params
+            .iter()
+            .find(|(ignore_param, _)| ignore_param == param)
+            .map(|(_, origin)| origin)
+    }
+
+    /// Explains which rules match a URL and which of its query parameters
+    /// get stripped (and why).
+    pub fn explain(&self, url: &Url) -> BoundariesExplanation {
+        let matching = |set: &RegexSet, origins: &[RuleOrigin], url: &str| {
+            set.matches(url)
+                .into_iter()
+                .map(|i| origins[i].clone())
+                .collect::<Vec<_>>()
+        };
+
+        let stripped_params = url
+            .query_pairs()
+            .filter_map(|(key, _)| {
+                let ignored_by = self.ignored_by(&key);
+                let is_used = self.use_all_params
+                    || self.use_params.iter().any(|use_param| use_param == &key);
+
+                if ignored_by.is_some() || !is_used {
+                    Some(StrippedParam {
+                        name: key.into_owned(),
+                        ignored_by: ignored_by.cloned(),
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        // Rules apply to the cleaned URL:
+        let clean_url = self.filter_query_params(url.clone());
+
+        BoundariesExplanation {
+            allowed_by: matching(&self.allowed, &self.allowed_origins, clean_url.as_str()),
+            disallowed_by: matching(
+                &self.disallowed,
+                &self.disallowed_origins,
+                clean_url.as_str(),
+            ),
+            frontier_by: matching(&self.frontier, &self.frontier_origins, clean_url.as_str()),
+            stripped_params,
+        }
+    }
+}
+
+#[test]
+fn load_diagnostics_test() {
+    let err = TestDir::new(&[
+        (
+            "main.lcd",
+            "import \"sub\";\nallow \"(\";\nselect a {\n    x: first(text;\n    y: first(text);\n}\n",
+        ),
+        ("sub.lcd", "seed \"not a url\";\n\n  disallow \"[\";\n"),
+    ])
+    .load()
+    .unwrap_err();
+
+    let diagnostics = err.downcast::<Diagnostics>().unwrap().diagnostics;
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|diagnostic| (
+                diagnostic.file.file_name().unwrap().to_str().unwrap(),
+                diagnostic.line,
+                diagnostic.column
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            ("main.lcd", 2, 1),
+            ("main.lcd", 4, 8),
+            ("sub.lcd", 1, 1),
+            ("sub.lcd", 3, 3)
+        ]
+    );
+}
+
+#[test]
+fn explain_test() {
+    let (directives, dir) = load_test_directives(&[(
+        "main.lcd",
+        "allow \"^https://example\\.foo/\";\n\
+        disallow \"/\\\"quoted\\\"/\";\n\
+        frontier \"\\d+$\";\n\
+        ignore param \"utm_source\";\n",
+    )]);
+
+    let explanation = directives
+        .boundaries()
+        .explain(&Url::parse("https://example.foo/%22quoted%22/1?utm_source=x").unwrap());
+    fn rules(origins: &[RuleOrigin]) -> Vec<(&str, usize)> {
+        origins
+            .iter()
+            .map(|origin| (origin.rule.as_str(), origin.line))
+            .collect()
+    }
+
+    assert_eq!(
+        rules(&explanation.allowed_by),
+        vec![(r#"allow "^https://example\.foo/";"#, 1)]
+    );
+    assert_eq!(explanation.allowed_by[0].file, dir.main());
+    assert_eq!(
+        rules(&explanation.frontier_by),
+        vec![(r#"frontier "\d+$";"#, 3)]
+    );
+    assert_eq!(
+        explanation.stripped_params[0]
+            .ignored_by
+            .as_ref()
+            .map(|origin| (origin.rule.as_str(), origin.line)),
+        Some((r#"ignore param "utm_source";"#, 4))
+    );
+
+    assert_eq!(
+        rules(&directives.boundaries().disallowed_origins),
+        vec![(r#"disallow "/\"quoted\"/";"#, 2)]
+    );
 }
 
 #[derive(Debug)]
@@ -579,7 +1036,8 @@ pub struct Analyzer {
 }
 
 impl Analyzer {
-    pub fn analyze(&self, url: &Url, html: &Html) -> Vec<(String, Value)> {
+    pub fn analyze(&self, page: &Page, html: &Html) -> Vec<(String, Value)> {
+        let url = page.info.url;
         self.rule_sets
             .iter()
             .filter(|(_, rule_set)| {
@@ -589,6 +1047,13 @@ impl Analyzer {
                     true
                 }
             })
+            .filter(|(_, rule_set)| {
+                if let Some(condition) = &rule_set.when {
+                    condition.holds(page, html)
+                } else {
+                    true
+                }
+            })
             .flat_map(|(module_name, rule_set)| {
                 let mut states = rule_set
                     .aggregators
@@ -596,9 +1061,10 @@ impl Analyzer {
                     .map(|(name, agg)| (name, AggregatorExpressionState::new(agg)))
                     .collect::<Vec<_>>();
 
-                for element_ref in html.select(&rule_set.selector) {
+                for element_ref in rule_set.selector.select(html) {
+                    let element = PageElement { element_ref, page };
                     for (_, state) in &mut states {
-                        state.aggregate(element_ref);
+                        state.aggregate(element);
                     }
                 }
 
@@ -606,7 +1072,9 @@ impl Analyzer {
                     (
                         // Top-level directives don't get the dot.
                         full_rule_name(module_name, name),
-                        state.finalize(),
+                        state.finalize(Context {
+                            page_url: Some(url),
+                        }),
                     )
                 })
             })
@@ -2,13 +2,50 @@ use serde_derive::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use smallvec::SmallVec;
 use std::collections::{BTreeMap, HashSet};
-use std::fmt;
+use std::{cmp, fmt};
 
 use super::value_ext::{force_f64, HashableJson};
 
 use super::extractor::ExplodingExtractorExpression;
-use super::transformer::TransformerExpression;
-use super::{Error, Extractable, Type, Typed};
+use super::transformer::{cmp_json, TransformerExpression};
+use super::{Context, Error, Extractable, Type, Typed};
+
+/// The `percentile`-th percentile of some numbers, interpolating linearly
+/// between the closest ranks.
+fn percentile_of(percentile: f64, mut numbers: Vec<f64>) -> Option<f64> {
+    if numbers.is_empty() {
+        return None;
+    }
+
+    numbers.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal));
+
+    let rank = percentile / 100. * (numbers.len() - 1) as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+
+    Some(numbers[below] + (numbers[above] - numbers[below]) * (rank - below as f64))
+}
+
+/// Keeps whichever of the current extreme and the value compares as `wanted`.
+fn keep_extreme(extreme: &mut Option<Value>, value: Value, wanted: cmp::Ordering) {
+    if value.is_null() {
+        return;
+    }
+
+    match extreme {
+        Some(current) if cmp_json(&value, current) != wanted => {}
+        _ => *extreme = Some(value),
+    }
+}
+
+#[test]
+fn percentile_of_test() {
+    assert_eq!(percentile_of(50., vec![]), None);
+    assert_eq!(percentile_of(50., vec![3., 1., 2.]), Some(2.));
+    assert_eq!(percentile_of(50., vec![4., 1., 2., 3.]), Some(2.5));
+    assert_eq!(percentile_of(0., vec![4., 1., 2., 3.]), Some(1.));
+    assert_eq!(percentile_of(100., vec![4., 1., 2., 3.]), Some(4.));
+    assert_eq!(percentile_of(90., vec![7.]), Some(7.));
+}
 
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub enum Aggregator<E: Typed> {
@@ -18,6 +55,18 @@ pub enum Aggregator<E: Typed> {
     Collect(ExplodingExtractorExpression<E>),
     Distinct(ExplodingExtractorExpression<E>),
     Sum(ExplodingExtractorExpression<E>),
+    Min(ExplodingExtractorExpression<E>),
+    Max(ExplodingExtractorExpression<E>),
+    Mean(ExplodingExtractorExpression<E>),
+    Last(ExplodingExtractorExpression<E>),
+    /// The n-th (from zero) value that is not null.
+    Nth(usize, ExplodingExtractorExpression<E>),
+    Any(ExplodingExtractorExpression<E>),
+    All(ExplodingExtractorExpression<E>),
+    /// A percentile, from 0 to 100, interpolated between values.
+    Percentile(f64, ExplodingExtractorExpression<E>),
+    /// How many times each string was seen.
+    CountBy(ExplodingExtractorExpression<E>),
     Group(
         ExplodingExtractorExpression<E>,
         Box<AggregatorExpression<E>>,
@@ -33,6 +82,17 @@ impl<E: Typed> fmt::Display for Aggregator<E> {
             Aggregator::Collect(extractor_expr) => write!(f, "collect({})", extractor_expr),
             Aggregator::Distinct(extractor_expr) => write!(f, "distinct({})", extractor_expr),
             Aggregator::Sum(extractor_expr) => write!(f, "sum({})", extractor_expr),
+            Aggregator::Min(extractor_expr) => write!(f, "min({})", extractor_expr),
+            Aggregator::Max(extractor_expr) => write!(f, "max({})", extractor_expr),
+            Aggregator::Mean(extractor_expr) => write!(f, "mean({})", extractor_expr),
+            Aggregator::Last(extractor_expr) => write!(f, "last({})", extractor_expr),
+            Aggregator::Nth(n, extractor_expr) => write!(f, "nth({}, {})", n, extractor_expr),
+            Aggregator::Any(extractor_expr) => write!(f, "any({})", extractor_expr),
+            Aggregator::All(extractor_expr) => write!(f, "all({})", extractor_expr),
+            Aggregator::Percentile(percentile, extractor_expr) => {
+                write!(f, "percentile({}, {})", percentile, extractor_expr)
+            }
+            Aggregator::CountBy(extractor_expr) => write!(f, "count-by({})", extractor_expr),
             Aggregator::Group(extractor_expr, aggregator_expr) => {
                 write!(f, "group({}, {})", extractor_expr, aggregator_expr)
             }
@@ -63,7 +123,9 @@ impl<E: Typed> Aggregator<E> {
             Aggregator::Distinct(extractor_expr) => {
                 Ok(Type::Array(Box::new(extractor_expr.type_of()?)))
             }
-            Aggregator::Sum(extractor_expr) => {
+            Aggregator::Sum(extractor_expr)
+            | Aggregator::Mean(extractor_expr)
+            | Aggregator::Percentile(_, extractor_expr) => {
                 let typ = extractor_expr.type_of()?;
                 if let Type::Number = extractor_expr.type_of()? {
                     Ok(Type::Number)
@@ -71,6 +133,31 @@ impl<E: Typed> Aggregator<E> {
                     self.type_error(&typ)
                 }
             }
+            Aggregator::Min(extractor_expr) | Aggregator::Max(extractor_expr) => {
+                match extractor_expr.type_of()? {
+                    typ @ (Type::Number | Type::String) => Ok(typ),
+                    typ => self.type_error(&typ),
+                }
+            }
+            Aggregator::Last(extractor_expr) | Aggregator::Nth(_, extractor_expr) => {
+                extractor_expr.type_of()
+            }
+            Aggregator::Any(extractor_expr) | Aggregator::All(extractor_expr) => {
+                let typ = extractor_expr.type_of()?;
+                if let Type::Bool = typ {
+                    Ok(Type::Bool)
+                } else {
+                    self.type_error(&typ)
+                }
+            }
+            Aggregator::CountBy(extractor_expr) => {
+                let typ = extractor_expr.type_of()?;
+                if let Type::String = typ {
+                    Ok(Type::Map(Box::new(Type::Number)))
+                } else {
+                    self.type_error(&typ)
+                }
+            }
             Aggregator::Group(extractor_expr, aggregator_expr) => {
                 let extract_type = extractor_expr.type_of()?;
                 let aggregator_type = aggregator_expr.type_of()?;
@@ -101,7 +188,16 @@ impl<E: Typed> Aggregator<E> {
             Aggregator::First(extractor_expr) => [f(extractor_expr)].into(),
             Aggregator::Collect(extractor_expr) => [f(extractor_expr)].into(),
             Aggregator::Distinct(extractor_expr) => [f(extractor_expr)].into(),
-            Aggregator::Sum(extractor_expr) => [f(extractor_expr)].into(),
+            Aggregator::Sum(extractor_expr)
+            | Aggregator::Min(extractor_expr)
+            | Aggregator::Max(extractor_expr)
+            | Aggregator::Mean(extractor_expr)
+            | Aggregator::Last(extractor_expr)
+            | Aggregator::Nth(_, extractor_expr)
+            | Aggregator::Any(extractor_expr)
+            | Aggregator::All(extractor_expr)
+            | Aggregator::Percentile(_, extractor_expr)
+            | Aggregator::CountBy(extractor_expr) => [f(extractor_expr)].into(),
             Aggregator::Group(extractor_expr, aggregator_expr) => {
                 let mut top: SmallVec<[T; 1]> = [f(extractor_expr)].into();
                 top.extend(aggregator_expr.as_mut().with_extractor_expr_mut(f));
@@ -147,6 +243,39 @@ impl<E: Typed> AggregatorExpression<E> {
     {
         self.aggregator.with_extractor_expr_mut(f)
     }
+
+    /// Calls `f` on every transformer expression in here, except for those
+    /// within the extractors themselves.
+    pub fn with_transformer_expression_mut<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&mut TransformerExpression),
+    {
+        f(&mut self.transformer_expression);
+
+        match &mut self.aggregator {
+            Aggregator::Count => {}
+            Aggregator::CountNotNull(extractor_expr)
+            | Aggregator::First(extractor_expr)
+            | Aggregator::Collect(extractor_expr)
+            | Aggregator::Distinct(extractor_expr)
+            | Aggregator::Sum(extractor_expr)
+            | Aggregator::Min(extractor_expr)
+            | Aggregator::Max(extractor_expr)
+            | Aggregator::Mean(extractor_expr)
+            | Aggregator::Last(extractor_expr)
+            | Aggregator::Nth(_, extractor_expr)
+            | Aggregator::Any(extractor_expr)
+            | Aggregator::All(extractor_expr)
+            | Aggregator::Percentile(_, extractor_expr)
+            | Aggregator::CountBy(extractor_expr) => {
+                f(&mut extractor_expr.extractor_expression.transformer_expression)
+            }
+            Aggregator::Group(extractor_expr, aggregator_expr) => {
+                f(&mut extractor_expr.extractor_expression.transformer_expression);
+                aggregator_expr.with_transformer_expression_mut(f);
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -157,6 +286,17 @@ pub(crate) enum AggregatorState<'a, E: Typed> {
     Collect(&'a ExplodingExtractorExpression<E>, Vec<Value>),
     Distinct(&'a ExplodingExtractorExpression<E>, HashSet<HashableJson>),
     Sum(&'a ExplodingExtractorExpression<E>, f64),
+    Min(&'a ExplodingExtractorExpression<E>, Option<Value>),
+    Max(&'a ExplodingExtractorExpression<E>, Option<Value>),
+    Mean(&'a ExplodingExtractorExpression<E>, f64, usize),
+    Last(&'a ExplodingExtractorExpression<E>, Option<Value>),
+    /// Counts down to the wanted value.
+    Nth(&'a ExplodingExtractorExpression<E>, usize, Option<Value>),
+    Any(&'a ExplodingExtractorExpression<E>, bool),
+    All(&'a ExplodingExtractorExpression<E>, bool),
+    /// Only the numbers are kept, not the whole values.
+    Percentile(&'a ExplodingExtractorExpression<E>, f64, Vec<f64>),
+    CountBy(&'a ExplodingExtractorExpression<E>, BTreeMap<String, usize>),
     Group(
         &'a ExplodingExtractorExpression<E>,
         &'a AggregatorExpression<E>,
@@ -182,6 +322,19 @@ impl<'a, E: Typed> AggregatorState<'a, E> {
                 AggregatorState::Distinct(extractor_expr, HashSet::new())
             }
             Aggregator::Sum(extractor_expr) => AggregatorState::Sum(extractor_expr, 0.),
+            Aggregator::Min(extractor_expr) => AggregatorState::Min(extractor_expr, None),
+            Aggregator::Max(extractor_expr) => AggregatorState::Max(extractor_expr, None),
+            Aggregator::Mean(extractor_expr) => AggregatorState::Mean(extractor_expr, 0., 0),
+            Aggregator::Last(extractor_expr) => AggregatorState::Last(extractor_expr, None),
+            Aggregator::Nth(n, extractor_expr) => AggregatorState::Nth(extractor_expr, *n, None),
+            Aggregator::Any(extractor_expr) => AggregatorState::Any(extractor_expr, false),
+            Aggregator::All(extractor_expr) => AggregatorState::All(extractor_expr, true),
+            Aggregator::Percentile(percentile, extractor_expr) => {
+                AggregatorState::Percentile(extractor_expr, *percentile, vec![])
+            }
+            Aggregator::CountBy(extractor_expr) => {
+                AggregatorState::CountBy(extractor_expr, BTreeMap::new())
+            }
             Aggregator::Group(extractor_expr, aggregator_expr) => {
                 AggregatorState::Group(extractor_expr, aggregator_expr, BTreeMap::new())
             }
@@ -234,6 +387,87 @@ impl<'a, E: Typed> AggregatorState<'a, E> {
                     }
                 }
             }
+            AggregatorState::Min(extractor, min) => {
+                for value in operand.extract_with(extractor) {
+                    keep_extreme(min, value, cmp::Ordering::Less);
+                }
+            }
+            AggregatorState::Max(extractor, max) => {
+                for value in operand.extract_with(extractor) {
+                    keep_extreme(max, value, cmp::Ordering::Greater);
+                }
+            }
+            AggregatorState::Mean(extractor, sum, count) => {
+                for value in operand.extract_with(extractor) {
+                    if let Value::Number(num) = value {
+                        *sum += force_f64(&num);
+                        *count += 1;
+                    } else if !value.is_null() {
+                        self.complain_about(&value)
+                    }
+                }
+            }
+            AggregatorState::Last(extractor, maybe_value) => {
+                for value in operand.extract_with(extractor) {
+                    if !value.is_null() {
+                        *maybe_value = Some(value);
+                    }
+                }
+            }
+            AggregatorState::Nth(extractor, remaining, maybe_value) => {
+                if maybe_value.is_none() {
+                    for value in operand.extract_with(extractor) {
+                        if value.is_null() {
+                            continue;
+                        } else if *remaining == 0 {
+                            *maybe_value = Some(value);
+                            break;
+                        } else {
+                            *remaining -= 1;
+                        }
+                    }
+                }
+            }
+            AggregatorState::Any(extractor, any) => {
+                if !*any {
+                    for value in operand.extract_with(extractor) {
+                        match value {
+                            Value::Bool(true) => *any = true,
+                            Value::Bool(false) | Value::Null => {}
+                            value => self.complain_about(&value),
+                        }
+                    }
+                }
+            }
+            AggregatorState::All(extractor, all) => {
+                if *all {
+                    for value in operand.extract_with(extractor) {
+                        match value {
+                            Value::Bool(true) => {}
+                            Value::Bool(false) | Value::Null => *all = false,
+                            value => self.complain_about(&value),
+                        }
+                    }
+                }
+            }
+            AggregatorState::Percentile(extractor, _, numbers) => {
+                for value in operand.extract_with(extractor) {
+                    if let Value::Number(num) = value {
+                        numbers.push(force_f64(&num));
+                    } else if !value.is_null() {
+                        self.complain_about(&value)
+                    }
+                }
+            }
+            AggregatorState::CountBy(extractor, counts) => {
+                for key in operand.extract_with(extractor) {
+                    if let Value::String(key) = key {
+                        *counts.entry(key).or_default() += 1;
+                    } else if !key.is_null() {
+                        self.complain_about(&key)
+                    }
+                }
+            }
             AggregatorState::Group(extractor_expr, aggregator_expr, groups) => {
                 for key in operand.extract_with(&extractor_expr) {
                     if let Value::String(key) = key {
@@ -249,7 +483,7 @@ impl<'a, E: Typed> AggregatorState<'a, E> {
         }
     }
 
-    pub fn finalize(self) -> Value {
+    pub fn finalize(self, context: Context) -> Value {
         match self {
             AggregatorState::Count(count) => count.into(),
             AggregatorState::CountNotNull(_, count) => count.into(),
@@ -261,9 +495,27 @@ impl<'a, E: Typed> AggregatorState<'a, E> {
                 .collect::<Vec<_>>()
                 .into(),
             AggregatorState::Sum(_, sum) => sum.into(),
+            AggregatorState::Min(_, value)
+            | AggregatorState::Max(_, value)
+            | AggregatorState::Last(_, value)
+            | AggregatorState::Nth(_, _, value) => value.unwrap_or_default(),
+            AggregatorState::Mean(_, _, 0) => Value::Null,
+            AggregatorState::Mean(_, sum, count) => (sum / count as f64).into(),
+            AggregatorState::Any(_, any) => any.into(),
+            AggregatorState::All(_, all) => all.into(),
+            AggregatorState::Percentile(_, percentile, numbers) => {
+                percentile_of(percentile, numbers)
+                    .map(Value::from)
+                    .unwrap_or_default()
+            }
+            AggregatorState::CountBy(_, counts) => counts
+                .into_iter()
+                .map(|(key, count)| (key, count.into()))
+                .collect::<Map<_, _>>()
+                .into(),
             AggregatorState::Group(_, _, groups) => groups
                 .into_iter()
-                .map(|(key, state)| (key, state.finalize()))
+                .map(|(key, state)| (key, state.finalize(context)))
                 .collect::<Map<_, _>>()
                 .into(),
         }
@@ -291,7 +543,8 @@ impl<'a, E: Typed> AggregatorExpressionState<'a, E> {
         self.state.aggregate(operand)
     }
 
-    pub fn finalize(self) -> Value {
-        self.transformer_expression.eval(self.state.finalize())
+    pub fn finalize(self, context: Context) -> Value {
+        self.transformer_expression
+            .eval_in(self.state.finalize(context), context)
     }
 }
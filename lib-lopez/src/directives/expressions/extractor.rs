@@ -48,7 +48,7 @@ where
     fn extract_with(self, extractor_expr: &ExtractorExpression<E>) -> Value {
         extractor_expr
             .transformer_expression
-            .eval(self.extract_with(&extractor_expr.extractor))
+            .eval_in(self.extract_with(&extractor_expr.extractor), self.context())
     }
 }
 
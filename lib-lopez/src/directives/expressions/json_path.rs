@@ -0,0 +1,184 @@
+//! A small subset of JSONPath, to dig into parsed JSON. Supported are the
+//! root `$`, child keys (`.key` or `['key']`), indexes (`[0]`), wildcards
+//! (`.*` or `[*]`) and recursive descent (`..key`).
+
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag},
+    character::complete::digit1,
+    combinator::{all_consuming, map, map_res},
+    multi::many0,
+    sequence::{delimited, preceded, tuple},
+    IResult,
+};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Step {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+impl Step {
+    fn select<'a>(&self, value: &'a Value, selected: &mut Vec<&'a Value>) {
+        match (self, value) {
+            (Step::Key(key), Value::Object(object)) => selected.extend(object.get(key)),
+            (Step::Index(idx), Value::Array(array)) => selected.extend(array.get(*idx)),
+            (Step::Wildcard, Value::Object(object)) => selected.extend(object.values()),
+            (Step::Wildcard, Value::Array(array)) => selected.extend(array),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Segment {
+    Child(Step),
+    Descendant(Step),
+}
+
+fn descendants<'a>(value: &'a Value, all: &mut Vec<&'a Value>) {
+    all.push(value);
+
+    match value {
+        Value::Object(object) => object.values().for_each(|value| descendants(value, all)),
+        Value::Array(array) => array.iter().for_each(|value| descendants(value, all)),
+        _ => {}
+    }
+}
+
+fn dot_step(i: &str) -> IResult<&str, Step> {
+    alt((
+        map(tag("*"), |_| Step::Wildcard),
+        map(is_not(".[]"), |key: &str| Step::Key(key.to_owned())),
+    ))(i)
+}
+
+fn bracket_step(i: &str) -> IResult<&str, Step> {
+    delimited(
+        tag("["),
+        alt((
+            map(tag("*"), |_| Step::Wildcard),
+            map_res(digit1, |idx: &str| idx.parse().map(Step::Index)),
+            map(delimited(tag("'"), is_not("'"), tag("'")), |key: &str| {
+                Step::Key(key.to_owned())
+            }),
+            map(
+                delimited(tag("\""), is_not("\""), tag("\"")),
+                |key: &str| Step::Key(key.to_owned()),
+            ),
+        )),
+        tag("]"),
+    )(i)
+}
+
+fn segment(i: &str) -> IResult<&str, Segment> {
+    alt((
+        map(
+            preceded(tag(".."), alt((bracket_step, dot_step))),
+            Segment::Descendant,
+        ),
+        map(preceded(tag("."), dot_step), Segment::Child),
+        map(bracket_step, Segment::Child),
+    ))(i)
+}
+
+/// A parsed JSONPath.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct JsonPath {
+    original: String,
+    segments: Vec<Segment>,
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.original)
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = String;
+    fn from_str(s: &str) -> Result<JsonPath, String> {
+        let (_, (_, segments)) = all_consuming(tuple((tag("$"), many0(segment))))(s)
+            .map_err(|_| format!("bad JSON path: {}", s))?;
+
+        Ok(JsonPath {
+            original: s.to_owned(),
+            segments,
+        })
+    }
+}
+
+impl JsonPath {
+    /// Whether this path selects at most one value.
+    fn is_definite(&self) -> bool {
+        self.segments
+            .iter()
+            .all(|segment| matches!(segment, Segment::Child(Step::Key(_) | Step::Index(_))))
+    }
+
+    /// Selects the value at this path, or `null` if there is none. Paths with
+    /// wildcards or recursive descent select an array of all matches.
+    pub fn eval(&self, value: &Value) -> Value {
+        let mut selected = vec![value];
+
+        for segment in &self.segments {
+            let mut next = vec![];
+
+            match segment {
+                Segment::Child(step) => {
+                    for value in selected {
+                        step.select(value, &mut next);
+                    }
+                }
+                Segment::Descendant(step) => {
+                    let mut all = vec![];
+
+                    for value in selected {
+                        descendants(value, &mut all);
+                    }
+
+                    for value in all {
+                        step.select(value, &mut next);
+                    }
+                }
+            }
+
+            selected = next;
+        }
+
+        if self.is_definite() {
+            selected
+                .first()
+                .map(|&value| value.clone())
+                .unwrap_or(Value::Null)
+        } else {
+            selected.into_iter().cloned().collect::<Vec<_>>().into()
+        }
+    }
+}
+
+#[test]
+fn json_path_test() {
+    let value = serde_json::json!({
+        "@type": "Product",
+        "name": "Lopez",
+        "offers": [{ "price": 10 }, { "price": "12.5" }],
+        "brand": { "name": "Ratatouille" },
+    });
+
+    let eval = |path: &str| path.parse::<JsonPath>().unwrap().eval(&value);
+
+    assert_eq!(eval("$.name"), "Lopez");
+    assert_eq!(eval("$['@type']"), "Product");
+    assert_eq!(eval("$.offers[1].price"), "12.5");
+    assert_eq!(eval("$.offers[2].price"), Value::Null);
+    assert_eq!(eval("$.offers[*].price"), serde_json::json!([10, "12.5"]));
+    assert_eq!(eval("$..name"), serde_json::json!(["Lopez", "Ratatouille"]));
+    assert!("$.offers[".parse::<JsonPath>().is_err());
+    assert!("offers".parse::<JsonPath>().is_err());
+}
@@ -4,15 +4,19 @@ pub mod parse;
 
 mod aggregator;
 mod extractor;
+mod json_path;
 mod transformer;
 mod value_ext;
 
 pub use aggregator::AggregatorExpressionState;
 pub use aggregator::{Aggregator, AggregatorExpression};
 pub use extractor::{ExplodingExtractorExpression, ExtractorExpression};
-pub use transformer::{ComparableRegex, Transformer, TransformerExpression};
+pub use json_path::JsonPath;
+pub use transformer::{ComparableRegex, Definition, Transformer, TransformerExpression};
+pub use value_ext::force_f64;
 
 use std::fmt;
+use url::Url;
 
 use crate::Type;
 
@@ -28,6 +32,8 @@ pub enum Error {
         thing: String,
         not_expected: Type,
     },
+    Undefined(String),
+    Uninferable(String),
 }
 
 impl fmt::Display for Error {
@@ -49,6 +55,14 @@ impl fmt::Display for Error {
                 thing,
                 not_expected,
             } => write!(f, "type error: not expected {} for {}", not_expected, thing,),
+            Error::Undefined(name) => {
+                write!(f, "`{}` is neither a transformer nor a valid definition", name)
+            }
+            Error::Uninferable(name) => write!(
+                f,
+                "type error: cannot infer the input type of `{}`; declare it, as in `define {}: string = ...;`",
+                name, name
+            ),
         }
     }
 }
@@ -61,7 +75,17 @@ pub trait Typed: fmt::Display + fmt::Debug + PartialEq {
     fn type_of(&self) -> Result<Type, Error>;
 }
 
+/// What transformers get to know about the page they are evaluated in.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Context<'a> {
+    pub page_url: Option<&'a Url>,
+}
+
 pub trait Extractable<E: Typed>: Copy {
     type Output;
     fn extract_with(self, extractor: &E) -> Self::Output;
+
+    fn context(&self) -> Context<'_> {
+        Context::default()
+    }
 }
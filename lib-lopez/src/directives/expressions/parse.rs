@@ -1,14 +1,17 @@
+use chrono::format::{Item, StrftimeItems};
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::digit1,
-    combinator::{map, opt},
-    multi::{many0, separated_list0},
+    bytes::complete::{tag, take_while},
+    character::complete::{digit1, satisfy},
+    combinator::{map, opt, recognize, verify},
+    multi::{many0, separated_list0, separated_list1},
     number::complete::double,
-    sequence::tuple,
+    sequence::{preceded, tuple},
     IResult,
 };
 
+#[cfg(test)]
+use super::super::parse::first_word;
 use super::super::parse_common::*;
 use super::Parseable;
 use super::*;
@@ -39,6 +42,7 @@ pub fn r#type(i: &str) -> IResult<&str, Type> {
             )),
             |(_, _, _, _, r#type, _)| Type::Map(Box::new(r#type)),
         ),
+        map(crate::r#type::object_fields, Type::Object),
     ))(i)
 }
 
@@ -50,14 +54,94 @@ fn type_test() {
     );
 }
 
+/// Names which cannot be given to definitions, since they are taken by
+/// transformers.
+pub const TRANSFORMER_KEYWORDS: &[&str] = &[
+    "is-null",
+    "is-not-null",
+    "hash",
+    "not",
+    "as-number",
+    "add",
+    "mul",
+    "div",
+    "round",
+    "greater-than",
+    "lesser-than",
+    "greater-or-equal",
+    "lesser-or-equal",
+    "between",
+    "equals",
+    "in",
+    "length",
+    "is-empty",
+    "get",
+    "flatten",
+    "each",
+    "filter",
+    "any",
+    "all",
+    "sort",
+    "sort-by",
+    "as-string",
+    "pretty",
+    "lower",
+    "upper",
+    "trim",
+    "split",
+    "join",
+    "starts-with",
+    "substring",
+    "url-decode",
+    "absolute-url",
+    "url-parts",
+    "parse-date",
+    "capture",
+    "all-captures",
+    "matches",
+    "parse-json",
+    "path",
+    "as",
+    "replace",
+];
+
+/// The name of a definition, like `price-of`.
+pub fn definition_name(i: &str) -> IResult<&str, &str> {
+    recognize(tuple((
+        satisfy(|ch| ch.is_alphabetic() || ch == '_'),
+        take_while(|ch: char| ch.is_alphanumeric() || ch == '_' || ch == '-'),
+    )))(i)
+}
+
+/// A reference to a definition, qualified by its module path if it was not
+/// defined in the same module, like `schema-org.price-of`.
+fn definition_reference(i: &str) -> IResult<&str, Result<Transformer, String>> {
+    map(
+        verify(
+            recognize(separated_list1(tag("."), definition_name)),
+            |name: &str| !TRANSFORMER_KEYWORDS.contains(&name),
+        ),
+        |name: &str| Ok(Transformer::Macro(name.into(), None)),
+    )(i)
+}
+
 fn transformer(i: &str) -> IResult<&str, Result<Transformer, String>> {
     // Multiple alts because too many elements in tuple for poor nom...
     alt((
+        definition_reference,
         alt((
             map(tag("is-null"), |_| Ok(Transformer::IsNull)),
             map(tag("is-not-null"), |_| Ok(Transformer::IsNotNull)),
             map(tag("hash"), |_| Ok(Transformer::Hash)),
             map(tag("not"), |_| Ok(Transformer::Not)),
+            map(
+                tuple((tag_whitespace("as-number"), escaped_string)),
+                |(_, locale)| {
+                    super::transformer::decimal_separator(&locale)
+                        .ok_or_else(|| format!("unknown locale `{}`", locale))?;
+                    Ok(Transformer::AsLocalNumber(locale.into_boxed_str()))
+                },
+            ),
             map(tag("as-number"), |_| Ok(Transformer::AsNumber)),
             map(
                 tuple((tag_whitespace("greater-than"), double)),
@@ -148,6 +232,17 @@ fn transformer(i: &str) -> IResult<&str, Result<Transformer, String>> {
                 )),
                 |(_, _, transformer_expression, _)| Ok(Transformer::All(transformer_expression?)),
             ),
+            map(
+                tuple((
+                    tag_whitespace("sort-by"),
+                    tag_whitespace("("),
+                    transformer_expression,
+                    tag(")"),
+                )),
+                |(_, _, transformer_expression, _)| {
+                    Ok(Transformer::SortBy(transformer_expression?))
+                },
+            ),
             map(tag("sort"), |_| Ok(Transformer::Sort)),
         )),
         alt((
@@ -184,6 +279,14 @@ fn transformer(i: &str) -> IResult<&str, Result<Transformer, String>> {
                 tuple((tag_whitespace("matches"), escaped_string)),
                 |(_, regexp)| Ok(Transformer::Matches(ComparableRegex(regex(&regexp)?))),
             ),
+            map(tag("parse-json"), |_| Ok(Transformer::ParseJson)),
+            map(
+                tuple((tag_whitespace("path"), escaped_string)),
+                |(_, path)| Ok(Transformer::Path(path.parse()?)),
+            ),
+            map(tuple((tag_whitespace("as"), r#type)), |(_, typ)| {
+                Ok(Transformer::Cast(typ))
+            }),
             map(
                 tuple((
                     tag_whitespace("replace"),
@@ -199,9 +302,244 @@ fn transformer(i: &str) -> IResult<&str, Result<Transformer, String>> {
                 },
             ),
         )),
+        alt((
+            map(tuple((tag_whitespace("add"), double)), |(_, rhs)| {
+                Ok(Transformer::Add(rhs))
+            }),
+            map(tuple((tag_whitespace("mul"), double)), |(_, rhs)| {
+                Ok(Transformer::Mul(rhs))
+            }),
+            map(tuple((tag_whitespace("div"), double)), |(_, rhs)| {
+                Ok(Transformer::Div(rhs))
+            }),
+            map(
+                tuple((tag("round"), opt(preceded(whitespace, digit1)))),
+                |(_, places)| {
+                    Ok(Transformer::Round(
+                        places
+                            .map(str::parse)
+                            .transpose()
+                            .map_err(|err| format!("{}", err))?
+                            .unwrap_or(0),
+                    ))
+                },
+            ),
+            map(tag("lower"), |_| Ok(Transformer::Lower)),
+            map(tag("upper"), |_| Ok(Transformer::Upper)),
+            map(tag("trim"), |_| Ok(Transformer::Trim)),
+            map(
+                tuple((tag_whitespace("split"), escaped_string)),
+                |(_, separator)| Ok(Transformer::Split(separator.into_boxed_str())),
+            ),
+            map(
+                tuple((tag_whitespace("join"), escaped_string)),
+                |(_, separator)| Ok(Transformer::Join(separator.into_boxed_str())),
+            ),
+            map(
+                tuple((tag_whitespace("starts-with"), escaped_string)),
+                |(_, prefix)| Ok(Transformer::StartsWith(prefix.into_boxed_str())),
+            ),
+            map(
+                tuple((
+                    tag_whitespace("substring"),
+                    digit1,
+                    opt(preceded(whitespace, digit1)),
+                )),
+                |(_, start, length)| {
+                    Ok(Transformer::Substring(
+                        start.parse().map_err(|err| format!("{}", err))?,
+                        length
+                            .map(str::parse)
+                            .transpose()
+                            .map_err(|err| format!("{}", err))?,
+                    ))
+                },
+            ),
+            map(tag("url-decode"), |_| Ok(Transformer::UrlDecode)),
+            map(tag("absolute-url"), |_| Ok(Transformer::AbsoluteUrl)),
+            map(tag("url-parts"), |_| Ok(Transformer::UrlParts)),
+            map(
+                tuple((tag_whitespace("parse-date"), escaped_string)),
+                |(_, format)| {
+                    if StrftimeItems::new(&format).any(|item| item == Item::Error) {
+                        return Err(format!("bad date format `{}`", format));
+                    }
+
+                    Ok(Transformer::ParseDate(format.into_boxed_str()))
+                },
+            ),
+        )),
     ))(i)
 }
 
+#[test]
+fn definition_reference_test() {
+    assert_eq!(
+        transformer_expression("hash-price schema-org.price-of each(pretty) as-number"),
+        Ok((
+            "",
+            Ok(TransformerExpression {
+                transformers: vec![
+                    Transformer::Macro("hash-price".into(), None),
+                    Transformer::Macro("schema-org.price-of".into(), None),
+                    Transformer::Each(TransformerExpression {
+                        transformers: vec![Transformer::Pretty].into_boxed_slice()
+                    }),
+                    Transformer::AsNumber,
+                ]
+                .into_boxed_slice()
+            })
+        ))
+    );
+}
+
+#[test]
+fn new_transformers_test() {
+    let expression = "as-number \"pt-BR\" add -1 mul 2 div 4 round 2 round as-string lower upper \
+        trim split \",\" join \" \" starts-with \"a\" not substring 2 substring 1 3 url-decode \
+        absolute-url url-parts get \"path\" parse-date \"%d/%m/%Y\"";
+    let (rest, parsed) = transformer_expression(expression).unwrap();
+    assert_eq!(rest, "");
+    assert_eq!(parsed.unwrap().to_string(), expression);
+
+    assert!(transformer_expression("as-number \"xx\"")
+        .unwrap()
+        .1
+        .is_err());
+    assert!(transformer_expression("parse-date \"%Q\"")
+        .unwrap()
+        .1
+        .is_err());
+}
+
+#[test]
+fn transformer_keywords_test() {
+    let samples = [
+        "is-null",
+        "is-not-null",
+        "hash",
+        "not",
+        "as-number",
+        "as-number \"pt-BR\"",
+        "add 1.5",
+        "mul 2",
+        "div 4",
+        "round",
+        "round 2",
+        "greater-than 1",
+        "lesser-than 1",
+        "greater-or-equal 1",
+        "lesser-or-equal 1",
+        "between 1 and 2",
+        "equals 1",
+        "equals \"a\"",
+        "in [1, 2.5]",
+        "in [\"a\", \"b\"]",
+        "length",
+        "is-empty",
+        "get \"a\"",
+        "get 0",
+        "flatten",
+        "each(pretty)",
+        "filter(is-null)",
+        "any(is-null)",
+        "all(is-null)",
+        "sort",
+        "sort-by(length)",
+        "as-string",
+        "pretty",
+        "lower",
+        "upper",
+        "trim",
+        "split \",\"",
+        "join \" \"",
+        "starts-with \"a\"",
+        "substring 1",
+        "substring 1 3",
+        "url-decode",
+        "absolute-url",
+        "url-parts",
+        "parse-date \"%d/%m/%Y\"",
+        "capture \"(a)\"",
+        "all-captures \"(a)\"",
+        "matches \"a\"",
+        "parse-json",
+        "path \"$.a\"",
+        "as array[number]",
+        "replace \"a\" with \"b\"",
+        "price-of",
+    ];
+
+    for sample in samples {
+        let (rest, transformer) = transformer(sample).unwrap();
+        assert_eq!(rest, "", "{sample}");
+        let transformer = transformer.unwrap();
+        assert_eq!(transformer.to_string(), sample);
+
+        // Fails to compile for new transformers, to have them added above.
+        match transformer {
+            Transformer::IsNull
+            | Transformer::IsNotNull
+            | Transformer::Hash
+            | Transformer::Not
+            | Transformer::AsNumber
+            | Transformer::AsLocalNumber(_)
+            | Transformer::Add(_)
+            | Transformer::Mul(_)
+            | Transformer::Div(_)
+            | Transformer::Round(_)
+            | Transformer::GreaterThan(_)
+            | Transformer::LesserThan(_)
+            | Transformer::GreaterOrEqual(_)
+            | Transformer::LesserOrEqual(_)
+            | Transformer::Between(_, _)
+            | Transformer::Equals(_)
+            | Transformer::In(_)
+            | Transformer::Length
+            | Transformer::IsEmpty
+            | Transformer::Get(_)
+            | Transformer::GetIdx(_)
+            | Transformer::Flatten
+            | Transformer::Each(_)
+            | Transformer::Filter(_)
+            | Transformer::Any(_)
+            | Transformer::All(_)
+            | Transformer::Sort
+            | Transformer::SortBy(_)
+            | Transformer::AsString
+            | Transformer::Pretty
+            | Transformer::Lower
+            | Transformer::Upper
+            | Transformer::Trim
+            | Transformer::Split(_)
+            | Transformer::Join(_)
+            | Transformer::StartsWith(_)
+            | Transformer::Substring(_, _)
+            | Transformer::UrlDecode
+            | Transformer::AbsoluteUrl
+            | Transformer::UrlParts
+            | Transformer::ParseDate(_)
+            | Transformer::EqualsString(_)
+            | Transformer::InStrings(_)
+            | Transformer::Capture(_)
+            | Transformer::AllCaptures(_)
+            | Transformer::Matches(_)
+            | Transformer::Replace(_, _)
+            | Transformer::ParseJson
+            | Transformer::Path(_)
+            | Transformer::Cast(_)
+            | Transformer::Macro(_, _) => {}
+        }
+    }
+
+    let mut keywords = samples[..samples.len() - 1]
+        .iter()
+        .map(|sample| first_word(sample))
+        .collect::<Vec<_>>();
+    keywords.dedup();
+    assert_eq!(keywords, TRANSFORMER_KEYWORDS);
+}
+
 #[test]
 fn transformer_test() {
     use regex::Regex;
@@ -221,7 +559,7 @@ fn transformer_test() {
     }
 }
 
-fn transformer_expression(i: &str) -> IResult<&str, Result<TransformerExpression, String>> {
+pub fn transformer_expression(i: &str) -> IResult<&str, Result<TransformerExpression, String>> {
     map(many0(trailing_whitespace(transformer)), |transformers| {
         Ok(TransformerExpression {
             transformers: {
@@ -264,8 +602,46 @@ pub fn exploding_extractor_expression<P: Parseable + Typed>(
     )(i)
 }
 
+/// Aggregators that take a single extractor, as in `name(extractor)`.
+fn simple_aggregator<P: Parseable + Typed>(
+    name: &'static str,
+    aggregator: fn(ExplodingExtractorExpression<P>) -> Aggregator<P>,
+) -> impl Fn(&str) -> IResult<&str, Result<Aggregator<P>, String>> {
+    move |i| {
+        map(
+            tuple((
+                tag_whitespace(name),
+                tag_whitespace("("),
+                trailing_whitespace(exploding_extractor_expression::<P>),
+                tag(")"),
+            )),
+            |(_, _, extractor, _)| Ok(aggregator(extractor?)),
+        )(i)
+    }
+}
+
+/// The keywords starting each aggregator.
+pub const AGGREGATOR_KEYWORDS: &[&str] = &[
+    "count-by",
+    "count",
+    "first",
+    "collect",
+    "distinct",
+    "sum",
+    "min",
+    "max",
+    "mean",
+    "last",
+    "any",
+    "all",
+    "nth",
+    "percentile",
+    "group",
+];
+
 pub fn aggregator<P: Parseable + Typed>(i: &str) -> IResult<&str, Result<Aggregator<P>, String>> {
     alt((
+        simple_aggregator("count-by", Aggregator::CountBy),
         map(
             tuple((
                 tag_whitespace("count"),
@@ -312,6 +688,48 @@ pub fn aggregator<P: Parseable + Typed>(i: &str) -> IResult<&str, Result<Aggrega
             )),
             |(_, _, extractor, _)| Ok(Aggregator::Sum(extractor?)),
         ),
+        simple_aggregator("min", Aggregator::Min),
+        simple_aggregator("max", Aggregator::Max),
+        simple_aggregator("mean", Aggregator::Mean),
+        simple_aggregator("last", Aggregator::Last),
+        simple_aggregator("any", Aggregator::Any),
+        simple_aggregator("all", Aggregator::All),
+        map(
+            tuple((
+                tag_whitespace("nth"),
+                tag_whitespace("("),
+                trailing_whitespace(digit1),
+                tag_whitespace(","),
+                trailing_whitespace(exploding_extractor_expression::<P>),
+                tag(")"),
+            )),
+            |(_, _, n, _, extractor, _)| {
+                Ok(Aggregator::Nth(
+                    n.parse().map_err(|err| format!("{}", err))?,
+                    extractor?,
+                ))
+            },
+        ),
+        map(
+            tuple((
+                tag_whitespace("percentile"),
+                tag_whitespace("("),
+                trailing_whitespace(double),
+                tag_whitespace(","),
+                trailing_whitespace(exploding_extractor_expression::<P>),
+                tag(")"),
+            )),
+            |(_, _, percentile, _, extractor, _)| {
+                if !(0. ..=100.).contains(&percentile) {
+                    return Err(format!(
+                        "percentile must be between 0 and 100, got {}",
+                        percentile
+                    ));
+                }
+
+                Ok(Aggregator::Percentile(percentile, extractor?))
+            },
+        ),
         map(
             tuple((
                 tag_whitespace("group"),
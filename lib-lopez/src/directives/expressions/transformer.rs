@@ -1,10 +1,15 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use percent_encoding::percent_decode_str;
 use regex::{Captures, Regex};
 use serde_derive::{Deserialize, Serialize};
 use serde_json::{Map, Value};
+use std::sync::Arc;
 use std::{cmp, fmt};
+use url::Url;
 
+use super::super::parse_common::quote;
 use super::value_ext::force_f64;
-use super::{Error, Type};
+use super::{Context, Error, JsonPath, Type};
 
 /// Puts captures into a nice JSON.
 fn capture_json(regex: &Regex, captures: Captures) -> Map<String, Value> {
@@ -79,6 +84,162 @@ fn pretty_test() {
     assert_eq!("a\nc\n", pretty(ugly));
 }
 
+/// Languages writing decimals with a comma, like `1.234,56`.
+const COMMA_DECIMAL_LANGUAGES: &[&str] = &[
+    "af", "bg", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fr", "gl", "hr", "hu", "id",
+    "is", "it", "lt", "lv", "nb", "nl", "nn", "no", "pl", "pt", "ro", "ru", "sk", "sl", "sr", "sv",
+    "tr", "uk", "vi",
+];
+
+/// Languages writing decimals with a dot, like `1,234.56`.
+const DOT_DECIMAL_LANGUAGES: &[&str] = &[
+    "en", "ga", "he", "hi", "ja", "ko", "ms", "mt", "th", "tl", "zh",
+];
+
+/// The decimal separator of a locale, like `pt-BR`, if the locale is known.
+pub(crate) fn decimal_separator(locale: &str) -> Option<char> {
+    let language = locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    if COMMA_DECIMAL_LANGUAGES.contains(&language.as_str()) {
+        Some(',')
+    } else if DOT_DECIMAL_LANGUAGES.contains(&language.as_str()) {
+        Some('.')
+    } else {
+        None
+    }
+}
+
+/// Parses a number written with the given decimal separator. Anything else
+/// that people use to group digits is ignored.
+fn parse_local_number(number: &str, decimal: char) -> Option<f64> {
+    let normalized = number
+        .trim()
+        .chars()
+        .filter(|&ch| {
+            ch == decimal || !matches!(ch, '.' | ',' | '\'' | ' ' | '\u{a0}' | '\u{202f}')
+        })
+        .map(|ch| if ch == decimal { '.' } else { ch })
+        .collect::<String>();
+
+    normalized.parse().ok()
+}
+
+#[test]
+fn parse_local_number_test() {
+    assert_eq!(parse_local_number("1.234,56", ','), Some(1234.56));
+    assert_eq!(parse_local_number(" 1 234,5 ", ','), Some(1234.5));
+    assert_eq!(parse_local_number("1,234.56", '.'), Some(1234.56));
+    assert_eq!(parse_local_number("-12", '.'), Some(-12.0));
+    assert_eq!(parse_local_number("1,2,3", ','), None);
+    assert_eq!(parse_local_number("R$ 12", ','), None);
+}
+
+/// Parses a date (and maybe a time) in the given `strftime` format into an
+/// ISO 8601 string.
+fn parse_date(date: &str, format: &str) -> Option<String> {
+    let date = date.trim();
+
+    if let Ok(date_time) = DateTime::parse_from_str(date, format) {
+        Some(date_time.to_rfc3339())
+    } else if let Ok(date_time) = NaiveDateTime::parse_from_str(date, format) {
+        Some(date_time.format("%Y-%m-%dT%H:%M:%S").to_string())
+    } else if let Ok(date) = NaiveDate::parse_from_str(date, format) {
+        Some(date.format("%Y-%m-%d").to_string())
+    } else {
+        None
+    }
+}
+
+#[test]
+fn parse_date_test() {
+    assert_eq!(
+        parse_date("31/01/2022", "%d/%m/%Y"),
+        Some("2022-01-31".to_owned())
+    );
+    assert_eq!(
+        parse_date("31/01/2022 13:45", "%d/%m/%Y %H:%M"),
+        Some("2022-01-31T13:45:00".to_owned())
+    );
+    assert_eq!(
+        parse_date("2022-01-31 13:45 -0300", "%Y-%m-%d %H:%M %z"),
+        Some("2022-01-31T13:45:00-03:00".to_owned())
+    );
+    assert_eq!(parse_date("31/02/2022", "%d/%m/%Y"), None);
+}
+
+/// Resolves a link against the page it was found in. Outside of a page,
+/// only absolute URLs resolve.
+fn resolve_url(link: &str, context: Context) -> Option<Url> {
+    let link = link.trim();
+
+    if let Some(page_url) = context.page_url {
+        page_url.join(link).ok()
+    } else {
+        Url::parse(link).ok()
+    }
+}
+
+/// The host, path and query (as a map, where the first of repeated keys
+/// wins) of an URL.
+fn url_parts(url: &Url) -> Map<String, Value> {
+    let mut query = Map::new();
+    for (key, value) in url.query_pairs() {
+        query
+            .entry(key.into_owned())
+            .or_insert_with(|| value.into_owned().into());
+    }
+
+    let mut parts = Map::new();
+    parts.insert(
+        "host".to_owned(),
+        url.host_str().map(Value::from).unwrap_or(Value::Null),
+    );
+    parts.insert("path".to_owned(), url.path().into());
+    parts.insert("query".to_owned(), query.into());
+
+    parts
+}
+
+fn url_parts_type() -> Type {
+    Type::Object(
+        vec![
+            ("host".to_owned(), Type::String),
+            ("path".to_owned(), Type::String),
+            ("query".to_owned(), Type::Map(Box::new(Type::String))),
+        ]
+        .into_iter()
+        .collect(),
+    )
+}
+
+#[test]
+fn resolve_url_test() {
+    let page_url = Url::parse("https://example.foo/a/b?c=d").unwrap();
+    let context = Context {
+        page_url: Some(&page_url),
+    };
+
+    assert_eq!(
+        resolve_url(" ../x?y=1 ", context).map(String::from),
+        Some("https://example.foo/x?y=1".to_owned())
+    );
+    assert_eq!(
+        resolve_url("//other.foo/", context).map(String::from),
+        Some("https://other.foo/".to_owned())
+    );
+    assert_eq!(resolve_url("/x", Context::default()), None);
+    assert_eq!(
+        Value::from(url_parts(
+            &Url::parse("https://example.foo/p?q=1&q=2&r").unwrap()
+        )),
+        serde_json::json!({"host": "example.foo", "path": "/p", "query": {"q": "1", "r": ""}})
+    );
+}
+
 /// Need this to shoehorn regex equality. Note: this is utterly broken in a
 /// context wider than unittesting.
 #[derive(Debug, Deserialize, Serialize)]
@@ -90,7 +251,7 @@ impl PartialEq for ComparableRegex {
     }
 }
 
-fn cmp_json(this: &Value, other: &Value) -> cmp::Ordering {
+pub(super) fn cmp_json(this: &Value, other: &Value) -> cmp::Ordering {
     match (this, other) {
         (Value::Null, Value::Null) => cmp::Ordering::Equal,
         (Value::Null, _) => cmp::Ordering::Less,
@@ -125,6 +286,13 @@ pub enum Transformer {
 
     // Numeric:
     AsNumber,
+    /// Parses a number the way it is written in a locale, like `pt-BR`.
+    AsLocalNumber(Box<str>),
+    Add(f64),
+    Mul(f64),
+    Div(f64),
+    /// Rounds to a number of decimal places.
+    Round(u32),
     GreaterThan(f64),
     LesserThan(f64),
     GreaterOrEqual(f64), // missing docs!
@@ -149,6 +317,21 @@ pub enum Transformer {
     // String manipulation
     AsString,
     Pretty,
+    Lower,
+    Upper,
+    Trim,
+    Split(Box<str>),
+    Join(Box<str>),
+    StartsWith(Box<str>),
+    /// Takes characters from a start, up to a length if given.
+    Substring(usize, Option<usize>),
+    UrlDecode,
+    /// Resolves a (maybe relative) link against the page URL.
+    AbsoluteUrl,
+    /// Breaks a (maybe relative) link into its host, path and query.
+    UrlParts,
+    /// Parses a date with a `strftime` format into an ISO 8601 string.
+    ParseDate(Box<str>),
     EqualsString(Box<str>),     // missing docs!
     InStrings(Box<[Box<str>]>), // missing docs!
 
@@ -157,6 +340,16 @@ pub enum Transformer {
     AllCaptures(ComparableRegex),
     Matches(ComparableRegex),
     Replace(ComparableRegex, Box<str>),
+
+    // JSON:
+    ParseJson,
+    Path(JsonPath),
+    Cast(Type),
+
+    // Definitions:
+    /// A reference to a `define`d expression, by the name it was referred
+    /// to. Definitions are resolved once all modules are loaded.
+    Macro(Box<str>, Option<Arc<Definition>>),
 }
 
 impl fmt::Display for Transformer {
@@ -167,6 +360,12 @@ impl fmt::Display for Transformer {
             Transformer::Hash => write!(f, "hash"),
             Transformer::Not => write!(f, "not"),
             Transformer::AsNumber => write!(f, "as-number"),
+            Transformer::AsLocalNumber(locale) => write!(f, "as-number {}", quote(locale)),
+            Transformer::Add(num) => write!(f, "add {}", num),
+            Transformer::Mul(num) => write!(f, "mul {}", num),
+            Transformer::Div(num) => write!(f, "div {}", num),
+            Transformer::Round(0) => write!(f, "round"),
+            Transformer::Round(places) => write!(f, "round {}", places),
             Transformer::GreaterThan(num) => write!(f, "greater-than {}", num),
             Transformer::LesserThan(num) => write!(f, "lesser-than {}", num),
             Transformer::GreaterOrEqual(num) => write!(f, "greater-or-equal {}", num),
@@ -183,7 +382,7 @@ impl fmt::Display for Transformer {
             ),
             Transformer::Length => write!(f, "length"),
             Transformer::IsEmpty => write!(f, "is-empty"),
-            Transformer::Get(key) => write!(f, "get {:?}", key),
+            Transformer::Get(key) => write!(f, "get {}", quote(key)),
             Transformer::GetIdx(idx) => write!(f, "get {}", idx),
             Transformer::Flatten => write!(f, "flatten"),
             Transformer::Each(transformer) => write!(f, "each({})", transformer),
@@ -193,21 +392,52 @@ impl fmt::Display for Transformer {
             Transformer::Sort => write!(f, "sort"),
             Transformer::SortBy(transformer) => write!(f, "sort-by({})", transformer),
             Transformer::Capture(ComparableRegex(regex)) => {
-                write!(f, "capture {:?}", regex.as_str())
+                write!(f, "capture {}", quote(regex.as_str()))
             }
             Transformer::AsString => write!(f, "as-string"),
             Transformer::Pretty => write!(f, "pretty"),
-            Transformer::EqualsString(string) => write!(f, "equals {:?}", string),
-            Transformer::InStrings(strings) => write!(f, "in {:?}", strings),
+            Transformer::Lower => write!(f, "lower"),
+            Transformer::Upper => write!(f, "upper"),
+            Transformer::Trim => write!(f, "trim"),
+            Transformer::Split(separator) => write!(f, "split {}", quote(separator)),
+            Transformer::Join(separator) => write!(f, "join {}", quote(separator)),
+            Transformer::StartsWith(prefix) => write!(f, "starts-with {}", quote(prefix)),
+            Transformer::Substring(start, None) => write!(f, "substring {}", start),
+            Transformer::Substring(start, Some(length)) => {
+                write!(f, "substring {} {}", start, length)
+            }
+            Transformer::UrlDecode => write!(f, "url-decode"),
+            Transformer::AbsoluteUrl => write!(f, "absolute-url"),
+            Transformer::UrlParts => write!(f, "url-parts"),
+            Transformer::ParseDate(format) => write!(f, "parse-date {}", quote(format)),
+            Transformer::EqualsString(string) => write!(f, "equals {}", quote(string)),
+            Transformer::InStrings(strings) => write!(
+                f,
+                "in [{}]",
+                strings
+                    .iter()
+                    .map(|string| quote(string))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             Transformer::AllCaptures(ComparableRegex(regex)) => {
-                write!(f, "all-captures {:?}", regex.as_str())
+                write!(f, "all-captures {}", quote(regex.as_str()))
             }
             Transformer::Matches(ComparableRegex(regex)) => {
-                write!(f, "matches {:?}", regex.as_str())
+                write!(f, "matches {}", quote(regex.as_str()))
             }
             Transformer::Replace(ComparableRegex(regex), replacer) => {
-                write!(f, "replace {:?} with {:?}", regex, replacer)
+                write!(
+                    f,
+                    "replace {} with {}",
+                    quote(regex.as_str()),
+                    quote(replacer)
+                )
             }
+            Transformer::ParseJson => write!(f, "parse-json"),
+            Transformer::Path(path) => write!(f, "path {}", quote(&path.to_string())),
+            Transformer::Cast(typ) => write!(f, "as {}", typ),
+            Transformer::Macro(name, _) => write!(f, "{}", name),
         }
     }
 }
@@ -224,6 +454,12 @@ impl Transformer {
             (Transformer::Hash, Type::String) => Ok(Type::Number),
             (Transformer::Not, Type::Bool) => Ok(Type::Bool),
             (Transformer::AsNumber, Type::String) => Ok(Type::Number),
+            (Transformer::AsNumber, Type::Any) => Ok(Type::Number),
+            (Transformer::AsLocalNumber(_), Type::String | Type::Any) => Ok(Type::Number),
+            (Transformer::Add(_), Type::Number) => Ok(Type::Number),
+            (Transformer::Mul(_), Type::Number) => Ok(Type::Number),
+            (Transformer::Div(_), Type::Number) => Ok(Type::Number),
+            (Transformer::Round(_), Type::Number) => Ok(Type::Number),
             (Transformer::GreaterThan(_), Type::Number) => Ok(Type::Bool),
             (Transformer::LesserThan(_), Type::Number) => Ok(Type::Bool),
             (Transformer::GreaterOrEqual(_), Type::Number) => Ok(Type::Bool),
@@ -234,11 +470,19 @@ impl Transformer {
             (Transformer::Length, Type::String) => Ok(Type::Number),
             (Transformer::Length, Type::Array(_)) => Ok(Type::Number),
             (Transformer::Length, Type::Map(_)) => Ok(Type::Number),
+            (Transformer::Length, Type::Object(_)) => Ok(Type::Number),
             (Transformer::IsEmpty, Type::String) => Ok(Type::Bool),
             (Transformer::IsEmpty, Type::Array(_)) => Ok(Type::Bool),
             (Transformer::IsEmpty, Type::Map(_)) => Ok(Type::Bool),
+            (Transformer::IsEmpty, Type::Object(_)) => Ok(Type::Bool),
             (Transformer::Get(_), Type::Map(typ)) => Ok(Type::clone(&*typ)),
             (Transformer::GetIdx(_), Type::Array(typ)) => Ok(Type::clone(&*typ)),
+            (Transformer::Get(_), Type::Any) => Ok(Type::Any),
+            (Transformer::Get(key), Type::Object(fields)) => match fields.get(key.as_ref()) {
+                Some(typ) => Ok(typ.clone()),
+                None => self.type_error(input),
+            },
+            (Transformer::GetIdx(_), Type::Any) => Ok(Type::Any),
             (Transformer::Flatten, Type::Array(inner)) => {
                 if let Type::Array(_) = &**inner {
                     Ok(Type::clone(&*inner))
@@ -284,10 +528,12 @@ impl Transformer {
                     predicate.expected(&Type::Bool, &predicate_typ)
                 }
             }
-            (Transformer::Sort, Type::Array(typ)) if !typ.is_map() => Ok(Type::Array(typ.clone())),
+            (Transformer::Sort, Type::Array(typ)) if !typ.is_map() && !typ.is_object() => {
+                Ok(Type::Array(typ.clone()))
+            }
             (Transformer::SortBy(key), Type::Array(typ)) => {
                 let key_typ = key.type_for(typ)?;
-                if !key_typ.is_map() {
+                if !key_typ.is_map() && !key_typ.is_object() {
                     Ok(Type::Array(typ.clone()))
                 } else {
                     key.not_expected(&key_typ)
@@ -296,7 +542,19 @@ impl Transformer {
             (Transformer::AsString, Type::Number) => Ok(Type::String),
             (Transformer::AsString, Type::Bool) => Ok(Type::String),
             (Transformer::AsString, Type::String) => Ok(Type::String),
+            (Transformer::AsString, Type::Any) => Ok(Type::String),
             (Transformer::Pretty, Type::String) => Ok(Type::String),
+            (Transformer::Lower, Type::String) => Ok(Type::String),
+            (Transformer::Upper, Type::String) => Ok(Type::String),
+            (Transformer::Trim, Type::String) => Ok(Type::String),
+            (Transformer::Split(_), Type::String) => Ok(Type::Array(Box::new(Type::String))),
+            (Transformer::Join(_), Type::Array(typ)) if **typ == Type::String => Ok(Type::String),
+            (Transformer::StartsWith(_), Type::String) => Ok(Type::Bool),
+            (Transformer::Substring(_, _), Type::String) => Ok(Type::String),
+            (Transformer::UrlDecode, Type::String) => Ok(Type::String),
+            (Transformer::AbsoluteUrl, Type::String) => Ok(Type::String),
+            (Transformer::UrlParts, Type::String) => Ok(url_parts_type()),
+            (Transformer::ParseDate(_), Type::String) => Ok(Type::String),
             (Transformer::EqualsString(_), Type::String) => Ok(Type::Bool),
             (Transformer::InStrings(_), Type::String) => Ok(Type::Bool),
             (Transformer::Capture(_), Type::String) => Ok(Type::Map(Box::new(Type::String))),
@@ -305,6 +563,20 @@ impl Transformer {
             }
             (Transformer::Matches(_), Type::String) => Ok(Type::Bool),
             (Transformer::Replace(_, _), Type::String) => Ok(Type::String),
+            (Transformer::ParseJson, Type::String) => Ok(Type::Any),
+            (Transformer::Path(_), Type::Any) => Ok(Type::Any),
+            (Transformer::Cast(typ), Type::Any) => Ok(typ.clone()),
+            (Transformer::Macro(name, None), _) => Err(Error::Undefined(name.to_string())),
+            (Transformer::Macro(name, Some(definition)), input) => match &definition.signature {
+                Some((typ, output)) if typ == input => Ok(output.clone()),
+                Some((typ, _)) if definition.input.is_some() => Err(Error::ExpectedType {
+                    thing: name.to_string(),
+                    expected: typ.clone(),
+                    got: input.clone(),
+                }),
+                Some(_) => definition.expression.type_for(input),
+                None => Err(Error::Undefined(name.to_string())),
+            },
             (_, _) => self.type_error(input),
         }
     }
@@ -314,8 +586,13 @@ impl Transformer {
         panic!("type checked: {:?} {:?}", self, value)
     }
 
-    #[inline(always)]
+    /// Evaluates this transformer outside of any page.
     pub fn eval(&self, input: Value) -> Value {
+        self.eval_in(input, Context::default())
+    }
+
+    #[inline(always)]
+    pub fn eval_in(&self, input: Value, context: Context) -> Value {
         match (self, input) {
             (Transformer::IsNull, Value::Null) => true.into(),
             (Transformer::IsNull, _) => false.into(),
@@ -328,6 +605,29 @@ impl Transformer {
                 .ok()
                 .map(|num| num.into())
                 .unwrap_or(Value::Null),
+            (Transformer::AsNumber, Value::Number(num)) => num.into(),
+            (Transformer::AsLocalNumber(locale), Value::String(string)) => {
+                decimal_separator(locale)
+                    .and_then(|decimal| parse_local_number(&string, decimal))
+                    .map(|num| num.into())
+                    .unwrap_or(Value::Null)
+            }
+            (&Transformer::Add(rhs), Value::Number(lhs)) => (force_f64(&lhs) + rhs).into(),
+            (&Transformer::Mul(rhs), Value::Number(lhs)) => (force_f64(&lhs) * rhs).into(),
+            // Division by zero gives no number, hence `null`.
+            (&Transformer::Div(rhs), Value::Number(lhs)) => (force_f64(&lhs) / rhs).into(),
+            (&Transformer::Round(0), Value::Number(num)) => {
+                let rounded = force_f64(&num).round();
+                if rounded.abs() < i64::MAX as f64 {
+                    (rounded as i64).into()
+                } else {
+                    rounded.into()
+                }
+            }
+            (&Transformer::Round(places), Value::Number(num)) => {
+                let scale = 10f64.powi(places as i32);
+                ((force_f64(&num) * scale).round() / scale).into()
+            }
             (&Transformer::GreaterThan(rhs), Value::Number(lhs)) => (force_f64(&lhs) > rhs).into(),
             (&Transformer::LesserThan(rhs), Value::Number(lhs)) => (force_f64(&lhs) < rhs).into(),
             (&Transformer::GreaterOrEqual(rhs), Value::Number(lhs)) => {
@@ -373,17 +673,17 @@ impl Transformer {
             }
             (&Transformer::Each(ref inner), Value::Array(array)) => array
                 .into_iter()
-                .map(|value| inner.eval(value))
+                .map(|value| inner.eval_in(value, context))
                 .collect::<Vec<_>>()
                 .into(),
             (&Transformer::Each(ref inner), Value::Object(map)) => map
                 .into_iter()
-                .map(|(key, value)| (key, inner.eval(value)))
+                .map(|(key, value)| (key, inner.eval_in(value, context)))
                 .collect::<Map<String, Value>>()
                 .into(),
             (Transformer::Filter(inner), Value::Array(array)) => array
                 .into_iter()
-                .filter_map(|value| match inner.eval(value.clone()) {
+                .filter_map(|value| match inner.eval_in(value.clone(), context) {
                     Value::Null | Value::Bool(false) => None,
                     Value::Bool(true) => Some(value),
                     value => self.complain_about(&value),
@@ -392,7 +692,7 @@ impl Transformer {
                 .into(),
             (Transformer::Filter(inner), Value::Object(map)) => map
                 .into_iter()
-                .filter_map(|(key, value)| match inner.eval(value.clone()) {
+                .filter_map(|(key, value)| match inner.eval_in(value.clone(), context) {
                     Value::Null | Value::Bool(false) => None,
                     Value::Bool(true) => Some((key, value)),
                     value => self.complain_about(&value),
@@ -401,7 +701,7 @@ impl Transformer {
                 .into(),
             (Transformer::Any(predicate), Value::Array(array)) => array
                 .into_iter()
-                .any(|value| match predicate.eval(value) {
+                .any(|value| match predicate.eval_in(value, context) {
                     Value::Null | Value::Bool(false) => false,
                     Value::Bool(true) => true,
                     value => self.complain_about(&value),
@@ -409,7 +709,7 @@ impl Transformer {
                 .into(),
             (Transformer::All(predicate), Value::Array(array)) => array
                 .into_iter()
-                .all(|value| match predicate.eval(value) {
+                .all(|value| match predicate.eval_in(value, context) {
                     Value::Null | Value::Bool(false) => false,
                     Value::Bool(true) => true,
                     value => self.complain_about(&value),
@@ -422,13 +722,51 @@ impl Transformer {
             }
             (Transformer::SortBy(key), Value::Array(array)) => {
                 let mut array = array.clone();
-                array.sort_unstable_by(|a, b| cmp_json(&key.eval(a.clone()), &key.eval(b.clone())));
+                array.sort_unstable_by(|a, b| {
+                    cmp_json(
+                        &key.eval_in(a.clone(), context),
+                        &key.eval_in(b.clone(), context),
+                    )
+                });
                 array.into()
             }
             (Transformer::AsString, Value::Number(num)) => num.to_string().into(),
             (Transformer::AsString, Value::Bool(b)) => b.to_string().into(),
             (Transformer::AsString, Value::String(string)) => Value::String(string),
             (Transformer::Pretty, Value::String(string)) => pretty(&string).into(),
+            (Transformer::Lower, Value::String(string)) => string.to_lowercase().into(),
+            (Transformer::Upper, Value::String(string)) => string.to_uppercase().into(),
+            (Transformer::Trim, Value::String(string)) => string.trim().into(),
+            (Transformer::Split(separator), Value::String(string)) => {
+                string.split(separator.as_ref()).collect::<Vec<_>>().into()
+            }
+            (Transformer::Join(separator), Value::Array(array)) => array
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(separator)
+                .into(),
+            (Transformer::StartsWith(prefix), Value::String(string)) => {
+                string.starts_with(prefix.as_ref()).into()
+            }
+            (&Transformer::Substring(start, length), Value::String(string)) => string
+                .chars()
+                .skip(start)
+                .take(length.unwrap_or(usize::MAX))
+                .collect::<String>()
+                .into(),
+            (Transformer::UrlDecode, Value::String(string)) => {
+                percent_decode_str(&string).decode_utf8_lossy().into()
+            }
+            (Transformer::AbsoluteUrl, Value::String(string)) => resolve_url(&string, context)
+                .map(|url| String::from(url).into())
+                .unwrap_or(Value::Null),
+            (Transformer::UrlParts, Value::String(string)) => resolve_url(&string, context)
+                .map(|url| url_parts(&url).into())
+                .unwrap_or(Value::Null),
+            (Transformer::ParseDate(format), Value::String(string)) => parse_date(&string, format)
+                .map(Value::String)
+                .unwrap_or(Value::Null),
             (Transformer::EqualsString(this), Value::String(other)) => {
                 (this.as_ref() == other.as_str()).into()
             }
@@ -454,7 +792,25 @@ impl Transformer {
                     .into_owned()
                     .into()
             }
+            (Transformer::ParseJson, Value::String(string)) => {
+                serde_json::from_str(&string).unwrap_or(Value::Null)
+            }
+            (Transformer::Path(path), value) => path.eval(&value),
+            (Transformer::Cast(typ), value) if typ.accepts(&value) => value,
+            (Transformer::Cast(_), _) => Value::Null,
+            (Transformer::Macro(_, Some(definition)), value) => {
+                definition.expression.eval_in(value, context)
+            }
             (_, Value::Null) => Value::Null,
+            // Values typed `any` may turn out to be anything:
+            (
+                Transformer::AsNumber
+                | Transformer::AsLocalNumber(_)
+                | Transformer::AsString
+                | Transformer::Get(_)
+                | Transformer::GetIdx(_),
+                _,
+            ) => Value::Null,
             (transformer, value) => panic!("type checked: {:?} {:?}", transformer, value),
         }
     }
@@ -511,11 +867,310 @@ impl TransformerExpression {
         Ok(typ)
     }
 
-    pub fn eval(&self, mut value: Value) -> Value {
+    /// Evaluates this expression outside of any page.
+    pub fn eval(&self, value: Value) -> Value {
+        self.eval_in(value, Context::default())
+    }
+
+    pub fn eval_in(&self, mut value: Value, context: Context) -> Value {
         for transformer in &*self.transformers {
-            value = transformer.eval(value);
+            value = transformer.eval_in(value, context);
         }
 
         value
     }
+
+    fn inner(&self) -> impl Iterator<Item = &TransformerExpression> {
+        self.transformers
+            .iter()
+            .filter_map(|transformer| match transformer {
+                Transformer::Each(inner)
+                | Transformer::Filter(inner)
+                | Transformer::Any(inner)
+                | Transformer::All(inner)
+                | Transformer::SortBy(inner) => Some(inner),
+                _ => None,
+            })
+    }
+
+    /// The names of all definitions this expression refers to.
+    pub fn references(&self) -> Vec<&str> {
+        let mut references = self
+            .transformers
+            .iter()
+            .filter_map(|transformer| match transformer {
+                Transformer::Macro(name, _) => Some(name.as_ref()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        for inner in self.inner() {
+            references.extend(inner.references());
+        }
+
+        references
+    }
+
+    /// A reference to a definition in this expression that was not resolved,
+    /// if any.
+    fn unresolved_reference(&self) -> Option<&str> {
+        self.transformers
+            .iter()
+            .find_map(|transformer| match transformer {
+                Transformer::Macro(name, None) => Some(name.as_ref()),
+                _ => None,
+            })
+            .or_else(|| {
+                self.inner()
+                    .find_map(TransformerExpression::unresolved_reference)
+            })
+    }
+
+    /// Points all references to definitions in this expression to what
+    /// `resolve` finds for their names. References `resolve` knows nothing
+    /// about are left as they are, to show up as type errors.
+    pub fn resolve_references<F>(&mut self, resolve: &mut F)
+    where
+        F: FnMut(&str) -> Option<Arc<Definition>>,
+    {
+        for transformer in &mut *self.transformers {
+            match transformer {
+                Transformer::Macro(name, definition) => *definition = resolve(name),
+                Transformer::Each(inner)
+                | Transformer::Filter(inner)
+                | Transformer::Any(inner)
+                | Transformer::All(inner)
+                | Transformer::SortBy(inner) => inner.resolve_references(resolve),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Input types tried, in order, for definitions that do not declare one.
+const INFERRED_INPUTS: &[fn() -> Type] = &[
+    || Type::String,
+    || Type::Number,
+    || Type::Bool,
+    || Type::Any,
+    || Type::Array(Box::new(Type::String)),
+    || Type::Array(Box::new(Type::Number)),
+    || Type::Array(Box::new(Type::Any)),
+    || Type::Map(Box::new(Type::String)),
+    || Type::Map(Box::new(Type::Any)),
+];
+
+/// A transformer expression given a name by `define`.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Definition {
+    pub name: String,
+    /// The input type, if declared.
+    pub input: Option<Type>,
+    pub expression: TransformerExpression,
+    /// The input and output types, found once the definition is resolved.
+    pub signature: Option<(Type, Type)>,
+}
+
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.input {
+            Some(input) => write!(f, "define {}: {} = {};", self.name, input, self.expression),
+            None => write!(f, "define {} = {};", self.name, self.expression),
+        }
+    }
+}
+
+impl Definition {
+    /// Type checks this definition, for its declared input type or for the
+    /// first input type that works. Its references must be resolved by now.
+    pub fn check(&mut self) -> Result<(), Error> {
+        if let Some(name) = self.expression.unresolved_reference() {
+            return Err(Error::Undefined(name.to_owned()));
+        }
+
+        let (input, output) = if let Some(input) = &self.input {
+            (input.clone(), self.expression.type_for(input)?)
+        } else {
+            INFERRED_INPUTS
+                .iter()
+                .find_map(|input| {
+                    let input = input();
+                    let output = self.expression.type_for(&input).ok()?;
+                    Some((input, output))
+                })
+                .ok_or_else(|| Error::Uninferable(self.name.clone()))?
+        };
+
+        self.signature = Some((input, output));
+
+        Ok(())
+    }
+}
+
+#[test]
+fn arithmetic_and_strings_test() {
+    let eval = |transformers: Vec<Transformer>, value: Value| {
+        TransformerExpression {
+            transformers: transformers.into_boxed_slice(),
+        }
+        .eval(value)
+    };
+
+    assert_eq!(
+        eval(
+            vec![
+                Transformer::AsLocalNumber("pt-BR".into()),
+                Transformer::Mul(2.0)
+            ],
+            "1.234,56".into()
+        ),
+        2469.12
+    );
+    assert_eq!(eval(vec![Transformer::Div(0.0)], 1.into()), Value::Null);
+    assert_eq!(
+        eval(vec![Transformer::Add(0.5), Transformer::Round(0)], 2.into()),
+        3
+    );
+    assert_eq!(eval(vec![Transformer::Round(2)], 1.23456.into()), 1.23);
+    assert_eq!(
+        eval(
+            vec![
+                Transformer::Trim,
+                Transformer::Lower,
+                Transformer::Split(" ".into()),
+                Transformer::Join("-".into()),
+            ],
+            "  Hello World ".into()
+        ),
+        "hello-world"
+    );
+    assert_eq!(
+        eval(vec![Transformer::Substring(1, Some(3))], "ação!".into()),
+        "ção"
+    );
+    assert_eq!(
+        eval(vec![Transformer::UrlDecode], "caf%C3%A9%20au%20lait".into()),
+        "café au lait"
+    );
+    assert_eq!(
+        eval(
+            vec![Transformer::ParseDate("%d/%m/%Y".into())],
+            "31/01/2022".into()
+        ),
+        "2022-01-31"
+    );
+
+    assert!(Transformer::Join(",".into())
+        .type_for(&Type::Array(Box::new(Type::Number)))
+        .is_err());
+    assert_eq!(
+        Transformer::Split(",".into())
+            .type_for(&Type::String)
+            .unwrap(),
+        Type::Array(Box::new(Type::String))
+    );
+}
+
+#[test]
+fn definition_test() {
+    let mut price = Definition {
+        name: "price-of".to_owned(),
+        input: None,
+        expression: TransformerExpression {
+            transformers: vec![Transformer::AsNumber].into_boxed_slice(),
+        },
+        signature: None,
+    };
+    price.check().unwrap();
+    assert_eq!(price.signature, Some((Type::String, Type::Number)));
+
+    let mut uses = TransformerExpression {
+        transformers: vec![
+            Transformer::Each(TransformerExpression {
+                transformers: vec![Transformer::Macro("price-of".into(), None)].into_boxed_slice(),
+            }),
+            Transformer::Macro("nothing".into(), None),
+        ]
+        .into_boxed_slice(),
+    };
+    assert_eq!(uses.references(), vec!["nothing", "price-of"]);
+
+    let price = Arc::new(price);
+    uses.resolve_references(&mut |name| (name == "price-of").then(|| price.clone()));
+    assert!(uses.type_for(&Type::Array(Box::new(Type::String))).is_err());
+
+    let mut each = TransformerExpression {
+        transformers: vec![Transformer::Each(TransformerExpression {
+            transformers: vec![Transformer::Macro("price-of".into(), None)].into_boxed_slice(),
+        })]
+        .into_boxed_slice(),
+    };
+    each.resolve_references(&mut |_| Some(price.clone()));
+    assert_eq!(
+        each.type_for(&Type::Array(Box::new(Type::String))).unwrap(),
+        Type::Array(Box::new(Type::Number))
+    );
+    assert_eq!(
+        each.eval(serde_json::json!(["1.5", "x"])),
+        serde_json::json!([1.5, null])
+    );
+
+    let mut bad = Definition {
+        name: "bad".to_owned(),
+        input: Some(Type::Number),
+        expression: TransformerExpression {
+            transformers: vec![Transformer::Pretty].into_boxed_slice(),
+        },
+        signature: None,
+    };
+    assert!(bad.check().is_err());
+}
+
+#[test]
+fn json_test() {
+    let price = TransformerExpression {
+        transformers: vec![
+            Transformer::ParseJson,
+            Transformer::Path("$.offers.price".parse().unwrap()),
+            Transformer::AsNumber,
+        ]
+        .into_boxed_slice(),
+    };
+    assert_eq!(price.type_for(&Type::String).unwrap(), Type::Number);
+    assert_eq!(price.eval(r#"{"offers": {"price": "12.5"}}"#.into()), 12.5);
+    assert_eq!(price.eval(r#"{"offers": [1, 2]}"#.into()), Value::Null);
+    assert_eq!(price.eval("not json".into()), Value::Null);
+
+    let cast = Transformer::Cast(Type::Array(Box::new(Type::Number)));
+    assert_eq!(
+        cast.type_for(&Type::Any).unwrap(),
+        Type::Array(Box::new(Type::Number))
+    );
+    assert!(cast.type_for(&Type::String).is_err());
+    assert!(cast.type_for(&Type::Array(Box::new(Type::Number))).is_err());
+    assert!(Transformer::Path("$.offers".parse().unwrap())
+        .type_for(&Type::Map(Box::new(Type::String)))
+        .is_err());
+    assert_eq!(
+        cast.eval(serde_json::json!([1, 2])),
+        serde_json::json!([1, 2])
+    );
+    assert_eq!(cast.eval(serde_json::json!([1, "2"])), Value::Null);
+}
+
+#[test]
+fn object_test() {
+    let object = "{name: string, price: number}".parse::<Type>().unwrap();
+    let value = serde_json::json!({ "name": "foo", "price": 1 });
+
+    assert_eq!(Transformer::Length.type_for(&object).unwrap(), Type::Number);
+    assert_eq!(Transformer::Length.eval(value.clone()), 2);
+    assert_eq!(Transformer::IsEmpty.type_for(&object).unwrap(), Type::Bool);
+    assert_eq!(Transformer::IsEmpty.eval(value.clone()), false);
+    assert_eq!(
+        Transformer::Get("price".into()).type_for(&object).unwrap(),
+        Type::Number
+    );
+    assert_eq!(Transformer::Get("price".into()).eval(value), 1);
+    assert!(Transformer::Get("weight".into()).type_for(&object).is_err());
 }
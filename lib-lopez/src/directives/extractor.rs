@@ -4,10 +4,14 @@ use serde_json::{Map, Value};
 use serde_with::{serde_as, DisplayFromStr};
 use std::fmt;
 
+use crate::crawler::{PageInfo, Reason};
 use crate::Type;
 
-use super::expressions::{Error, Extractable, ExtractorExpression, Typed};
-use super::Selector;
+use super::expressions::{
+    Context, Error, Extractable, ExtractorExpression, TransformerExpression, Typed,
+};
+use super::parse_common::quote;
+use super::{Selector, XPath};
 
 #[serde_as]
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -30,6 +34,26 @@ pub enum Extractor {
         Box<ExtractorExpression<Self>>,
         #[serde_as(as = "DisplayFromStr")] Selector,
     ),
+    SelectAnyXPath(
+        Box<ExtractorExpression<Self>>,
+        #[serde_as(as = "DisplayFromStr")] XPath,
+    ),
+    SelectAllXPath(
+        Box<ExtractorExpression<Self>>,
+        #[serde_as(as = "DisplayFromStr")] XPath,
+    ),
+    /// Builds a JSON object, one field per expression.
+    Object(Vec<(Box<str>, ExtractorExpression<Self>)>),
+
+    // Page-level (the same for every element in the page):
+    PageUrl,
+    PageDepth,
+    StatusCode,
+    OutgoingLinksCount,
+    /// Outgoing links to the same host as the page.
+    InternalLinksCount,
+    /// Outgoing links to a host other than the page's.
+    ExternalLinksCount,
 }
 
 impl fmt::Display for Extractor {
@@ -39,18 +63,71 @@ impl fmt::Display for Extractor {
             Extractor::Text => write!(f, "text"),
             Extractor::Html => write!(f, "html"),
             Extractor::InnerHtml => write!(f, "inner-html"),
-            Extractor::Attr(attr) => write!(f, "attr \"{:?}\"", attr.replace('\"', "\\\"")),
+            Extractor::Attr(attr) => write!(f, "attr {}", quote(attr)),
             Extractor::Attrs => write!(f, "attrs"),
             Extractor::Classes => write!(f, "classes"),
             Extractor::Id => write!(f, "id"),
             Extractor::Parent(parent) => write!(f, "parent({})", parent),
             Extractor::Children(children) => write!(f, "children({})", children),
-            Extractor::SelectAny(select_any, extractor) => {
-                write!(f, "select-any({}, {:?})", select_any, extractor)
-            } // TODO: display correctly.
-            Extractor::SelectAll(select_all, extractor) => {
-                write!(f, "select-any({}, {:?})", select_all, extractor)
-            } // TODO: display correctly.
+            Extractor::SelectAny(select_any, selector) => {
+                write!(f, "select-any({}, {})", select_any, selector)
+            }
+            Extractor::SelectAll(select_all, selector) => {
+                write!(f, "select-all({}, {})", select_all, selector)
+            }
+            Extractor::SelectAnyXPath(select_any, xpath) => write!(
+                f,
+                "select-any-xpath({}, {})",
+                select_any,
+                quote(&xpath.to_string())
+            ),
+            Extractor::SelectAllXPath(select_all, xpath) => write!(
+                f,
+                "select-all-xpath({}, {})",
+                select_all,
+                quote(&xpath.to_string())
+            ),
+            Extractor::Object(fields) => write!(
+                f,
+                "{{{}}}",
+                fields
+                    .iter()
+                    .map(|(name, field)| format!("{}: {}", name, field))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Extractor::PageUrl => write!(f, "page-url"),
+            Extractor::PageDepth => write!(f, "page-depth"),
+            Extractor::StatusCode => write!(f, "status-code"),
+            Extractor::OutgoingLinksCount => write!(f, "outgoing-links-count"),
+            Extractor::InternalLinksCount => write!(f, "internal-links-count"),
+            Extractor::ExternalLinksCount => write!(f, "external-links-count"),
+        }
+    }
+}
+
+impl Extractor {
+    /// Calls `f` on every transformer expression nested in this extractor.
+    pub fn with_transformer_expression_mut<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(&mut TransformerExpression),
+    {
+        let mut nested = |extractor_expr: &mut ExtractorExpression<Self>| {
+            f(&mut extractor_expr.transformer_expression);
+            extractor_expr.extractor.with_transformer_expression_mut(f);
+        };
+
+        match self {
+            Extractor::Parent(extractor_expr)
+            | Extractor::Children(extractor_expr)
+            | Extractor::SelectAny(extractor_expr, _)
+            | Extractor::SelectAll(extractor_expr, _)
+            | Extractor::SelectAnyXPath(extractor_expr, _)
+            | Extractor::SelectAllXPath(extractor_expr, _) => nested(extractor_expr),
+            Extractor::Object(fields) => fields
+                .iter_mut()
+                .for_each(|(_, extractor_expr)| nested(extractor_expr)),
+            _ => {}
         }
     }
 }
@@ -70,54 +147,187 @@ impl Typed for Extractor {
             Extractor::Children(children) => Type::Array(Box::new(children.type_of()?)),
             Extractor::SelectAny(extractor, _) => extractor.type_of()?,
             Extractor::SelectAll(extractor, _) => Type::Array(Box::new(extractor.type_of()?)),
+            Extractor::SelectAnyXPath(extractor, _) => extractor.type_of()?,
+            Extractor::SelectAllXPath(extractor, _) => Type::Array(Box::new(extractor.type_of()?)),
+            Extractor::Object(fields) => Type::Object(
+                fields
+                    .iter()
+                    .map(|(name, field)| Ok((name.to_string(), field.type_of()?)))
+                    .collect::<Result<_, Error>>()?,
+            ),
+            Extractor::PageUrl => Type::String,
+            Extractor::PageDepth => Type::Number,
+            Extractor::StatusCode => Type::Number,
+            Extractor::OutgoingLinksCount => Type::Number,
+            Extractor::InternalLinksCount => Type::Number,
+            Extractor::ExternalLinksCount => Type::Number,
         })
     }
 }
 
-impl<'a> Extractable<Extractor> for ElementRef<'a> {
+/// All that is known of the page being analyzed.
+#[derive(Debug)]
+pub struct Page<'a> {
+    pub info: PageInfo<'a>,
+    pub internal_links: usize,
+    pub external_links: usize,
+}
+
+impl<'a> Page<'a> {
+    /// Counts the links found in the page, keeping only those that lead to
+    /// another page (i.e., no `#section`s, `mailto:`s and the like).
+    pub fn new(info: PageInfo<'a>, links: &[(Reason, String)]) -> Page<'a> {
+        let mut internal_links = 0;
+        let mut external_links = 0;
+
+        for (reason, link) in links {
+            if *reason != Reason::Ahref || link.starts_with('#') {
+                continue;
+            }
+
+            match info.url.join(link) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+                    if url.host_str() == info.url.host_str() {
+                        internal_links += 1;
+                    } else {
+                        external_links += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Page {
+            info,
+            internal_links,
+            external_links,
+        }
+    }
+}
+
+#[test]
+fn page_test() {
+    let url = "https://example.foo/a/b".parse().unwrap();
+    let links = [
+        "../x",
+        "https://other.foo/",
+        "mailto:me@x.foo",
+        "#top",
+        "//example.foo/z",
+    ]
+    .iter()
+    .map(|link| (Reason::Ahref, link.to_string()))
+    .chain(Some((Reason::Canonical, "/a/b".to_owned())))
+    .collect::<Vec<_>>();
+    let page = Page::new(
+        PageInfo {
+            url: &url,
+            depth: 3,
+            status_code: http::StatusCode::OK,
+        },
+        &links,
+    );
+
+    assert_eq!(page.internal_links, 2);
+    assert_eq!(page.external_links, 1);
+}
+
+/// An element, together with the page it was found in.
+#[derive(Debug, Clone, Copy)]
+pub struct PageElement<'a> {
+    pub element_ref: ElementRef<'a>,
+    pub page: &'a Page<'a>,
+}
+
+impl<'a> PageElement<'a> {
+    fn with(self, element_ref: ElementRef<'a>) -> PageElement<'a> {
+        PageElement {
+            element_ref,
+            ..self
+        }
+    }
+}
+
+impl<'a> Extractable<Extractor> for PageElement<'a> {
     type Output = Value;
 
     #[inline(always)]
     fn extract_with(self, extractor: &Extractor) -> Value {
+        let element = self.element_ref;
         match extractor {
-            Extractor::Name => self.value().name().into(),
-            Extractor::Html => self.html().into(),
-            Extractor::InnerHtml => self.inner_html().into(),
-            Extractor::Text => self.text().collect::<Vec<_>>().join(" ").into(),
-            Extractor::Attr(attr) => self
+            Extractor::Name => element.value().name().into(),
+            Extractor::Html => element.html().into(),
+            Extractor::InnerHtml => element.inner_html().into(),
+            Extractor::Text => element.text().collect::<Vec<_>>().join(" ").into(),
+            Extractor::Attr(attr) => element
                 .value()
                 .attr(attr)
                 .map(|value| value.into())
                 .unwrap_or(Value::Null),
-            Extractor::Attrs => self
+            Extractor::Attrs => element
                 .value()
                 .attrs()
                 .map(|(key, value)| (key.to_owned(), value.to_owned().into()))
                 .collect::<Map<_, _>>()
                 .into(),
-            Extractor::Classes => self.value().classes().collect::<Vec<_>>().into(),
-            Extractor::Id => self.value().id().map(|id| id.into()).unwrap_or(Value::Null),
-            Extractor::Parent(parent) => self
+            Extractor::Classes => element.value().classes().collect::<Vec<_>>().into(),
+            Extractor::Id => element
+                .value()
+                .id()
+                .map(|id| id.into())
+                .unwrap_or(Value::Null),
+            Extractor::Parent(parent) => element
                 .parent()
                 .and_then(ElementRef::wrap)
-                .map(|element_ref| element_ref.extract_with(parent.as_ref()))
+                .map(|element_ref| self.with(element_ref).extract_with(parent.as_ref()))
                 .unwrap_or(Value::Null),
-            Extractor::Children(children) => self
+            Extractor::Children(children) => element
                 .children()
                 .filter_map(ElementRef::wrap)
-                .map(|element_ref| element_ref.extract_with(children.as_ref()))
+                .map(|element_ref| self.with(element_ref).extract_with(children.as_ref()))
                 .collect::<Vec<_>>()
                 .into(),
-            Extractor::SelectAny(extractor, selector) => self
+            Extractor::SelectAny(extractor, selector) => element
                 .select(selector)
                 .next()
-                .map(|element_ref| element_ref.extract_with(extractor.as_ref()))
+                .map(|element_ref| self.with(element_ref).extract_with(extractor.as_ref()))
                 .unwrap_or(Value::Null),
-            Extractor::SelectAll(extractor, selector) => self
+            Extractor::SelectAll(extractor, selector) => element
                 .select(selector)
-                .map(|element_ref| element_ref.extract_with(extractor.as_ref()))
+                .map(|element_ref| self.with(element_ref).extract_with(extractor.as_ref()))
+                .collect::<Vec<_>>()
+                .into(),
+            Extractor::SelectAnyXPath(extractor, xpath) => xpath
+                .select_in(element)
+                .into_iter()
+                .next()
+                .map(|element_ref| self.with(element_ref).extract_with(extractor.as_ref()))
+                .unwrap_or(Value::Null),
+            Extractor::SelectAllXPath(extractor, xpath) => xpath
+                .select_in(element)
+                .into_iter()
+                .map(|element_ref| self.with(element_ref).extract_with(extractor.as_ref()))
                 .collect::<Vec<_>>()
                 .into(),
+            Extractor::Object(fields) => fields
+                .iter()
+                .map(|(name, field)| (name.to_string(), self.extract_with(field)))
+                .collect::<Map<_, _>>()
+                .into(),
+            Extractor::PageUrl => self.page.info.url.as_str().into(),
+            Extractor::PageDepth => self.page.info.depth.into(),
+            Extractor::StatusCode => self.page.info.status_code.as_u16().into(),
+            Extractor::OutgoingLinksCount => {
+                (self.page.internal_links + self.page.external_links).into()
+            }
+            Extractor::InternalLinksCount => self.page.internal_links.into(),
+            Extractor::ExternalLinksCount => self.page.external_links.into(),
+        }
+    }
+
+    fn context(&self) -> Context<'_> {
+        Context {
+            page_url: Some(self.page.info.url),
         }
     }
 }
@@ -0,0 +1,405 @@
+//! Canonical formatting of directive files, for `lopez fmt`. Comments
+//! (doc comments included) and blank lines separating groups of items are
+//! kept; everything else is rewritten from the parsed items.
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    combinator::{consumed, map, opt, recognize},
+    sequence::tuple,
+    IResult,
+};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::diagnostics::{Diagnostic, Diagnostics};
+use super::expressions::parse::aggregator_expression;
+use super::parse::{
+    boundary, condition, definition, element_selector, entrypoint, expectation, fixture,
+    identified_value, module, seed, set_variable, web_driver,
+};
+use super::parse_common::*;
+use super::parse_utils::{Located, ParseError};
+use super::Extractor;
+
+/// Tells whether a piece of code has a comment outside its strings. Such
+/// pieces are kept as they are, since the comment would be lost otherwise.
+fn has_comment(code: &str) -> bool {
+    let mut chars = code.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => {
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => return true,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+#[test]
+fn has_comment_test() {
+    assert!(!has_comment("seed \"https://example.foo/\";"));
+    assert!(has_comment("seed // the home\n \"https://example.foo/\";"));
+}
+
+/// The comments and blank lines between two pieces of code.
+struct Trivia<'a> {
+    /// A comment in the same line as the previous piece of code.
+    trailing: Option<&'a str>,
+    /// The comments in their own lines. A `None` stands for blank lines.
+    lines: Vec<Option<&'a str>>,
+}
+
+impl<'a> Trivia<'a> {
+    fn has_comments(&self) -> bool {
+        self.trailing.is_some() || self.lines.iter().any(Option::is_some)
+    }
+}
+
+fn trivia(i: &str) -> (&str, Trivia<'_>) {
+    let (rest, span) = recognize(whitespace)(i).unwrap_or((i, ""));
+    let mut segments = span.split('\n');
+    let trailing = segments.next().map(str::trim).filter(|s| !s.is_empty());
+    let segments = segments.map(str::trim).collect::<Vec<_>>();
+    let mut lines = vec![];
+
+    for (idx, segment) in segments.iter().enumerate() {
+        if !segment.is_empty() {
+            lines.push(Some(*segment));
+        } else if idx + 1 < segments.len() && lines.last() != Some(&None) {
+            // The last segment is just the indentation of the next line.
+            lines.push(None);
+        }
+    }
+
+    (rest, Trivia { trailing, lines })
+}
+
+#[derive(Default)]
+struct Formatter {
+    output: String,
+    indent: usize,
+    /// Whether the last line still accepts a trailing comment.
+    line_open: bool,
+    /// Whether a blank line is to be written before the next line.
+    blank: bool,
+    /// Whether we are just after the opening of a block.
+    block_start: bool,
+}
+
+impl Formatter {
+    fn line(&mut self, code: &str) {
+        self.end_line();
+
+        if self.blank && !self.output.is_empty() && !self.block_start {
+            self.output.push('\n');
+        }
+
+        self.output.push_str(&"    ".repeat(self.indent));
+        self.output.push_str(code);
+        self.line_open = true;
+        self.blank = false;
+        self.block_start = false;
+    }
+
+    fn end_line(&mut self) {
+        if self.line_open {
+            self.output.push('\n');
+            self.line_open = false;
+        }
+    }
+
+    fn trivia(&mut self, trivia: Trivia) {
+        if let Some(comment) = trivia.trailing {
+            if self.line_open {
+                self.output.push(' ');
+                self.output.push_str(comment);
+            } else {
+                self.line(comment);
+            }
+        }
+
+        for line in trivia.lines {
+            if let Some(comment) = line {
+                self.line(comment);
+            } else {
+                self.blank = true;
+            }
+        }
+    }
+
+    /// Formats a block whose head has already been parsed, up to the `{`.
+    fn block<'a, F>(&mut self, head: &str, i: &'a str, mut entry: F) -> IResult<&'a str, ()>
+    where
+        F: FnMut(&'a str) -> IResult<&'a str, String>,
+    {
+        let (i, _) = tag("{")(i)?;
+        let (mut i, inner) = trivia(i);
+
+        if !inner.has_comments() && i.starts_with('}') {
+            self.line(&format!("{} {{}}", head));
+            return Ok((&i[1..], ()));
+        }
+
+        self.line(&format!("{} {{", head));
+        self.indent += 1;
+        self.block_start = true;
+        self.trivia(inner);
+
+        while !i.starts_with('}') {
+            let (rest, code) = entry(i)?;
+            self.line(&code);
+            let (rest, inner) = trivia(rest);
+            self.trivia(inner);
+            i = rest;
+        }
+
+        self.indent -= 1;
+        self.blank = false;
+        self.line("}");
+
+        Ok((&i[1..], ()))
+    }
+
+    fn item<'a>(&mut self, i: &'a str) -> IResult<&'a str, ()> {
+        if let Ok((rest, (raw, (_, in_page, when, selector)))) = consumed(tuple((
+            tag_whitespace("select"),
+            opt(trailing_whitespace(tuple((
+                tag_whitespace("in"),
+                escaped_string,
+            )))),
+            opt(trailing_whitespace(tuple((
+                tag_whitespace("when"),
+                consumed(condition),
+            )))),
+            consumed(element_selector('{')),
+        )))(i)
+        {
+            let head = keep_comments(raw, || {
+                let selector = match selector {
+                    (_, Ok(selector)) => selector.to_string(),
+                    (raw, Err(_)) => raw.trim().to_owned(),
+                };
+
+                let mut head = "select".to_owned();
+
+                if let Some((_, in_page)) = in_page {
+                    head += &format!(" in {}", quote(&in_page));
+                }
+
+                match when {
+                    Some((_, (_, Ok(condition)))) => head += &format!(" when {}", condition),
+                    Some((_, (raw, Err(_)))) => head += &format!(" when {}", raw.trim()),
+                    None => {}
+                }
+
+                format!("{} {}", head, selector)
+            });
+
+            return self.block(&head, rest, rule);
+        }
+
+        if let Ok((rest, (raw, (_, name)))) = consumed(trailing_whitespace(tuple((
+            tag_whitespace("test"),
+            escaped_string,
+        ))))(i)
+        {
+            let head = keep_comments(raw, || format!("test {}", quote(&name)));
+
+            return self.block(&head, rest, test_statement);
+        }
+
+        let (rest, code) = statement(i)?;
+        self.line(&code);
+
+        Ok((rest, ()))
+    }
+}
+
+/// Keeps `raw` as it is if it has comments; formats it otherwise.
+fn keep_comments(raw: &str, format: impl FnOnce() -> String) -> String {
+    if has_comment(raw) {
+        raw.trim().to_owned()
+    } else {
+        format()
+    }
+}
+
+fn rule(i: &str) -> IResult<&str, String> {
+    map(
+        consumed(identified_value(consumed(
+            aggregator_expression::<Extractor>,
+        ))),
+        |(raw, (name, (expression_raw, expression)))| {
+            keep_comments(raw, || match expression {
+                Ok(expression) => format!("{}: {};", name, expression),
+                Err(_) => format!("{}: {};", name, expression_raw.trim()),
+            })
+        },
+    )(i)
+}
+
+/// Formats a piece of code with the `Display` of what `parser` parses it
+/// into.
+fn displayed<'a, T, E>(
+    mut parser: impl FnMut(&'a str) -> IResult<&'a str, Result<T, E>>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, String>
+where
+    T: fmt::Display,
+{
+    move |i| {
+        let (rest, (raw, parsed)) = consumed(&mut parser)(i)?;
+        let code = keep_comments(raw, || match parsed {
+            Ok(parsed) => parsed.to_string(),
+            Err(_) => raw.trim().to_owned(),
+        });
+
+        Ok((rest, code))
+    }
+}
+
+fn test_statement(i: &str) -> IResult<&str, String> {
+    alt((
+        displayed(fixture),
+        displayed(map(expectation, Ok::<_, String>)),
+    ))(i)
+}
+
+fn statement(i: &str) -> IResult<&str, String> {
+    alt((
+        displayed(definition),
+        displayed(map(module, Ok::<_, String>)),
+        displayed(map(seed, |seed| {
+            seed.map(|seed| format!("seed {};", quote(seed.as_str())))
+        })),
+        displayed(boundary),
+        displayed(map(set_variable, Ok::<_, String>)),
+        displayed(web_driver),
+    ))(i)
+}
+
+/// Formats the source of a module. This fails with the parse errors of the
+/// module if it does not parse.
+pub fn format(source: &str) -> Result<String, Vec<Located<String>>> {
+    let (_, errors) = entrypoint(source);
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut formatter = Formatter::default();
+    let (mut i, leading) = trivia(source);
+    formatter.trivia(leading);
+
+    while !i.is_empty() {
+        let (rest, _) = formatter.item(i).map_err(|err| {
+            let error = ParseError::new(source, err);
+            vec![(error.position(), error.message())]
+        })?;
+        let (rest, inner) = trivia(rest);
+        formatter.trivia(inner);
+        i = rest;
+    }
+
+    formatter.end_line();
+
+    Ok(formatter.output)
+}
+
+#[test]
+fn format_test() {
+    let source = "// The store.\n\n\n/// Where to start.\nseed   \"https://example.foo/\" ; // home\n\
+        allow \"^https://example\\.foo/\";\nset  max-depth =3;\ndefine  price-of:string= pretty as-number ;\n\
+        select in \"product\"   h1.title{ // titles\n\n\n  title :first( text pretty )  ;\n\
+        \n// The price.\nprice: first(attr \"data-price\" as-number); }\n\
+        test \"home\" { fixture \"home.html\" as \"https://example.foo/\"; expect title == [\"a\", 1]; }\n\
+        select xpath   \"//dt\" {}\nselect a {}\n\
+        select  when status-code==200 and(page-depth>3 or exists( \".p\" ))  a {}\n";
+    let expected = "// The store.\n\n/// Where to start.\nseed \"https://example.foo/\"; // home\n\
+        allow \"^https://example\\.foo/\";\nset max-depth = 3;\n\
+        define price-of: string = pretty as-number;\nselect in \"product\" h1.title { // titles\n    title: first(text pretty);\n\
+        \n    // The price.\n    price: first(attr \"data-price\" as-number);\n}\n\
+        test \"home\" {\n    fixture \"home.html\" as \"https://example.foo/\";\n    \
+        expect title == [\"a\", 1];\n}\nselect xpath \"//dt\" {}\nselect a {}\n\
+        select when status-code == 200 and (page-depth > 3 or exists(\".p\")) a {}\n";
+
+    let formatted = format(source).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format(&formatted).unwrap(), formatted);
+}
+
+#[test]
+fn format_keeps_comments_in_code_test() {
+    let source =
+        "select a {\n    href: first(\n        // the link\n        attr \"href\"\n    );\n}\n";
+    assert_eq!(format(source).unwrap(), source);
+}
+
+#[test]
+fn format_round_trip_test() {
+    fn modules(dir: &Path, found: &mut Vec<std::path::PathBuf>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+
+            if path.is_dir() {
+                modules(&path, found);
+            } else if path.extension().is_some_and(|ext| ext == "lcd") {
+                found.push(path);
+            }
+        }
+    }
+
+    fn items(source: &str) -> Vec<serde_json::Value> {
+        let (items, errors) = entrypoint(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        items
+            .into_iter()
+            .map(|(_, item)| serde_json::to_value(item).unwrap())
+            .collect()
+    }
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let mut paths = vec![root.join("lopez/main.lcd")];
+    modules(&root.join("std-lopez"), &mut paths);
+    modules(&root.join("examples"), &mut paths);
+
+    for path in paths {
+        let source = fs::read_to_string(&path).unwrap();
+        let formatted = format(&source).unwrap();
+
+        assert_eq!(items(&formatted), items(&source), "{}", path.display());
+        assert_eq!(format(&formatted).unwrap(), formatted, "{}", path.display());
+    }
+}
+
+/// Formats the module in `path`. Returns the formatted source or `None`, if
+/// the file is already formatted.
+pub fn format_file(path: &Path) -> Result<Option<String>, anyhow::Error> {
+    let source = fs::read_to_string(path)
+        .map_err(|err| anyhow::anyhow!("could not read {}: {}", path.display(), err))?;
+    let formatted = format(&source).map_err(|errors| Diagnostics {
+        diagnostics: errors
+            .into_iter()
+            .map(|(position, message)| Diagnostic::new(path, &source, position, message))
+            .collect(),
+    })?;
+
+    if formatted == source {
+        Ok(None)
+    } else {
+        Ok(Some(formatted))
+    }
+}
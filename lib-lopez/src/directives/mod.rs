@@ -1,18 +1,34 @@
+mod condition;
+mod diagnostics;
 mod directives;
 mod error;
 mod expressions;
 mod extractor;
+mod format;
 mod parse;
 mod parse_common;
 mod parse_utils;
 mod selector;
+#[cfg(test)]
+mod test_dir;
+mod testing;
 mod variable;
+mod xpath;
 
 // Note on where to put parseable items: if it has an impl-block, it goes
 // Somewhere Else©; if it does not have an impl-block, it stays in `parse`.
 
+pub use self::diagnostics::{Diagnostic, Diagnostics};
 pub use self::directives::Directives;
+pub(crate) use self::expressions::parse::{AGGREGATOR_KEYWORDS, TRANSFORMER_KEYWORDS};
 pub use self::error::Error;
+pub use self::format::format_file;
+pub(crate) use self::parse::{EXTRACTOR_KEYWORDS, ITEM_KEYWORDS};
+#[cfg(test)]
+pub(crate) use self::test_dir::{load_test_directives, TestDir};
+pub use self::testing::DirectivesTestReport;
+pub(crate) use self::testing::{ExpectationFailure, TestOutcome};
+pub(crate) use self::variable::Variable;
 
 use async_trait::async_trait;
 use lazy_static::lazy_static;
@@ -23,15 +39,16 @@ use url::Url;
 use std::sync::Arc;
 
 use crate::crawler::{
-    Boundaries, Configuration, Downloaded, Downloader, Parameters, Parsed, Parser, Reason,
-    SimpleDownloader, WebDriverDownloader,
+    Boundaries, BoundariesExplanation, Configuration, Downloaded, Downloader, PageInfo, Parameters,
+    Parsed, Parser, Reason, SimpleDownloader, WebDriverDownloader,
 };
 use crate::{Type, Profile};
 
 use self::directives::{Analyzer, Boundaries as DirectiveBoundaries, WebDriverSelector};
-use self::extractor::Extractor;
-use self::selector::Selector;
-use self::variable::{SetVariables, Variable};
+use self::extractor::{Extractor, Page};
+use self::xpath::XPath;
+use self::selector::{ElementSelector, Selector};
+use self::variable::SetVariables;
 
 /// Finds all "hrefs" in an HTML and run all analyses.
 fn tree_search(html: &Html) -> Vec<(Reason, String)> {
@@ -56,14 +73,14 @@ fn tree_search(html: &Html) -> Vec<(Reason, String)> {
 }
 
 impl Parser for Analyzer {
-    fn parse(&self, page_url: &Url, content: &[u8]) -> Parsed {
+    fn parse(&self, page: PageInfo, content: &[u8]) -> Parsed {
         let html = Html::parse_document(&String::from_utf8_lossy(&content));
 
         // Search HTML:
         let links = tree_search(&html);
         log::debug!("found: {:?}", links);
 
-        let analyses = self.analyze(page_url, &html);
+        let analyses = self.analyze(&Page::new(page, &links), &html);
 
         Parsed::Accepted { links, analyses }
     }
@@ -81,6 +98,10 @@ impl Boundaries for DirectiveBoundaries {
     fn clean_query_params(&self, url: Url) -> Url {
         self.filter_query_params(url)
     }
+
+    fn explain(&self, url: &Url) -> BoundariesExplanation {
+        self.explain(url)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -157,6 +178,17 @@ impl Configuration for DirectivesConfiguration {
                 .expect("bad val"),
         }
     }
+
+    fn content_hash(&self) -> String {
+        // Going through `Value` sorts all maps, hash maps included.
+        let content = serde_json::to_value((self.directives.content(), &self.variables))
+            .expect("can serialize directives");
+        format!("{:016x}", crate::hash(&content.to_string()) as u64)
+    }
+
+    fn source(&self) -> String {
+        self.directives.formatted_source()
+    }
 }
 
 pub struct SelectiveDownloader {
@@ -175,3 +207,66 @@ impl Downloader for SelectiveDownloader {
         }
     }
 }
+
+#[test]
+fn content_hash_test() {
+    use std::path::{Component, Path, PathBuf};
+
+    let main = "import \"sub\";\nseed \"https://example.foo/\";\n";
+    let sub = "allow \"^https://example\\.foo/\";\nselect title {\n    title: first(text);\n}\n";
+    let write = |main: &str, sub: &str| TestDir::new(&[("main.lcd", main), ("sub.lcd", sub)]);
+    let configuration = |dir: &Path| {
+        let directives = Directives::load(dir.join("main.lcd"), dir).unwrap();
+        DirectivesConfiguration::new(directives, Arc::new(Profile::default()))
+    };
+
+    let original = write(main, sub);
+    let copy = write(main, sub);
+    let blank_line = write(&format!("\n{}", main), sub);
+    let with_test = write(
+        &format!(
+            "{}test \"home\" {{\n    fixture \"home.html\" as \"https://example.foo/\";\n    \
+                expect title == \"Home\";\n}}\n",
+            main
+        ),
+        sub,
+    );
+    let changed = write(main, &sub.replace("first(text)", "last(text)"));
+
+    // The same directory, reached from the current one with a relative path:
+    let relative = std::env::current_dir()
+        .unwrap()
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .map(|_| Component::ParentDir.as_os_str())
+        .chain(
+            original
+                .path()
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .map(|component| component.as_os_str()),
+        )
+        .collect::<PathBuf>();
+    assert!(relative.is_relative());
+
+    let hash = configuration(original.path()).content_hash();
+    assert_eq!(configuration(&relative).content_hash(), hash);
+    assert_eq!(configuration(copy.path()).content_hash(), hash);
+    assert_eq!(configuration(blank_line.path()).content_hash(), hash);
+    assert_eq!(configuration(with_test.path()).content_hash(), hash);
+    assert_ne!(configuration(changed.path()).content_hash(), hash);
+
+    // `show-config` reads the source back from what the wave stored:
+    let stored = serde_json::to_value(
+        &(Arc::new(configuration(blank_line.path())) as Arc<dyn Configuration>),
+    )
+    .unwrap();
+    let source = serde_json::from_value::<Box<dyn Configuration>>(stored)
+        .unwrap()
+        .source();
+
+    assert!(!source.starts_with("//\n"));
+    assert!(source.contains(&format!("// {}\n", blank_line.main().display())));
+    assert!(source.contains(&format::format(main).unwrap()));
+    assert!(source.contains(&format::format(sub).unwrap()));
+}
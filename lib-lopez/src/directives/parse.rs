@@ -1,29 +1,30 @@
 use nom::{
     branch::alt,
     bytes::complete::{is_not, tag},
-    character::complete::digit1,
-    combinator::{all_consuming, map, map_res, not, opt},
+    character::complete::{digit1, satisfy},
+    combinator::{map, map_res, not, opt, recognize},
     multi::{many0, separated_list0},
     number::complete::double,
-    sequence::{delimited, tuple},
+    sequence::{delimited, preceded, terminated, tuple},
     IResult,
 };
 use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
-use serde_with::DisplayFromStr;
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
 use url::Url;
 
 #[cfg(test)]
 use std::str::FromStr;
 
+use super::condition::{Comparison, Condition};
 use super::expressions::parse::*;
 use super::expressions::Parseable;
 use super::expressions::*;
 use super::parse_common::*;
-use super::parse_utils::ParseError;
-use super::{Extractor, Selector, Value};
+use super::parse_utils::{located, recovering, skip_item, Located, ParseError, Position, Spanned};
+use super::{ElementSelector, Extractor, Selector, Value, XPath};
 
 fn identifier(i: &str) -> IResult<&str, &str> {
     is_not("\\/:;.()[]{}\'\" \n\t\r\0")(i)
@@ -37,7 +38,9 @@ fn identifier_test() {
     );
 }
 
-fn identified_value<'a, F, T>(f: F) -> impl FnMut(&'a str) -> IResult<&'a str, (&'a str, T)>
+pub(super) fn identified_value<'a, F, T>(
+    f: F,
+) -> impl FnMut(&'a str) -> IResult<&'a str, (&'a str, T)>
 where
     F: FnMut(&'a str) -> IResult<&'a str, T>,
 {
@@ -89,7 +92,9 @@ fn block_test() {
     );
 }
 
-fn css_selector(boundary_hint: char) -> impl Fn(&str) -> IResult<&str, Result<Selector, String>> {
+pub(super) fn css_selector(
+    boundary_hint: char,
+) -> impl Fn(&str) -> IResult<&str, Result<Selector, String>> {
     move |i: &str| {
         let mut level = 0;
         let mut idx = 0;
@@ -127,6 +132,51 @@ fn css_selector_test() {
     );
 }
 
+pub(super) fn xpath(i: &str) -> IResult<&str, Result<XPath, String>> {
+    map(escaped_string, |xpath| xpath.parse())(i)
+}
+
+/// Either `xpath "<xpath>"` or a CSS selector ending at the boundary hint.
+pub(super) fn element_selector(
+    boundary_hint: char,
+) -> impl Fn(&str) -> IResult<&str, Result<ElementSelector, String>> {
+    move |i: &str| {
+        alt((
+            map(
+                trailing_whitespace(tuple((tag_whitespace("xpath"), xpath))),
+                |(_, xpath)| Ok(ElementSelector::XPath(xpath?)),
+            ),
+            map(css_selector(boundary_hint), |selector| {
+                Ok(ElementSelector::Css(selector?))
+            }),
+        ))(i)
+    }
+}
+
+/// The keywords starting each extractor.
+pub const EXTRACTOR_KEYWORDS: &[&str] = &[
+    "name",
+    "text",
+    "html",
+    "inner-html",
+    "attrs",
+    "classes",
+    "id",
+    "page-url",
+    "page-depth",
+    "status-code",
+    "outgoing-links-count",
+    "internal-links-count",
+    "external-links-count",
+    "attr",
+    "parent",
+    "children",
+    "select-any-xpath",
+    "select-all-xpath",
+    "select-any",
+    "select-all",
+];
+
 impl Parseable for Extractor {
     fn parse(i: &str) -> IResult<&str, Result<Extractor, String>> {
         alt((
@@ -137,6 +187,18 @@ impl Parseable for Extractor {
             map(tag("attrs"), |_| Ok(Extractor::Attrs)),
             map(tag("classes"), |_| Ok(Extractor::Classes)),
             map(tag("id"), |_| Ok(Extractor::Id)),
+            map(tag("page-url"), |_| Ok(Extractor::PageUrl)),
+            map(tag("page-depth"), |_| Ok(Extractor::PageDepth)),
+            map(tag("status-code"), |_| Ok(Extractor::StatusCode)),
+            map(tag("outgoing-links-count"), |_| {
+                Ok(Extractor::OutgoingLinksCount)
+            }),
+            map(tag("internal-links-count"), |_| {
+                Ok(Extractor::InternalLinksCount)
+            }),
+            map(tag("external-links-count"), |_| {
+                Ok(Extractor::ExternalLinksCount)
+            }),
             map(
                 tuple((tag_whitespace("attr"), escaped_string)),
                 |(_, attr)| Ok(Extractor::Attr(attr.into_boxed_str())),
@@ -159,6 +221,32 @@ impl Parseable for Extractor {
                 )),
                 |(_, _, extractor, _)| Ok(Extractor::Children(Box::new(extractor?))),
             ),
+            map(
+                tuple((
+                    tag_whitespace("select-any-xpath"),
+                    tag_whitespace("("),
+                    trailing_whitespace(extractor_expression::<Extractor>),
+                    tag_whitespace(","),
+                    trailing_whitespace(xpath),
+                    tag(")"),
+                )),
+                |(_, _, extractor, _, xpath, _)| {
+                    Ok(Extractor::SelectAnyXPath(Box::new(extractor?), xpath?))
+                },
+            ),
+            map(
+                tuple((
+                    tag_whitespace("select-all-xpath"),
+                    tag_whitespace("("),
+                    trailing_whitespace(extractor_expression::<Extractor>),
+                    tag_whitespace(","),
+                    trailing_whitespace(xpath),
+                    tag(")"),
+                )),
+                |(_, _, extractor, _, xpath, _)| {
+                    Ok(Extractor::SelectAllXPath(Box::new(extractor?), xpath?))
+                },
+            ),
             map(
                 tuple((
                     tag_whitespace("select-any"),
@@ -185,10 +273,86 @@ impl Parseable for Extractor {
                     Ok(Extractor::SelectAll(Box::new(extractor?), selector?))
                 },
             ),
+            map(
+                tuple((
+                    tag_whitespace("{"),
+                    separated_list0(
+                        tag_whitespace(","),
+                        tuple((
+                            trailing_whitespace(identifier),
+                            tag_whitespace(":"),
+                            trailing_whitespace(extractor_expression::<Extractor>),
+                        )),
+                    ),
+                    opt(tag_whitespace(",")),
+                    tag("}"),
+                )),
+                |(_, field_list, _, _)| {
+                    let mut fields: Vec<(Box<str>, _)> = vec![];
+
+                    for (name, _, field) in field_list {
+                        if fields.iter().any(|(existing, _)| existing.as_ref() == name) {
+                            return Err(format!("field `{}` defined more than once", name));
+                        }
+
+                        fields.push((name.into(), field?));
+                    }
+
+                    Ok(Extractor::Object(fields))
+                },
+            ),
         ))(i)
     }
 }
 
+/// The first word of a piece of code.
+#[cfg(test)]
+pub(super) fn first_word(code: &str) -> &str {
+    code.split(|ch: char| !ch.is_alphanumeric() && ch != '-')
+        .next()
+        .unwrap_or_default()
+}
+
+#[test]
+fn extractor_keywords_test() {
+    let samples = [
+        "name",
+        "text",
+        "html",
+        "inner-html",
+        "attrs",
+        "classes",
+        "id",
+        "page-url",
+        "page-depth",
+        "status-code",
+        "outgoing-links-count",
+        "internal-links-count",
+        "external-links-count",
+        "attr \"href\"",
+        "parent(text)",
+        "children(text)",
+        "select-any-xpath(text, \"//a\")",
+        "select-all-xpath(text, \"//a\")",
+        "select-any(text, a)",
+        "select-all(text, a)",
+    ];
+
+    for sample in samples {
+        let (rest, extractor) = Extractor::parse(sample).unwrap();
+        assert_eq!(rest, "", "{sample}");
+        assert_eq!(extractor.unwrap().to_string(), sample);
+    }
+
+    assert_eq!(
+        samples
+            .iter()
+            .map(|sample| first_word(sample))
+            .collect::<Vec<_>>(),
+        EXTRACTOR_KEYWORDS
+    );
+}
+
 #[test]
 fn extractor_test() {
     assert_eq!(Extractor::parse("name"), Ok(("", Ok(Extractor::Name))));
@@ -200,6 +364,10 @@ fn extractor_test() {
         Extractor::parse("inner-html"),
         Ok(("", Ok(Extractor::InnerHtml)))
     );
+    assert_eq!(
+        Extractor::parse("outgoing-links-count"),
+        Ok(("", Ok(Extractor::OutgoingLinksCount)))
+    );
     assert_eq!(
         Extractor::parse("select-all(text pretty, li)"),
         Ok((
@@ -217,6 +385,42 @@ fn extractor_test() {
     );
 }
 
+#[test]
+fn xpath_extractor_test() {
+    let (_, select_any) =
+        Extractor::parse("select-any-xpath(text, \"following-sibling::dd[1]\")").unwrap();
+    let select_any = select_any.unwrap();
+
+    assert_eq!(
+        select_any.to_string(),
+        "select-any-xpath(text, \"following-sibling::dd[1]\")"
+    );
+    assert_eq!(select_any.type_of().unwrap(), crate::Type::String);
+    assert!(Extractor::parse("select-all-xpath(text, \"//dd[\")")
+        .unwrap()
+        .1
+        .is_err());
+}
+
+#[test]
+fn object_extractor_test() {
+    let (_, object) = Extractor::parse(
+        "{ name: select-any(text, .name), price: attr \"data-price\" as-number, }",
+    )
+    .unwrap();
+    let object = object.unwrap();
+
+    assert_eq!(
+        object.to_string(),
+        "{name: select-any(text, .name), price: attr \"data-price\" as-number}"
+    );
+    assert_eq!(
+        object.type_of().unwrap(),
+        "{name: string, price: number}".parse().unwrap()
+    );
+    assert!(Extractor::parse("{ a: text, a: name }").unwrap().1.is_err());
+}
+
 #[test]
 fn extractor_expression_test() {
     assert_eq!(
@@ -308,6 +512,83 @@ fn aggregator_expression_test() {
     )
 }
 
+#[test]
+fn statistical_aggregator_test() {
+    for expression in [
+        "min(attr \"price\" as-number)",
+        "max(text)",
+        "mean(text as-number)",
+        "last(text)",
+        "nth(2, text)",
+        "any(text is-empty)",
+        "all(text is-empty)",
+        "percentile(90, text as-number)",
+        "percentile(12.5, text as-number)",
+        "count-by(name)",
+    ] {
+        let (rest, parsed) = aggregator::<Extractor>(expression).unwrap();
+        assert_eq!(rest, "");
+        let parsed = parsed.unwrap();
+        assert_eq!(parsed.to_string(), expression);
+        assert!(parsed.type_of().is_ok(), "{}", expression);
+    }
+
+    assert_eq!(
+        aggregator::<Extractor>("count-by(name)")
+            .unwrap()
+            .1
+            .unwrap()
+            .type_of()
+            .unwrap(),
+        crate::Type::Map(Box::new(crate::Type::Number))
+    );
+    assert!(aggregator::<Extractor>("percentile(101, text as-number)")
+        .unwrap()
+        .1
+        .is_err());
+    assert!(aggregator::<Extractor>("mean(text)")
+        .unwrap()
+        .1
+        .unwrap()
+        .type_of()
+        .is_err());
+}
+
+#[test]
+fn aggregator_keywords_test() {
+    let samples = [
+        "count-by(name)",
+        "count",
+        "first(text)",
+        "collect(text)",
+        "distinct(text)",
+        "sum(text as-number)",
+        "min(text)",
+        "max(text)",
+        "mean(text as-number)",
+        "last(text)",
+        "any(text is-empty)",
+        "all(text is-empty)",
+        "nth(2, text)",
+        "percentile(90, text as-number)",
+        "group(name, count)",
+    ];
+
+    for sample in samples {
+        let (rest, parsed) = aggregator::<Extractor>(sample).unwrap();
+        assert_eq!(rest, "", "{sample}");
+        assert!(parsed.is_ok(), "{sample}");
+    }
+
+    assert_eq!(
+        samples
+            .iter()
+            .map(|sample| first_word(sample))
+            .collect::<Vec<_>>(),
+        AGGREGATOR_KEYWORDS
+    );
+}
+
 fn in_directive(i: &str) -> IResult<&str, Result<Regex, String>> {
     map(
         tuple((tag_whitespace("in"), escaped_string)),
@@ -327,7 +608,7 @@ fn in_directive_test() {
     );
 }
 
-fn string_directive(
+pub(super) fn string_directive(
     directive_tags: &'static [&'static str],
 ) -> impl Fn(&str) -> IResult<&str, String> {
     move |i: &str| {
@@ -350,7 +631,9 @@ fn string_directive_test() {
     );
 }
 
-fn flag_directive(directive_tags: &'static [&'static str]) -> impl Fn(&str) -> IResult<&str, ()> {
+pub(super) fn flag_directive(
+    directive_tags: &'static [&'static str],
+) -> impl Fn(&str) -> IResult<&str, ()> {
     move |i: &str| {
         map(
             tuple((tags_whitespace(directive_tags), tag(";"))),
@@ -364,48 +647,197 @@ fn flag_directive_test() {
     assert_eq!(flag_directive(&["foo"])("foo;"), Ok(("", ())));
 }
 
-#[serde_with::serde_as]
+/// A keyword, as long as it is not the start of a longer word.
+fn keyword<'a>(keyword: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    trailing_whitespace(terminated(
+        tag(keyword),
+        not(satisfy(|ch: char| {
+            ch.is_alphanumeric() || ch == '_' || ch == '-'
+        })),
+    ))
+}
+
+fn comparison(i: &str) -> IResult<&str, Comparison> {
+    alt((
+        map(tag("=="), |_| Comparison::Equal),
+        map(tag("!="), |_| Comparison::NotEqual),
+        map(tag("<="), |_| Comparison::LesserOrEqual),
+        map(tag(">="), |_| Comparison::GreaterOrEqual),
+        map(tag("<"), |_| Comparison::Lesser),
+        map(tag(">"), |_| Comparison::Greater),
+    ))(i)
+}
+
+fn condition_atom(i: &str) -> IResult<&str, Result<Condition, String>> {
+    alt((
+        map(tuple((keyword("not"), condition_atom)), |(_, inner)| {
+            Ok(Condition::Not(Box::new(inner?)))
+        }),
+        map(
+            tuple((
+                tag_whitespace("("),
+                trailing_whitespace(condition),
+                tag(")"),
+            )),
+            |(_, inner, _)| inner,
+        ),
+        map(
+            tuple((
+                tag_whitespace("exists"),
+                tag_whitespace("("),
+                trailing_whitespace(alt((
+                    map(tuple((tag_whitespace("xpath"), xpath)), |(_, xpath)| {
+                        Ok::<_, String>(ElementSelector::XPath(xpath?))
+                    }),
+                    map(escaped_string, |selector| {
+                        Ok(ElementSelector::Css(selector.parse()?))
+                    }),
+                ))),
+                tag(")"),
+            )),
+            |(_, _, selector, _)| Ok(Condition::Exists(selector?)),
+        ),
+        map(
+            tuple((
+                trailing_whitespace(extractor_expression::<Extractor>),
+                trailing_whitespace(comparison),
+                literal,
+            )),
+            |(extractor_expr, comparison, literal)| {
+                Ok(Condition::Compare(extractor_expr?, comparison, literal))
+            },
+        ),
+    ))(i)
+}
+
+/// Conditions over the page, with `and` binding tighter than `or`.
+pub(super) fn condition(i: &str) -> IResult<&str, Result<Condition, String>> {
+    let and = |i| {
+        map(
+            tuple((
+                condition_atom,
+                many0(preceded(
+                    tuple((whitespace, keyword("and"))),
+                    condition_atom,
+                )),
+            )),
+            |(first, rest)| {
+                rest.into_iter().try_fold(first?, |lhs, rhs| {
+                    Ok::<_, String>(Condition::And(Box::new(lhs), Box::new(rhs?)))
+                })
+            },
+        )(i)
+    };
+
+    map(
+        tuple((
+            and,
+            many0(preceded(tuple((whitespace, keyword("or"))), and)),
+        )),
+        |(first, rest)| {
+            rest.into_iter().try_fold(first?, |lhs, rhs| {
+                Ok::<_, String>(Condition::Or(Box::new(lhs), Box::new(rhs?)))
+            })
+        },
+    )(i)
+}
+
+#[test]
+fn condition_test() {
+    for expression in [
+        "exists(\"meta[property='og:type'][content='product']\")",
+        "exists(xpath \"//dt\")",
+        "status-code == 200",
+        "page-depth > 3 and outgoing-links-count >= 100",
+        "not exists(\".price\") or page-url matches \"/p/\" == true",
+        "(page-depth < 1 or page-depth > 3) and page-url != \"https://example.foo/\"",
+        "not (status-code == 200 and page-depth <= 2)",
+    ] {
+        let (rest, parsed) = condition(expression).unwrap();
+        assert_eq!(rest, "");
+        let parsed = parsed.unwrap();
+        assert_eq!(parsed.to_string(), expression);
+        assert!(parsed.type_check().is_ok(), "{}", expression);
+    }
+
+    let (rest, parsed) = condition("status-code == 200 .price {").unwrap();
+    assert_eq!(rest, " .price {");
+    assert!(parsed.is_ok());
+    let (rest, _) = condition("status-code == 200 order {").unwrap();
+    assert_eq!(rest, " order {");
+
+    assert!(condition("page-url > 3")
+        .unwrap()
+        .1
+        .unwrap()
+        .type_check()
+        .is_err());
+    assert!(condition("page-url < \"a\"")
+        .unwrap()
+        .1
+        .unwrap()
+        .type_check()
+        .is_err());
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RuleSet {
     #[serde(with = "serde_regex")]
     pub in_page: Option<Regex>,
-    #[serde_as(as = "DisplayFromStr")]
-    pub selector: Selector,
+    /// Only pages for which this holds get analyzed by this rule set.
+    pub when: Option<Condition>,
+    pub selector: ElementSelector,
     pub aggregators: HashMap<String, AggregatorExpression<Extractor>>,
 }
 
-fn rule_set(i: &str) -> IResult<&str, Result<RuleSet, String>> {
+fn rule_set(i: &str) -> IResult<&str, Result<RuleSet, Vec<Spanned<'_, String>>>> {
     map(
         block(
             tuple((
                 tag_whitespace("select"),
                 opt(trailing_whitespace(in_directive)),
-                css_selector('{'),
+                opt(trailing_whitespace(preceded(keyword("when"), condition))),
+                element_selector('{'),
             )),
-            identified_value(aggregator_expression::<Extractor>),
+            recovering(identified_value(aggregator_expression::<Extractor>)),
         ),
-        |((_, in_page, selector), aggregator_list)| {
+        |((_, in_page, when, selector), entries)| {
+            let mut errors = vec![];
             let mut aggregators = HashMap::new();
 
-            for (identifier, aggregator) in aggregator_list {
-                if aggregators.contains_key(identifier) {
-                    return Err(format!("rule `{}` defined more than once", identifier));
+            for (at, entry) in entries {
+                match entry {
+                    Ok((identifier, _)) if aggregators.contains_key(identifier) => {
+                        errors.push((at, format!("rule `{}` defined more than once", identifier)))
+                    }
+                    Ok((identifier, Ok(aggregator))) => {
+                        aggregators.insert(identifier.to_owned(), aggregator);
+                    }
+                    Ok((_, Err(err))) | Err(err) => errors.push((at, err)),
                 }
-
-                aggregators.insert(identifier.to_owned(), aggregator?);
             }
 
-            Ok(RuleSet {
-                in_page: in_page.transpose()?,
-                selector: selector?,
-                aggregators,
-            })
+            let in_page = in_page.transpose().map_err(|err| errors.push((i, err)));
+            let when = when.transpose().map_err(|err| errors.push((i, err)));
+            let selector = selector.map_err(|err| errors.push((i, err)));
+
+            match (in_page, when, selector) {
+                (Ok(in_page), Ok(when), Ok(selector)) if errors.is_empty() => Ok(RuleSet {
+                    in_page,
+                    when,
+                    selector,
+                    aggregators,
+                }),
+                _ => Err(errors),
+            }
         },
     )(i)
 }
 
 #[test]
 fn rule_set_test() {
+    let (_, xpath) = rule_set("select xpath \"//dt\" { foo: first(text); }").unwrap();
+    assert_eq!(xpath.unwrap().selector.to_string(), "xpath \"//dt\"");
     rule_set("select td > a[href^=\"https\"] { foo: first ( text ) ; }")
         .unwrap()
         .1
@@ -418,6 +850,17 @@ fn rule_set_test() {
         .unwrap()
         .1
         .unwrap();
+    let (_, when) = rule_set(
+        "select in \"/p/\" when exists(\"meta[property='og:type']\") .price { foo: first(text); }",
+    )
+    .unwrap();
+    let when = when.unwrap();
+    assert!(when.in_page.is_some());
+    assert_eq!(
+        when.when.unwrap().to_string(),
+        "exists(\"meta[property='og:type']\")"
+    );
+    assert_eq!(when.selector.to_string(), ".price");
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -425,7 +868,13 @@ pub struct Module {
     pub path: String,
 }
 
-fn module(i: &str) -> IResult<&str, Module> {
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "import {};", quote(&self.path))
+    }
+}
+
+pub(super) fn module(i: &str) -> IResult<&str, Module> {
     map(string_directive(&["import"]), |path| Module { path })(i)
 }
 
@@ -442,7 +891,7 @@ fn module_test() {
     );
 }
 
-fn seed(i: &str) -> IResult<&str, Result<Url, String>> {
+pub(super) fn seed(i: &str) -> IResult<&str, Result<Url, String>> {
     map(string_directive(&["seed"]), |seed| {
         seed.parse::<Url>().map_err(|err| err.to_string())
     })(i)
@@ -466,7 +915,22 @@ pub enum Boundary {
     UseAllParams,
 }
 
-fn boundary(i: &str) -> IResult<&str, Result<Boundary, String>> {
+impl fmt::Display for Boundary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Boundary::Allowed(allowed) => write!(f, "allow {};", quote(allowed.as_str())),
+            Boundary::Disallowed(disallowed) => {
+                write!(f, "disallow {};", quote(disallowed.as_str()))
+            }
+            Boundary::Frontier(frontier) => write!(f, "frontier {};", quote(frontier.as_str())),
+            Boundary::UseParam(param) => write!(f, "use param {};", quote(param)),
+            Boundary::IgnoreParam(param) => write!(f, "ignore param {};", quote(param)),
+            Boundary::UseAllParams => write!(f, "use param *;"),
+        }
+    }
+}
+
+pub(super) fn boundary(i: &str) -> IResult<&str, Result<Boundary, String>> {
     alt((
         map(string_directive(&["allow"]), |allowed| {
             Ok(Boundary::Allowed(regex(&allowed)?))
@@ -526,6 +990,42 @@ fn boundary_test() {
         ),
         b => panic!("got {:?}", b),
     }
+
+    for source in [
+        r#"allow "^https?://example\.foo/";"#,
+        r#"disallow "/\"quoted\"/";"#,
+        r#"frontier "\d+$";"#,
+        r#"use param "page";"#,
+        r#"ignore param "utm_\w+";"#,
+        "use param *;",
+    ] {
+        assert_eq!(boundary(source).unwrap().1.unwrap().to_string(), source);
+    }
+}
+
+/// Displays a literal value the way it is written in the directives.
+pub(super) struct Literal<'a>(pub &'a Value);
+
+impl<'a> fmt::Display for Literal<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Value::String(string) => write!(f, "{}", quote(string)),
+            Value::Array(array) => {
+                write!(f, "[")?;
+
+                for (idx, value) in array.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    write!(f, "{}", Literal(value))?;
+                }
+
+                write!(f, "]")
+            }
+            value => write!(f, "{}", value),
+        }
+    }
 }
 
 fn literal(i: &str) -> IResult<&str, Value> {
@@ -563,6 +1063,15 @@ fn literal_test() {
     assert_eq!(literal("-1234"), Ok(("", (-1234).into())));
     assert_eq!(literal("-1234.0"), Ok(("", (-1234.0).into())));
     assert_eq!(literal("1234.0"), Ok(("", (1234.0).into())));
+
+    for source in [
+        "\"a \\\"string\\\"\"",
+        "-1234",
+        "1.234",
+        "[\"a\", 1, [true]]",
+    ] {
+        assert_eq!(Literal(&literal(source).unwrap().1).to_string(), source);
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -571,7 +1080,13 @@ pub struct SetVariable {
     pub value: Value,
 }
 
-fn set_variable(i: &str) -> IResult<&str, SetVariable> {
+impl fmt::Display for SetVariable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "set {} = {};", self.name, Literal(&self.value))
+    }
+}
+
+pub(super) fn set_variable(i: &str) -> IResult<&str, SetVariable> {
     map(
         tuple((
             tag_whitespace("set"),
@@ -587,6 +1102,46 @@ fn set_variable(i: &str) -> IResult<&str, SetVariable> {
     )(i)
 }
 
+pub(super) fn definition(i: &str) -> IResult<&str, Result<Definition, String>> {
+    map(
+        tuple((
+            tag_whitespace("define"),
+            trailing_whitespace(definition_name),
+            opt(tuple((tag_whitespace(":"), trailing_whitespace(r#type)))),
+            tag_whitespace("="),
+            trailing_whitespace(transformer_expression),
+            tag(";"),
+        )),
+        |(_, name, input, _, expression, _)| {
+            if TRANSFORMER_KEYWORDS.contains(&name) {
+                return Err(format!("`{}` is already a transformer", name));
+            }
+
+            Ok(Definition {
+                name: name.to_owned(),
+                input: input.map(|(_, input)| input),
+                expression: expression?,
+                signature: None,
+            })
+        },
+    )(i)
+}
+
+#[test]
+fn definition_test() {
+    let (_, price) =
+        definition("define price-of: string = capture \"[0-9.]+\" get \"0\" as-number;").unwrap();
+    assert_eq!(
+        price.unwrap().to_string(),
+        "define price-of: string = capture \"[0-9.]+\" get \"0\" as-number;"
+    );
+    assert!(definition("define pretty = pretty;").unwrap().1.is_err());
+    assert!(definition("define price-of = capture \"(\";")
+        .unwrap()
+        .1
+        .is_err());
+}
+
 #[test]
 fn set_variable_test() {
     assert_eq!(
@@ -627,7 +1182,13 @@ pub struct WebDriver {
     pub regex: regex::Regex,
 }
 
-fn web_driver(i: &str) -> IResult<&str, Result<WebDriver, String>> {
+impl fmt::Display for WebDriver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "use webdriver on {};", quote(self.regex.as_str()))
+    }
+}
+
+pub(super) fn web_driver(i: &str) -> IResult<&str, Result<WebDriver, String>> {
     map(string_directive(&["use", "webdriver", "on"]), |parsed| {
         Ok(WebDriver {
             regex: regex(&parsed)?,
@@ -635,6 +1196,190 @@ fn web_driver(i: &str) -> IResult<&str, Result<WebDriver, String>> {
     })(i)
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    /// The path to the HTML file, relative to the module declaring the test.
+    pub path: String,
+    /// The URL from which the HTML is supposed to have been downloaded.
+    pub url: Url,
+}
+
+impl fmt::Display for Fixture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fixture {} as {};",
+            quote(&self.path),
+            quote(self.url.as_str())
+        )
+    }
+}
+
+pub(super) fn fixture(i: &str) -> IResult<&str, Result<Fixture, String>> {
+    map(
+        tuple((
+            tag_whitespace("fixture"),
+            trailing_whitespace(escaped_string),
+            tag_whitespace("as"),
+            trailing_whitespace(escaped_string),
+            tag(";"),
+        )),
+        |(_, path, _, url, _)| {
+            Ok(Fixture {
+                path,
+                url: url.parse::<Url>().map_err(|err| err.to_string())?,
+            })
+        },
+    )(i)
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Expectation {
+    /// The name of the rule, relative to the module declaring the test.
+    pub rule_name: String,
+    pub value: Value,
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expect {} == {};", self.rule_name, Literal(&self.value))
+    }
+}
+
+/// A rule name, possibly qualified with a module path.
+fn rule_path(i: &str) -> IResult<&str, &str> {
+    recognize(tuple((identifier, many0(tuple((tag("."), identifier))))))(i)
+}
+
+pub(super) fn expectation(i: &str) -> IResult<&str, Expectation> {
+    map(
+        tuple((
+            tag_whitespace("expect"),
+            trailing_whitespace(rule_path),
+            tag_whitespace("=="),
+            trailing_whitespace(alt((map(tag("null"), |_| Value::Null), literal))),
+            tag(";"),
+        )),
+        |(_, rule_name, _, value, _)| Expectation {
+            rule_name: rule_name.to_owned(),
+            value,
+        },
+    )(i)
+}
+
+#[test]
+fn expectation_test() {
+    assert_eq!(
+        expectation("expect seo.title == \"Hello\";"),
+        Ok((
+            "",
+            Expectation {
+                rule_name: "seo.title".to_owned(),
+                value: Value::String("Hello".to_owned()),
+            }
+        ))
+    );
+    assert_eq!(
+        expectation("expect count-h1 == null ;"),
+        Ok((
+            "",
+            Expectation {
+                rule_name: "count-h1".to_owned(),
+                value: Value::Null,
+            }
+        ))
+    );
+}
+
+/// A unit test for the rules in the directives, run against a saved page.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct DirectiveTest {
+    pub name: String,
+    pub fixture: Fixture,
+    pub expectations: Vec<Expectation>,
+}
+
+enum TestStatement {
+    Fixture(Fixture),
+    Expectation(Expectation),
+}
+
+fn directive_test(i: &str) -> IResult<&str, Result<DirectiveTest, Vec<Spanned<'_, String>>>> {
+    map(
+        block(
+            tuple((tag_whitespace("test"), escaped_string)),
+            recovering(alt((
+                map(fixture, |fixture| fixture.map(TestStatement::Fixture)),
+                map(expectation, |expectation| {
+                    Ok(TestStatement::Expectation(expectation))
+                }),
+            ))),
+        ),
+        |((_, name), statements)| {
+            let mut errors = vec![];
+            let mut fixture = None;
+            let mut expectations = vec![];
+
+            for (at, statement) in statements {
+                match statement.and_then(|statement| statement) {
+                    Ok(TestStatement::Fixture(_)) if fixture.is_some() => {
+                        errors.push((at, format!("test `{}` has more than one fixture", name)));
+                    }
+                    Ok(TestStatement::Fixture(found)) => fixture = Some(found),
+                    Ok(TestStatement::Expectation(expectation)) => expectations.push(expectation),
+                    Err(err) => errors.push((at, err)),
+                }
+            }
+
+            match fixture {
+                Some(fixture) if errors.is_empty() => Ok(DirectiveTest {
+                    fixture,
+                    name,
+                    expectations,
+                }),
+                None if errors.is_empty() => {
+                    Err(vec![(i, format!("test `{}` has no fixture", name))])
+                }
+                _ => Err(errors),
+            }
+        },
+    )(i)
+}
+
+#[test]
+fn directive_test_test() {
+    assert_eq!(
+        directive_test(
+            "test \"home\" {\n    fixture \"home.html\" as \"https://example.foo/\";\n    \
+                expect title == \"Home\";\n    expect count-h1 == 1;\n}"
+        ),
+        Ok((
+            "",
+            Ok(DirectiveTest {
+                name: "home".to_owned(),
+                fixture: Fixture {
+                    path: "home.html".to_owned(),
+                    url: Url::parse("https://example.foo/").unwrap(),
+                },
+                expectations: vec![
+                    Expectation {
+                        rule_name: "title".to_owned(),
+                        value: Value::String("Home".to_owned()),
+                    },
+                    Expectation {
+                        rule_name: "count-h1".to_owned(),
+                        value: 1.into(),
+                    },
+                ],
+            })
+        ))
+    );
+    assert!(directive_test("test \"nothing\" { expect title == 1; }")
+        .unwrap()
+        .1
+        .is_err());
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[non_exhaustive]
 pub enum Item {
@@ -642,20 +1387,32 @@ pub enum Item {
     Boundary(Boundary),
     Module(Module),
     RuleSet(Arc<RuleSet>),
+    Define(Arc<Definition>),
     SetVariable(SetVariable),
     WebDriver(WebDriver),
+    Test(DirectiveTest),
 }
 
-fn item(i: &str) -> IResult<&str, Result<Item, String>> {
+fn item(i: &str) -> IResult<&str, Result<Item, Vec<Spanned<'_, String>>>> {
+    let at_item = |err: String| vec![(i, err)];
+
     alt((
         map(rule_set, |rule_set| Ok(Item::RuleSet(Arc::new(rule_set?)))),
+        map(definition, move |definition| {
+            Ok(Item::Define(Arc::new(definition.map_err(at_item)?)))
+        }),
         map(module, |module| Ok(Item::Module(module))),
-        map(seed, |seed| Ok(Item::Seed(seed?))),
-        map(boundary, |boundary| Ok(Item::Boundary(boundary?))),
+        map(seed, move |seed| Ok(Item::Seed(seed.map_err(at_item)?))),
+        map(boundary, move |boundary| {
+            Ok(Item::Boundary(boundary.map_err(at_item)?))
+        }),
         map(set_variable, |set_variable| {
             Ok(Item::SetVariable(set_variable))
         }),
-        map(web_driver, |web_driver| Ok(Item::WebDriver(web_driver?))),
+        map(web_driver, move |web_driver| {
+            Ok(Item::WebDriver(web_driver.map_err(at_item)?))
+        }),
+        map(directive_test, |test| Ok(Item::Test(test?))),
     ))(i)
 }
 
@@ -677,21 +1434,138 @@ fn item_test() {
     //     ));
 }
 
-pub fn entrypoint(i: &str) -> Result<Result<Vec<Item>, String>, ParseError> {
-    ParseError::map_iresult(
-        i,
-        all_consuming(map(
-            tuple((whitespace, many0(trailing_whitespace(item)))),
-            |(_, results)| results.into_iter().collect::<Result<Vec<_>, _>>(),
-        ))(i),
-    )
+/// The keywords of items and of the heads of rule sets. Directives of more
+/// than one word are written in full.
+pub const ITEM_KEYWORDS: &[&str] = &[
+    "import",
+    "seed",
+    "allow",
+    "disallow",
+    "frontier",
+    "use param",
+    "ignore param",
+    "use webdriver on",
+    "set",
+    "define",
+    "select",
+    "xpath",
+    "in",
+    "when",
+    "exists",
+    "and",
+    "or",
+    "test",
+    "fixture",
+    "expect",
+];
+
+#[test]
+fn item_keywords_test() {
+    let source = "import \"sub\";\nseed \"https://example.foo/\";\nallow \"a\";\n\
+        disallow \"b\";\nfrontier \"c\";\nuse param \"d\";\nignore param \"e\";\n\
+        use webdriver on \"f\";\nset quota = 1;\ndefine g = trim;\n\
+        select xpath \"//h\" {}\nselect in \"i\" when exists(\"j\") and status-code == 200 \
+        or page-depth == 1 k {}\ntest \"l\" { fixture \"m.html\" as \"https://example.foo/\"; \
+        expect n == 1; }\n";
+    let (items, errors) = entrypoint(source);
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(items.len(), 13);
+
+    let words = source
+        .split(|ch: char| !ch.is_alphanumeric() && ch != '-')
+        .collect::<Vec<_>>();
+
+    for keyword in ITEM_KEYWORDS {
+        for part in keyword.split(' ') {
+            assert!(words.contains(&part), "{keyword}");
+        }
+    }
+}
+
+/// Parses a whole module, returning each item together with the position
+/// where it was declared. Parsing does not stop at the first error: bad items
+/// and bad entries within blocks are skipped and all errors found are
+/// returned together with their positions.
+pub fn entrypoint(text: &str) -> (Vec<Located<Item>>, Vec<Located<String>>) {
+    let mut items = vec![];
+    let mut errors = vec![];
+    let mut i = whitespace(text).map(|(i, _)| i).unwrap_or(text);
+
+    while !i.is_empty() {
+        match located(text, item)(i) {
+            Ok((rest, (position, Ok(item)))) => {
+                items.push((position, item));
+                i = rest;
+            }
+            Ok((rest, (_, Err(item_errors)))) => {
+                errors.extend(
+                    item_errors
+                        .into_iter()
+                        .map(|(at, err)| (Position::of(text, at), err)),
+                );
+                i = rest;
+            }
+            Err(err) => {
+                let error = ParseError::new(text, err);
+                errors.push((error.position(), error.message()));
+                i = skip_item(i);
+            }
+        }
+
+        i = whitespace(i).map(|(i, _)| i).unwrap_or(i);
+    }
+
+    (items, errors)
 }
 
 #[test]
 fn entrypoint_test() {
-    dbg!(entrypoint(
-        "select * { } set foo = \"bar\"; allow \"foo\";\n"
-    ))
-    .unwrap()
-    .unwrap();
+    let (items, errors) = dbg!(entrypoint(
+        "select * { } set foo = \"bar\";\n\n  allow \"foo\";\n"
+    ));
+    assert!(errors.is_empty());
+
+    assert_eq!(
+        items
+            .iter()
+            .map(|(position, _)| position.line())
+            .collect::<Vec<_>>(),
+        vec![1, 1, 3]
+    );
+}
+
+#[test]
+fn entrypoint_recovery_test() {
+    let (items, errors) = entrypoint(
+        "seed \"not a url\";\nallow \"a\";\nfoo bar { baz; }\n  allow \"(\";\nallow \"b\";",
+    );
+
+    assert_eq!(items.len(), 2);
+    assert_eq!(
+        errors
+            .iter()
+            .map(|(position, _)| (position.line(), position.column()))
+            .collect::<Vec<_>>(),
+        vec![(1, 1), (3, 1), (4, 3)]
+    );
+}
+
+#[test]
+fn entrypoint_recovery_within_items_test() {
+    let (items, errors) = entrypoint(
+        "select a {\n    x: first(text;\n    y: first(text);\n    y: first(text);\n}\n\
+            test \"t\" {\n    expect a = 1;\n    fixture \"a.html\" as \"not a url\";\n}\n\
+            allow \"(\";\nseed \"https://example.foo/\";",
+    );
+
+    assert_eq!(items.len(), 1);
+    assert_eq!(
+        errors
+            .iter()
+            .map(|(position, _)| (position.line(), position.column()))
+            .collect::<Vec<_>>(),
+        vec![(2, 8), (4, 5), (7, 14), (8, 5), (10, 1)]
+    );
+    assert_eq!(errors[1].1, "rule `y` defined more than once");
+    assert_eq!(errors[3].1, "relative URL without a base");
 }
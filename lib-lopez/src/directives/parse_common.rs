@@ -132,6 +132,18 @@ bar"#
     );
 }
 
+/// Writes a string in the syntax understood by [`escaped_string`].
+pub fn quote(string: &str) -> String {
+    format!("\"{}\"", string.replace('"', "\\\""))
+}
+
+#[test]
+fn quote_test() {
+    for string in ["foo", "foo\"bar", "foo\\.bar", "\\\\\"", "a\nb"] {
+        assert_eq!(escaped_string(&quote(string)), Ok(("", string.to_owned())));
+    }
+}
+
 pub fn regex(parsed: &str) -> Result<Regex, String> {
     Regex::from_str(parsed).map_err(|err| format!("{}", err))
 }
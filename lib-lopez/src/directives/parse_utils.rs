@@ -1,9 +1,10 @@
-use nom::error::Error;
+use nom::error::{Error, ErrorKind};
 use nom::Err;
 use nom::IResult;
+use serde_derive::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     line: usize,
     column: usize,
@@ -16,7 +17,17 @@ impl fmt::Display for Position {
 }
 
 impl Position {
-    fn of(text: &str, fragment: &str) -> Position {
+    /// The line of this position, starting from 1.
+    pub fn line(&self) -> usize {
+        self.line + 1
+    }
+
+    /// The column of this position, starting from 1.
+    pub fn column(&self) -> usize {
+        self.column + 1
+    }
+
+    pub(super) fn of(text: &str, fragment: &str) -> Position {
         let fragment_pos = text.len() - fragment.len();
         let mut line = 0;
         let mut column = 0;
@@ -34,6 +45,25 @@ impl Position {
     }
 }
 
+/// Something, together with the position where it starts in the source.
+pub type Located<T> = (Position, T);
+
+/// Wraps a parser so that it also returns the position in `text` where its
+/// match started.
+pub fn located<'a, F, T>(
+    text: &'a str,
+    mut f: F,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Located<T>>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, T>,
+{
+    move |i| {
+        let position = Position::of(text, i);
+        let (i, output) = f(i)?;
+        Ok((i, (position, output)))
+    }
+}
+
 #[derive(Debug)]
 pub struct ParseError {
     position: Position,
@@ -71,10 +101,119 @@ impl ParseError {
         }
     }
 
-    pub fn map_iresult<T>(text: &str, iresult: IResult<&str, T>) -> Result<T, ParseError> {
-        match iresult {
-            Ok((_left_over, result)) => Ok(result),
-            Err(err) => Err(ParseError::new(text, err)),
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// The error message, without the position.
+    pub fn message(&self) -> String {
+        format!("syntax error near {:?}: {}", self.hint, self.message)
+    }
+}
+
+/// Skips the input until the first `;` or `}` outside any block. Strings and
+/// comments are skipped as a whole; unbalanced parentheses are not, so that
+/// a `;` still ends a broken expression. A closing `}` is consumed only if
+/// `consume_close` is set.
+fn skip_until_end(i: &str, consume_close: bool) -> &str {
+    let mut depth = 0usize;
+    let mut chars = i.char_indices().peekable();
+
+    while let Some((pos, ch)) = chars.next() {
+        match ch {
+            '"' => {
+                while let Some((_, ch)) = chars.next() {
+                    match ch {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if matches!(chars.peek(), Some((_, '/'))) => {
+                for (_, ch) in chars.by_ref() {
+                    if ch == '\n' {
+                        break;
+                    }
+                }
+            }
+            '}' if depth == 0 && !consume_close => return &i[pos..],
+            '{' => depth += 1,
+            '}' => {
+                depth = depth.saturating_sub(1);
+
+                if depth == 0 {
+                    return &i[pos + 1..];
+                }
+            }
+            ';' if depth == 0 => return &i[pos + 1..],
+            _ => {}
         }
     }
+
+    ""
+}
+
+/// Skips the input until the end of the current item: the first `;` or the
+/// first closing `}` outside any block. Strings and comments are skipped
+/// as a whole. This is used to recover from parse errors.
+pub fn skip_item(i: &str) -> &str {
+    skip_until_end(i, true)
+}
+
+/// Skips the input until the end of the current entry of a block: the first
+/// `;` outside any inner block or the `}` closing the block, which is not
+/// consumed. This is used to recover from parse errors within a block.
+pub fn skip_entry(i: &str) -> &str {
+    skip_until_end(i, false)
+}
+
+/// Something, together with the input where it starts. This becomes a
+/// [`Located`] once the whole text is known.
+pub type Spanned<'a, T> = (&'a str, T);
+
+/// Wraps the parser of the entries of a block so that a bad entry is skipped
+/// and its syntax error returned, instead of failing the whole block. Fails
+/// only at the end of the block, so that it can be repeated.
+pub fn recovering<'a, F, T>(
+    mut f: F,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Spanned<'a, Result<T, String>>>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, T>,
+{
+    move |i| {
+        if i.is_empty() || i.starts_with('}') {
+            return Err(Err::Error(Error::new(i, ErrorKind::Eof)));
+        }
+
+        match f(i) {
+            Ok((rest, output)) => Ok((rest, (i, Ok(output)))),
+            Err(Err::Error(error)) | Err(Err::Failure(error)) => {
+                let input = error.input;
+                let message = ParseError::new(input, Err::Error(error)).message();
+                Ok((skip_entry(i), (input, Err(message))))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[test]
+fn skip_item_test() {
+    assert_eq!(skip_item("seed foo; allow \"bar\";"), " allow \"bar\";");
+    assert_eq!(
+        skip_item("select a { x: \"};\" // }\n ; } seed \"a\";"),
+        " seed \"a\";"
+    );
+    assert_eq!(skip_item("select a { b: c"), "");
+}
+
+#[test]
+fn skip_entry_test() {
+    assert_eq!(skip_entry("a: b; c: d; }"), " c: d; }");
+    assert_eq!(skip_entry("a: first(\"}\") }"), "}");
+    assert_eq!(skip_entry("a: { b; } c; }"), " c; }");
+    assert_eq!(skip_entry("a: first(text; b: c; }"), " b: c; }");
 }
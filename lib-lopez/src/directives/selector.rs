@@ -1,10 +1,16 @@
 //! Because people didn't bother with selector serialization!
 //!
 
+use scraper::{ElementRef, Html};
+use serde_derive::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
 use std::fmt::Display;
 use std::ops::Deref;
 use std::str::FromStr;
 
+use super::parse_common::quote;
+use super::xpath::XPath;
+
 #[derive(Debug)]
 pub struct Selector {
     selector: scraper::Selector,
@@ -16,7 +22,7 @@ impl FromStr for Selector {
     fn from_str(i: &str) -> Result<Selector, String> {
         Ok(Selector {
             selector: scraper::Selector::parse(i).map_err(|err| format!("{err:?}"))?,
-            original: i.to_owned(),
+            original: i.trim().to_owned(),
         })
     }
 }
@@ -39,3 +45,29 @@ impl PartialEq for Selector {
         self.selector.eq(&other.selector)
     }
 }
+
+/// What picks the elements a rule set runs on.
+#[serde_as]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum ElementSelector {
+    Css(#[serde_as(as = "DisplayFromStr")] Selector),
+    XPath(#[serde_as(as = "DisplayFromStr")] XPath),
+}
+
+impl ElementSelector {
+    pub fn select<'a>(&self, html: &'a Html) -> Vec<ElementRef<'a>> {
+        match self {
+            ElementSelector::Css(selector) => html.select(selector).collect(),
+            ElementSelector::XPath(xpath) => xpath.select(html),
+        }
+    }
+}
+
+impl Display for ElementSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ElementSelector::Css(selector) => write!(f, "{}", selector),
+            ElementSelector::XPath(xpath) => write!(f, "xpath {}", quote(&xpath.to_string())),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::Directives;
+
+static NEXT_TEST_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory with some modules in it, removed when dropped. The
+/// first module is the main one.
+pub(crate) struct TestDir {
+    path: PathBuf,
+    main: PathBuf,
+}
+
+impl TestDir {
+    /// Writes `(file name, source)` pairs into a directory of their own.
+    pub(crate) fn new(modules: &[(&str, &str)]) -> TestDir {
+        let path = std::env::temp_dir().join(format!(
+            "lopez-test-{}-{}",
+            std::process::id(),
+            NEXT_TEST_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).unwrap();
+
+        for (name, source) in modules {
+            fs::write(path.join(name), source).unwrap();
+        }
+
+        TestDir {
+            main: path.join(modules[0].0),
+            path,
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn main(&self) -> &Path {
+        &self.main
+    }
+
+    /// Loads the main module, importing from this directory.
+    pub(crate) fn load(&self) -> Result<Directives, anyhow::Error> {
+        Directives::load(&self.main, &self.path)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.path).ok();
+    }
+}
+
+/// Loads directives from the given modules, the first being the main one.
+/// The directory they are in lives as long as the returned [`TestDir`].
+pub(crate) fn load_test_directives(modules: &[(&str, &str)]) -> (Directives, TestDir) {
+    let dir = TestDir::new(modules);
+    let directives = dir.load().unwrap();
+
+    (directives, dir)
+}
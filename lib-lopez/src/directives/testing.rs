@@ -0,0 +1,178 @@
+//! Runs the unit tests declared with `test` items in the directives against
+//! their fixtures.
+
+use http::StatusCode;
+use scraper::Html;
+use serde_derive::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::crawler::PageInfo;
+
+use super::directives::{canonical_path, full_rule_name, Analyzer};
+use super::expressions::force_f64;
+use super::extractor::Page;
+use super::parse::DirectiveTest;
+use super::Directives;
+
+/// Compares two JSON values, regardless of how numbers are represented (the
+/// literal `1` equals the float `1.0` coming from a `sum`, for example).
+fn json_eq(this: &Value, other: &Value) -> bool {
+    match (this, other) {
+        (Value::Number(this), Value::Number(other)) => force_f64(this) == force_f64(other),
+        (Value::Array(this), Value::Array(other)) => {
+            this.len() == other.len() && this.iter().zip(other).all(|(a, b)| json_eq(a, b))
+        }
+        (Value::Object(this), Value::Object(other)) => {
+            this.len() == other.len()
+                && this
+                    .iter()
+                    .all(|(key, a)| other.get(key).map(|b| json_eq(a, b)).unwrap_or(false))
+        }
+        (this, other) => this == other,
+    }
+}
+
+#[test]
+fn json_eq_test() {
+    assert!(json_eq(&1.into(), &1.0.into()));
+    assert!(json_eq(
+        &serde_json::json!({ "a": [1, "b"] }),
+        &serde_json::json!({ "a": [1.0, "b"] })
+    ));
+    assert!(!json_eq(
+        &serde_json::json!([1]),
+        &serde_json::json!([1, 2])
+    ));
+    assert!(!json_eq(&Value::Null, &false.into()));
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ExpectationFailure {
+    pub(crate) rule_name: String,
+    pub(crate) expected: Value,
+    /// This is `None` if no rule with the given name exists.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) got: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) enum TestOutcome {
+    Passed,
+    Failed(Vec<ExpectationFailure>),
+    Error(String),
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TestResult {
+    pub(crate) module: String,
+    pub(crate) name: String,
+    pub(crate) outcome: TestOutcome,
+}
+
+/// The outcome of running all tests declared in the directives.
+#[derive(Debug, Serialize)]
+pub struct DirectivesTestReport {
+    pub(crate) results: Vec<TestResult>,
+}
+
+impl DirectivesTestReport {
+    pub fn n_tests(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn n_passed(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|result| matches!(result.outcome, TestOutcome::Passed))
+            .count()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.n_passed() == self.n_tests()
+    }
+}
+
+fn run_test(
+    analyzer: &Analyzer,
+    module_name: &str,
+    module_path: &Path,
+    test: &DirectiveTest,
+) -> TestOutcome {
+    // Fixtures are relative to the module file:
+    let fixture_path = module_path
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(&test.fixture.path);
+    let content = match fs::read(&fixture_path) {
+        Ok(content) => content,
+        Err(err) => {
+            return TestOutcome::Error(format!(
+                "could not read fixture `{}`: {err}",
+                fixture_path.display()
+            ))
+        }
+    };
+
+    // Fixtures are taken as seeds, served with `200 OK`:
+    let html = Html::parse_document(&String::from_utf8_lossy(&content));
+    let page = Page::new(
+        PageInfo {
+            url: &test.fixture.url,
+            depth: 0,
+            status_code: StatusCode::OK,
+        },
+        &super::tree_search(&html),
+    );
+    let analyses = analyzer
+        .analyze(&page, &html)
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let mut failures = vec![];
+
+    for expectation in &test.expectations {
+        let rule_name = match canonical_path(&full_rule_name(module_name, &expectation.rule_name)) {
+            Ok(rule_name) => rule_name,
+            Err(err) => return TestOutcome::Error(err.to_string()),
+        };
+        let got = analyses.get(&rule_name);
+
+        if !got
+            .map(|got| json_eq(got, &expectation.value))
+            .unwrap_or(false)
+        {
+            failures.push(ExpectationFailure {
+                rule_name,
+                expected: expectation.value.clone(),
+                got: got.cloned(),
+            });
+        }
+    }
+
+    if failures.is_empty() {
+        TestOutcome::Passed
+    } else {
+        TestOutcome::Failed(failures)
+    }
+}
+
+impl Directives {
+    /// Runs all tests declared in all modules of these directives.
+    pub fn run_tests(&self) -> DirectivesTestReport {
+        let analyzer = self.analyzer();
+        let results = self
+            .tests()
+            .into_iter()
+            .map(|(module_name, module_path, test)| TestResult {
+                module: module_name.to_owned(),
+                name: test.name.clone(),
+                outcome: run_test(&analyzer, module_name, module_path, test),
+            })
+            .collect();
+
+        DirectivesTestReport { results }
+    }
+}
@@ -45,6 +45,17 @@ impl fmt::Display for Variable {
 }
 
 impl Variable {
+    /// All the variables that can be set.
+    pub const ALL: &'static [Variable] = &[
+        Variable::UserAgent,
+        Variable::Quota,
+        Variable::MaxDepth,
+        Variable::MaxHitsPerSec,
+        Variable::RequestTimeout,
+        Variable::MaxBodySize,
+        Variable::EnablePageRank,
+    ];
+
     pub fn try_parse(input: &str) -> Option<Variable> {
         Some(match input {
             "user_agent" => Variable::UserAgent,
@@ -58,6 +69,20 @@ impl Variable {
         })
     }
 
+    /// Checks whether a literal is a good value for this variable.
+    pub fn check_value(&self, literal: &Value) -> Result<(), super::Error> {
+        match self {
+            Variable::UserAgent => self.retrieve_as_str(Some(literal)).map(|_| ()),
+            Variable::Quota | Variable::MaxDepth | Variable::MaxBodySize => {
+                self.retrieve_as_u64(Some(literal)).map(|_| ())
+            }
+            Variable::MaxHitsPerSec | Variable::RequestTimeout => {
+                self.retrieve_as_positive_f64(Some(literal)).map(|_| ())
+            }
+            Variable::EnablePageRank => self.retrieve_as_bool(Some(literal)).map(|_| ()),
+        }
+    }
+
     fn bad_value<T>(&self, literal: &Value) -> Result<T, super::Error> {
         Err(super::Error::BadSetVariableValue(*self, literal.clone()))
     }
@@ -0,0 +1,901 @@
+//! A subset of XPath 1.0, evaluated over `scraper` documents. This covers
+//! location paths with all axes, predicates, unions, comparisons and the most
+//! common functions. Only elements can come out of a selection, though: other
+//! kinds of nodes (text, attributes) are only useful inside predicates.
+
+use ego_tree::NodeRef;
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag, take_while},
+    character::complete::{digit0, digit1, multispace0, satisfy},
+    combinator::{all_consuming, map, map_res, not, opt, peek, recognize, value},
+    multi::{many0, separated_list0},
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    IResult,
+};
+use scraper::{ElementRef, Html, Node};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Axis {
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    Parent,
+    Ancestor,
+    AncestorOrSelf,
+    FollowingSibling,
+    PrecedingSibling,
+    Following,
+    Preceding,
+    SelfNode,
+    Attribute,
+}
+
+impl Axis {
+    fn is_reverse(self) -> bool {
+        matches!(
+            self,
+            Axis::Parent
+                | Axis::Ancestor
+                | Axis::AncestorOrSelf
+                | Axis::PrecedingSibling
+                | Axis::Preceding
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum NodeTest {
+    Name(String),
+    Wildcard,
+    Text,
+    Node,
+}
+
+#[derive(Debug, PartialEq)]
+struct Step {
+    axis: Axis,
+    test: NodeTest,
+    predicates: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+}
+
+#[derive(Debug, PartialEq)]
+enum Expr {
+    /// A location path, absolute or not.
+    Path(bool, Vec<Step>),
+    /// A parenthesized expression, with predicates.
+    Filter(Box<Expr>, Vec<Expr>),
+    Literal(String),
+    Number(f64),
+    Function(String, Vec<Expr>),
+    Binary(Box<Expr>, Op, Box<Expr>),
+    Negate(Box<Expr>),
+    Union(Box<Expr>, Box<Expr>),
+}
+
+/// Known functions, with their minimum and maximum number of arguments.
+const FUNCTIONS: &[(&str, usize, usize)] = &[
+    ("last", 0, 0),
+    ("position", 0, 0),
+    ("count", 1, 1),
+    ("not", 1, 1),
+    ("true", 0, 0),
+    ("false", 0, 0),
+    ("boolean", 1, 1),
+    ("number", 0, 1),
+    ("string", 0, 1),
+    ("concat", 2, usize::MAX),
+    ("contains", 2, 2),
+    ("starts-with", 2, 2),
+    ("normalize-space", 0, 1),
+    ("string-length", 0, 1),
+    ("name", 0, 0),
+    ("local-name", 0, 0),
+];
+
+fn ws<'a, F, T>(f: F) -> impl FnMut(&'a str) -> IResult<&'a str, T>
+where
+    F: FnMut(&'a str) -> IResult<&'a str, T>,
+{
+    delimited(multispace0, f, multispace0)
+}
+
+fn name(i: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        satisfy(|ch| ch.is_alphabetic() || ch == '_'),
+        take_while(|ch: char| ch.is_alphanumeric() || ch == '_' || ch == '-'),
+    ))(i)
+}
+
+/// A keyword operator, like `and`, that cannot be the start of a name.
+fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    ws(terminated(
+        tag(word),
+        not(satisfy(|ch| ch.is_alphanumeric() || ch == '_' || ch == '-')),
+    ))
+}
+
+fn axis(i: &str) -> IResult<&str, Axis> {
+    alt((
+        value(Axis::Attribute, ws(tag("@"))),
+        map_res(terminated(name, ws(tag("::"))), |axis| {
+            Ok(match axis {
+                "child" => Axis::Child,
+                "descendant" => Axis::Descendant,
+                "descendant-or-self" => Axis::DescendantOrSelf,
+                "parent" => Axis::Parent,
+                "ancestor" => Axis::Ancestor,
+                "ancestor-or-self" => Axis::AncestorOrSelf,
+                "following-sibling" => Axis::FollowingSibling,
+                "preceding-sibling" => Axis::PrecedingSibling,
+                "following" => Axis::Following,
+                "preceding" => Axis::Preceding,
+                "self" => Axis::SelfNode,
+                "attribute" => Axis::Attribute,
+                _ => return Err(()),
+            })
+        }),
+    ))(i)
+}
+
+fn node_test(i: &str) -> IResult<&str, NodeTest> {
+    alt((
+        value(NodeTest::Wildcard, tag("*")),
+        value(NodeTest::Text, tuple((tag("text"), ws(tag("(")), tag(")")))),
+        value(NodeTest::Node, tuple((tag("node"), ws(tag("(")), tag(")")))),
+        map(name, |name| NodeTest::Name(name.to_lowercase())),
+    ))(i)
+}
+
+fn predicate(i: &str) -> IResult<&str, Expr> {
+    delimited(ws(tag("[")), expr, ws(tag("]")))(i)
+}
+
+fn step(i: &str) -> IResult<&str, Step> {
+    alt((
+        map(ws(tag("..")), |_| Step {
+            axis: Axis::Parent,
+            test: NodeTest::Node,
+            predicates: vec![],
+        }),
+        map(ws(tag(".")), |_| Step {
+            axis: Axis::SelfNode,
+            test: NodeTest::Node,
+            predicates: vec![],
+        }),
+        map(
+            tuple((opt(axis), ws(node_test), many0(predicate))),
+            |(axis, test, predicates)| Step {
+                axis: axis.unwrap_or(Axis::Child),
+                test,
+                predicates,
+            },
+        ),
+    ))(i)
+}
+
+fn descendant_or_self() -> Step {
+    Step {
+        axis: Axis::DescendantOrSelf,
+        test: NodeTest::Node,
+        predicates: vec![],
+    }
+}
+
+fn relative_path(i: &str) -> IResult<&str, Vec<Step>> {
+    let (mut i, first) = step(i)?;
+    let mut steps = vec![first];
+
+    loop {
+        if let Ok((rest, next)) = preceded(ws(tag("//")), step)(i) {
+            steps.push(descendant_or_self());
+            steps.push(next);
+            i = rest;
+        } else if let Ok((rest, next)) = preceded(ws(tag("/")), step)(i) {
+            steps.push(next);
+            i = rest;
+        } else {
+            return Ok((i, steps));
+        }
+    }
+}
+
+fn location_path(i: &str) -> IResult<&str, Expr> {
+    alt((
+        map(preceded(ws(tag("//")), relative_path), |mut steps| {
+            steps.insert(0, descendant_or_self());
+            Expr::Path(true, steps)
+        }),
+        map(preceded(ws(tag("/")), opt(relative_path)), |steps| {
+            Expr::Path(true, steps.unwrap_or_default())
+        }),
+        map(relative_path, |steps| Expr::Path(false, steps)),
+    ))(i)
+}
+
+fn literal(i: &str) -> IResult<&str, String> {
+    map(
+        ws(alt((
+            delimited(tag("'"), opt(is_not("'")), tag("'")),
+            delimited(tag("\""), opt(is_not("\"")), tag("\"")),
+        ))),
+        |literal: Option<&str>| literal.unwrap_or_default().to_owned(),
+    )(i)
+}
+
+fn function_call(i: &str) -> IResult<&str, Expr> {
+    map_res(
+        tuple((
+            ws(terminated(name, peek(ws(tag("("))))),
+            delimited(
+                ws(tag("(")),
+                separated_list0(ws(tag(",")), expr),
+                ws(tag(")")),
+            ),
+        )),
+        |(name, args)| {
+            let (_, min, max) = FUNCTIONS
+                .iter()
+                .find(|(function, _, _)| *function == name)
+                .ok_or(())?;
+
+            if args.len() < *min || args.len() > *max {
+                return Err(());
+            }
+
+            Ok(Expr::Function(name.to_owned(), args))
+        },
+    )(i)
+}
+
+/// A number. Numbers must start with a digit, so that `.` and `..` are left
+/// to the steps.
+fn number(i: &str) -> IResult<&str, f64> {
+    map_res(
+        ws(recognize(pair(digit1, opt(pair(tag("."), digit0))))),
+        str::parse,
+    )(i)
+}
+
+fn primary(i: &str) -> IResult<&str, Expr> {
+    alt((
+        map(literal, Expr::Literal),
+        map(number, Expr::Number),
+        map(
+            tuple((
+                delimited(ws(tag("(")), expr, ws(tag(")"))),
+                many0(predicate),
+            )),
+            |(expr, predicates)| Expr::Filter(Box::new(expr), predicates),
+        ),
+        function_call,
+        location_path,
+    ))(i)
+}
+
+fn union_expr(i: &str) -> IResult<&str, Expr> {
+    let (mut i, mut lhs) = primary(i)?;
+
+    while let Ok((rest, rhs)) = preceded(ws(tag("|")), primary)(i) {
+        lhs = Expr::Union(Box::new(lhs), Box::new(rhs));
+        i = rest;
+    }
+
+    Ok((i, lhs))
+}
+
+fn unary_expr(i: &str) -> IResult<&str, Expr> {
+    alt((
+        map(preceded(ws(tag("-")), unary_expr), |expr| {
+            Expr::Negate(Box::new(expr))
+        }),
+        union_expr,
+    ))(i)
+}
+
+/// Parses a left-associative chain of binary operations.
+fn binary<'a>(
+    i: &'a str,
+    mut operand: impl FnMut(&'a str) -> IResult<&'a str, Expr>,
+    mut operator: impl FnMut(&'a str) -> IResult<&'a str, Op>,
+) -> IResult<&'a str, Expr> {
+    let (mut i, mut lhs) = operand(i)?;
+
+    while let Ok((rest, (op, rhs))) = pair(&mut operator, &mut operand)(i) {
+        lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+        i = rest;
+    }
+
+    Ok((i, lhs))
+}
+
+fn additive_expr(i: &str) -> IResult<&str, Expr> {
+    binary(
+        i,
+        unary_expr,
+        alt((value(Op::Add, ws(tag("+"))), value(Op::Sub, ws(tag("-"))))),
+    )
+}
+
+fn relational_expr(i: &str) -> IResult<&str, Expr> {
+    binary(
+        i,
+        additive_expr,
+        alt((
+            value(Op::Le, ws(tag("<="))),
+            value(Op::Ge, ws(tag(">="))),
+            value(Op::Lt, ws(tag("<"))),
+            value(Op::Gt, ws(tag(">"))),
+        )),
+    )
+}
+
+fn equality_expr(i: &str) -> IResult<&str, Expr> {
+    binary(
+        i,
+        relational_expr,
+        alt((value(Op::Ne, ws(tag("!="))), value(Op::Eq, ws(tag("="))))),
+    )
+}
+
+fn and_expr(i: &str) -> IResult<&str, Expr> {
+    binary(i, equality_expr, value(Op::And, keyword("and")))
+}
+
+fn expr(i: &str) -> IResult<&str, Expr> {
+    binary(i, and_expr, value(Op::Or, keyword("or")))
+}
+
+/// A node, as XPath sees it. Attributes are not nodes in the `scraper` tree,
+/// so they get a variant of their own.
+#[derive(Debug, Clone, Copy)]
+enum XNode<'a> {
+    Node(NodeRef<'a, Node>),
+    Attribute(NodeRef<'a, Node>, &'a str, &'a str),
+}
+
+impl<'a> PartialEq for XNode<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (XNode::Node(this), XNode::Node(other)) => this.id() == other.id(),
+            (XNode::Attribute(this, this_name, _), XNode::Attribute(other, other_name, _)) => {
+                this.id() == other.id() && this_name == other_name
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'a> XNode<'a> {
+    fn string_value(&self) -> String {
+        match self {
+            XNode::Node(node) => match node.value() {
+                Node::Text(text) => text.to_string(),
+                Node::Comment(comment) => comment.to_string(),
+                _ => node
+                    .descendants()
+                    .filter_map(|node| node.value().as_text())
+                    .map(|text| &**text)
+                    .collect(),
+            },
+            XNode::Attribute(_, _, value) => value.to_string(),
+        }
+    }
+
+    fn name(&self) -> &'a str {
+        match self {
+            XNode::Node(node) => node.value().as_element().map(|el| el.name()).unwrap_or(""),
+            XNode::Attribute(_, name, _) => name,
+        }
+    }
+
+    /// The position of this node in document order, as a path of sibling
+    /// indexes from the root.
+    fn order_key(&self) -> Vec<usize> {
+        let node = match self {
+            XNode::Node(node) | XNode::Attribute(node, _, _) => *node,
+        };
+        let mut key = std::iter::once(node)
+            .chain(node.ancestors())
+            .map(|node| node.prev_siblings().count())
+            .collect::<Vec<_>>();
+        key.reverse();
+
+        if let XNode::Attribute(..) = self {
+            // Attributes come right after their element.
+            key.push(0);
+        }
+
+        key
+    }
+
+    fn matches(&self, axis: Axis, test: &NodeTest) -> bool {
+        match (self, test) {
+            (XNode::Attribute(..), NodeTest::Wildcard | NodeTest::Node) => true,
+            (XNode::Attribute(_, name, _), NodeTest::Name(test)) => name.eq_ignore_ascii_case(test),
+            (XNode::Attribute(..), NodeTest::Text) => false,
+            (XNode::Node(_), _) if axis == Axis::Attribute => false,
+            (XNode::Node(_), NodeTest::Node) => true,
+            (XNode::Node(node), NodeTest::Text) => node.value().is_text(),
+            (XNode::Node(node), NodeTest::Wildcard) => node.value().is_element(),
+            (XNode::Node(node), NodeTest::Name(test)) => node
+                .value()
+                .as_element()
+                .map(|el| el.name().eq_ignore_ascii_case(test))
+                .unwrap_or(false),
+        }
+    }
+
+    /// The nodes along an axis, in the axis' own order.
+    fn along(&self, axis: Axis) -> Vec<XNode<'a>> {
+        let node = match (self, axis) {
+            (XNode::Node(node), _) => *node,
+            (XNode::Attribute(..), Axis::SelfNode) => return vec![*self],
+            (XNode::Attribute(element, _, _), Axis::Parent) => return vec![XNode::Node(*element)],
+            (XNode::Attribute(element, _, _), Axis::Ancestor | Axis::AncestorOrSelf) => {
+                let mut along = if axis == Axis::AncestorOrSelf {
+                    vec![*self]
+                } else {
+                    vec![]
+                };
+                along.extend(
+                    std::iter::once(*element)
+                        .chain(element.ancestors())
+                        .map(XNode::Node),
+                );
+                return along;
+            }
+            (XNode::Attribute(..), _) => return vec![],
+        };
+
+        match axis {
+            Axis::Child => node.children().map(XNode::Node).collect(),
+            Axis::Descendant => node.descendants().skip(1).map(XNode::Node).collect(),
+            Axis::DescendantOrSelf => node.descendants().map(XNode::Node).collect(),
+            Axis::Parent => node.parent().map(XNode::Node).into_iter().collect(),
+            Axis::Ancestor => node.ancestors().map(XNode::Node).collect(),
+            Axis::AncestorOrSelf => std::iter::once(node)
+                .chain(node.ancestors())
+                .map(XNode::Node)
+                .collect(),
+            Axis::FollowingSibling => node.next_siblings().map(XNode::Node).collect(),
+            Axis::PrecedingSibling => node.prev_siblings().map(XNode::Node).collect(),
+            Axis::Following => std::iter::once(node)
+                .chain(node.ancestors())
+                .flat_map(|node| node.next_siblings())
+                .flat_map(|node| node.descendants())
+                .map(XNode::Node)
+                .collect(),
+            Axis::Preceding => std::iter::once(node)
+                .chain(node.ancestors())
+                .flat_map(|node| node.prev_siblings())
+                .flat_map(|node| node.descendants().collect::<Vec<_>>().into_iter().rev())
+                .map(XNode::Node)
+                .collect(),
+            Axis::SelfNode => vec![XNode::Node(node)],
+            Axis::Attribute => node
+                .value()
+                .as_element()
+                .map(|el| {
+                    el.attrs()
+                        .map(|(name, value)| XNode::Attribute(node, name, value))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum XValue<'a> {
+    Nodes(Vec<XNode<'a>>),
+    String(String),
+    Number(f64),
+    Bool(bool),
+}
+
+fn string_to_number(string: &str) -> f64 {
+    string.trim().parse().unwrap_or(f64::NAN)
+}
+
+fn number_to_string(number: f64) -> String {
+    if number.fract() == 0.0 && number.is_finite() {
+        format!("{}", number as i64)
+    } else {
+        number.to_string()
+    }
+}
+
+impl<'a> XValue<'a> {
+    fn into_bool(self) -> bool {
+        match self {
+            XValue::Nodes(nodes) => !nodes.is_empty(),
+            XValue::String(string) => !string.is_empty(),
+            XValue::Number(number) => number != 0.0 && !number.is_nan(),
+            XValue::Bool(b) => b,
+        }
+    }
+
+    fn into_number(self) -> f64 {
+        match self {
+            XValue::Number(number) => number,
+            XValue::Bool(b) => b as u8 as f64,
+            value => string_to_number(&value.into_string()),
+        }
+    }
+
+    fn into_string(self) -> String {
+        match self {
+            XValue::Nodes(nodes) => nodes.first().map(XNode::string_value).unwrap_or_default(),
+            XValue::String(string) => string,
+            XValue::Number(number) => number_to_string(number),
+            XValue::Bool(b) => b.to_string(),
+        }
+    }
+}
+
+fn compare_numbers(op: Op, lhs: f64, rhs: f64) -> bool {
+    match op {
+        Op::Eq => lhs == rhs,
+        Op::Ne => lhs != rhs,
+        Op::Lt => lhs < rhs,
+        Op::Le => lhs <= rhs,
+        Op::Gt => lhs > rhs,
+        Op::Ge => lhs >= rhs,
+        _ => unreachable!("not a comparison"),
+    }
+}
+
+fn compare_atoms(op: Op, lhs: XValue, rhs: XValue) -> bool {
+    match (op, lhs, rhs) {
+        (Op::Eq | Op::Ne, XValue::Bool(lhs), rhs) => {
+            compare_numbers(op, lhs as u8 as f64, rhs.into_bool() as u8 as f64)
+        }
+        (Op::Eq | Op::Ne, lhs, XValue::Bool(rhs)) => {
+            compare_numbers(op, lhs.into_bool() as u8 as f64, rhs as u8 as f64)
+        }
+        (Op::Eq | Op::Ne, lhs @ XValue::Number(_), rhs)
+        | (Op::Eq | Op::Ne, lhs, rhs @ XValue::Number(_)) => {
+            compare_numbers(op, lhs.into_number(), rhs.into_number())
+        }
+        (Op::Eq, lhs, rhs) => lhs.into_string() == rhs.into_string(),
+        (Op::Ne, lhs, rhs) => lhs.into_string() != rhs.into_string(),
+        (op, lhs, rhs) => compare_numbers(op, lhs.into_number(), rhs.into_number()),
+    }
+}
+
+/// Compares two values. Node sets compare true if any of their nodes does.
+fn compare(op: Op, lhs: XValue, rhs: XValue) -> bool {
+    let as_atom = |node: &XNode, other: &XValue| match other {
+        XValue::Number(_) => XValue::Number(string_to_number(&node.string_value())),
+        XValue::Bool(_) => XValue::Bool(true),
+        _ => XValue::String(node.string_value()),
+    };
+
+    match (lhs, rhs) {
+        (XValue::Nodes(lhs), XValue::Nodes(rhs)) => lhs.iter().any(|lhs| {
+            rhs.iter().any(|rhs| {
+                compare_atoms(
+                    op,
+                    XValue::String(lhs.string_value()),
+                    XValue::String(rhs.string_value()),
+                )
+            })
+        }),
+        (XValue::Nodes(nodes), XValue::Bool(b)) => {
+            compare_atoms(op, XValue::Bool(!nodes.is_empty()), XValue::Bool(b))
+        }
+        (XValue::Bool(b), XValue::Nodes(nodes)) => {
+            compare_atoms(op, XValue::Bool(b), XValue::Bool(!nodes.is_empty()))
+        }
+        (XValue::Nodes(nodes), rhs) => nodes
+            .iter()
+            .any(|node| compare_atoms(op, as_atom(node, &rhs), clone_atom(&rhs))),
+        (lhs, XValue::Nodes(nodes)) => nodes
+            .iter()
+            .any(|node| compare_atoms(op, clone_atom(&lhs), as_atom(node, &lhs))),
+        (lhs, rhs) => compare_atoms(op, lhs, rhs),
+    }
+}
+
+fn clone_atom<'a>(value: &XValue) -> XValue<'a> {
+    match value {
+        XValue::String(string) => XValue::String(string.clone()),
+        XValue::Number(number) => XValue::Number(*number),
+        XValue::Bool(b) => XValue::Bool(*b),
+        XValue::Nodes(_) => unreachable!("not an atom"),
+    }
+}
+
+/// Puts nodes in document order, without duplicates.
+fn sort_nodes(nodes: Vec<XNode>) -> Vec<XNode> {
+    let mut keyed = nodes
+        .into_iter()
+        .map(|node| (node.order_key(), node))
+        .collect::<Vec<_>>();
+    keyed.sort_by(|(this, _), (other, _)| this.cmp(other));
+    keyed.dedup_by(|(this, _), (other, _)| this == other);
+    keyed.into_iter().map(|(_, node)| node).collect()
+}
+
+struct Context<'a> {
+    node: XNode<'a>,
+    position: usize,
+    size: usize,
+}
+
+/// Filters nodes by a predicate. The nodes must be in the order of the axis
+/// they come from, since this is what positions refer to.
+fn filter<'a>(nodes: Vec<XNode<'a>>, predicate: &Expr) -> Vec<XNode<'a>> {
+    let size = nodes.len();
+
+    nodes
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, node)| {
+            let context = Context {
+                node: *node,
+                position: idx + 1,
+                size,
+            };
+
+            match eval(predicate, &context) {
+                XValue::Number(number) => number == (idx + 1) as f64,
+                value => value.into_bool(),
+            }
+        })
+        .map(|(_, node)| node)
+        .collect()
+}
+
+fn eval_path<'a>(absolute: bool, steps: &[Step], context: &Context<'a>) -> Vec<XNode<'a>> {
+    let start = match context.node {
+        XNode::Node(node) if absolute => XNode::Node(node.ancestors().last().unwrap_or(node)),
+        XNode::Attribute(node, _, _) if absolute => {
+            XNode::Node(node.ancestors().last().unwrap_or(node))
+        }
+        node => node,
+    };
+    let mut nodes = vec![start];
+
+    for step in steps {
+        let mut selected = vec![];
+        let mut seen = HashSet::new();
+
+        for node in &nodes {
+            let mut along = node
+                .along(step.axis)
+                .into_iter()
+                .filter(|candidate| candidate.matches(step.axis, &step.test))
+                .collect::<Vec<_>>();
+
+            for predicate in &step.predicates {
+                along = filter(along, predicate);
+            }
+
+            for candidate in along {
+                if seen.insert(candidate.order_key()) {
+                    selected.push(candidate);
+                }
+            }
+        }
+
+        nodes = if step.axis.is_reverse() || nodes.len() > 1 {
+            sort_nodes(selected)
+        } else {
+            selected
+        };
+    }
+
+    nodes
+}
+
+fn eval<'a>(expr: &Expr, context: &Context<'a>) -> XValue<'a> {
+    match expr {
+        Expr::Path(absolute, steps) => XValue::Nodes(eval_path(*absolute, steps, context)),
+        Expr::Filter(expr, predicates) => match eval(expr, context) {
+            XValue::Nodes(mut nodes) => {
+                for predicate in predicates {
+                    nodes = filter(nodes, predicate);
+                }
+                XValue::Nodes(nodes)
+            }
+            value if predicates.is_empty() => value,
+            _ => XValue::Nodes(vec![]),
+        },
+        Expr::Literal(literal) => XValue::String(literal.clone()),
+        Expr::Number(number) => XValue::Number(*number),
+        Expr::Negate(expr) => XValue::Number(-eval(expr, context).into_number()),
+        Expr::Union(lhs, rhs) => match (eval(lhs, context), eval(rhs, context)) {
+            (XValue::Nodes(mut lhs), XValue::Nodes(rhs)) => {
+                lhs.extend(rhs);
+                XValue::Nodes(sort_nodes(lhs))
+            }
+            _ => XValue::Nodes(vec![]),
+        },
+        Expr::Binary(lhs, Op::Or, rhs) => {
+            XValue::Bool(eval(lhs, context).into_bool() || eval(rhs, context).into_bool())
+        }
+        Expr::Binary(lhs, Op::And, rhs) => {
+            XValue::Bool(eval(lhs, context).into_bool() && eval(rhs, context).into_bool())
+        }
+        Expr::Binary(lhs, Op::Add, rhs) => {
+            XValue::Number(eval(lhs, context).into_number() + eval(rhs, context).into_number())
+        }
+        Expr::Binary(lhs, Op::Sub, rhs) => {
+            XValue::Number(eval(lhs, context).into_number() - eval(rhs, context).into_number())
+        }
+        Expr::Binary(lhs, op, rhs) => {
+            XValue::Bool(compare(*op, eval(lhs, context), eval(rhs, context)))
+        }
+        Expr::Function(name, args) => eval_function(name, args, context),
+    }
+}
+
+fn eval_function<'a>(name: &str, args: &[Expr], context: &Context<'a>) -> XValue<'a> {
+    let arg = |idx: usize| eval(&args[idx], context);
+    let string_arg = |idx: usize| {
+        if args.len() > idx {
+            arg(idx).into_string()
+        } else {
+            context.node.string_value()
+        }
+    };
+
+    match name {
+        "last" => XValue::Number(context.size as f64),
+        "position" => XValue::Number(context.position as f64),
+        "count" => match arg(0) {
+            XValue::Nodes(nodes) => XValue::Number(nodes.len() as f64),
+            _ => XValue::Number(f64::NAN),
+        },
+        "not" => XValue::Bool(!arg(0).into_bool()),
+        "true" => XValue::Bool(true),
+        "false" => XValue::Bool(false),
+        "boolean" => XValue::Bool(arg(0).into_bool()),
+        "number" => XValue::Number(string_to_number(&string_arg(0))),
+        "string" => XValue::String(string_arg(0)),
+        "concat" => XValue::String((0..args.len()).map(|idx| arg(idx).into_string()).collect()),
+        "contains" => XValue::Bool(string_arg(0).contains(&string_arg(1))),
+        "starts-with" => XValue::Bool(string_arg(0).starts_with(&string_arg(1))),
+        "normalize-space" => XValue::String(
+            string_arg(0)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        "string-length" => XValue::Number(string_arg(0).chars().count() as f64),
+        "name" | "local-name" => XValue::String(context.node.name().to_owned()),
+        _ => unreachable!("functions are checked when parsing"),
+    }
+}
+
+/// A parsed XPath expression, which must select elements.
+#[derive(Debug)]
+pub struct XPath {
+    expr: Expr,
+    original: String,
+}
+
+impl FromStr for XPath {
+    type Err = String;
+    fn from_str(i: &str) -> Result<XPath, String> {
+        let (_, expr) = all_consuming(expr)(i).map_err(|_| format!("bad XPath: {}", i))?;
+
+        Ok(XPath {
+            expr,
+            original: i.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for XPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.original)
+    }
+}
+
+impl PartialEq for XPath {
+    fn eq(&self, other: &Self) -> bool {
+        self.expr == other.expr
+    }
+}
+
+impl XPath {
+    fn select_from<'a>(&self, node: NodeRef<'a, Node>) -> Vec<ElementRef<'a>> {
+        let context = Context {
+            node: XNode::Node(node),
+            position: 1,
+            size: 1,
+        };
+
+        match eval(&self.expr, &context) {
+            XValue::Nodes(nodes) => nodes
+                .into_iter()
+                .filter_map(|node| match node {
+                    XNode::Node(node) => ElementRef::wrap(node),
+                    XNode::Attribute(..) => None,
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Selects the elements of a document.
+    pub fn select<'a>(&self, html: &'a Html) -> Vec<ElementRef<'a>> {
+        self.select_from(html.tree.root())
+    }
+
+    /// Selects elements relative to another element.
+    pub fn select_in<'a>(&self, element: ElementRef<'a>) -> Vec<ElementRef<'a>> {
+        self.select_from(*element)
+    }
+}
+
+#[test]
+fn xpath_test() {
+    let html = Html::parse_document(
+        r#"<html><body>
+            <dl>
+                <dt>Name</dt><dd>Lopez</dd>
+                <dt>Price</dt><dd class="price">12.50</dd>
+            </dl>
+            <ul><li>a</li><li id="b">b</li><li>c</li></ul>
+        </body></html>"#,
+    );
+    let select = |xpath: &str| {
+        xpath
+            .parse::<XPath>()
+            .unwrap()
+            .select(&html)
+            .into_iter()
+            .map(|element| element.text().collect::<String>())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        select("//dt[normalize-space(.) = 'Price']/following-sibling::dd[1]"),
+        vec!["12.50"]
+    );
+    assert_eq!(select("//li[@id='b']/preceding-sibling::li"), vec!["a"]);
+    assert_eq!(select("//li[last()]"), vec!["c"]);
+    assert_eq!(select("//li[position() > 1 and not(@id)]"), vec!["c"]);
+    assert_eq!(
+        select("//dd[contains(@class, 'pri')]/ancestor::dl/dt[1]"),
+        vec!["Name"]
+    );
+    assert_eq!(select("(//dt | //li)[2]"), vec!["Price"]);
+    assert_eq!(select("/html/body/ul/li[2]"), vec!["b"]);
+    assert!("//li[".parse::<XPath>().is_err());
+    assert!("//li[frobnicate()]".parse::<XPath>().is_err());
+
+    let dt = "//dt"
+        .parse::<XPath>()
+        .unwrap()
+        .select(&html)
+        .into_iter()
+        .next()
+        .unwrap();
+    let dd = "following-sibling::dd[1]"
+        .parse::<XPath>()
+        .unwrap()
+        .select_in(dt);
+    assert_eq!(dd[0].text().collect::<String>(), "Lopez");
+}
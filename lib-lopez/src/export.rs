@@ -0,0 +1,405 @@
+//! Writing the pages of a wave to files, in a handful of formats.
+
+use futures::prelude::*;
+use parquet::basic::{LogicalType, Repetition, Type as PhysicalType};
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int32Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type as SchemaType;
+use serde_json::Value;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::backend::{ExportedPage, WaveExport};
+use crate::Type;
+
+/// The columns that every page has, before the analyses.
+const PAGE_COLUMNS: [&str; 4] = ["url", "status_code", "depth", "rank"];
+
+/// How many rows go in each parquet row group.
+const ROW_GROUP_SIZE: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line, with analyses in an `analyses` field.
+    Jsonl,
+    /// One column per analysis; values that are not scalars are written as
+    /// JSON.
+    Csv,
+    /// One column per analysis, typed according to the analysis result type.
+    Parquet,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<ExportFormat, String> {
+        match s {
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(format!(
+                "unknown export format `{s}` (expected `jsonl`, `csv` or `parquet`)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::Jsonl => write!(f, "jsonl"),
+            ExportFormat::Csv => write!(f, "csv"),
+            ExportFormat::Parquet => write!(f, "parquet"),
+        }
+    }
+}
+
+/// Writes all pages in the export to `out` (`-` for the standard output),
+/// returning the number of pages written.
+pub async fn write_export(
+    export: WaveExport,
+    format: ExportFormat,
+    out: &Path,
+) -> Result<usize, anyhow::Error> {
+    let writer = BufWriter::new(create_output(out)?);
+
+    match format {
+        ExportFormat::Jsonl => write_jsonl(export, writer).await,
+        ExportFormat::Csv => write_csv(export, writer).await,
+        ExportFormat::Parquet => write_parquet(export, writer).await,
+    }
+}
+
+/// Creates (or truncates) the file at `out`, or opens the standard output if
+/// `out` is `-`.
+pub(crate) fn create_output(out: &Path) -> Result<Box<dyn Write + Send>, anyhow::Error> {
+    if out == Path::new("-") {
+        Ok(Box::new(io::stdout()))
+    } else {
+        Ok(Box::new(File::create(out).map_err(|err| {
+            anyhow::anyhow!("could not create `{}`: {err}", out.display())
+        })?))
+    }
+}
+
+/// Tabular formats have analyses side by side with the page columns.
+fn check_column_names(analyses: &[(String, Type)]) -> Result<(), anyhow::Error> {
+    for (name, _) in analyses {
+        if PAGE_COLUMNS.contains(&name.as_str()) {
+            anyhow::bail!(
+                "analysis `{name}` clashes with the page column of the same name (try `jsonl`)"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn write_jsonl<W: Write>(export: WaveExport, mut writer: W) -> Result<usize, anyhow::Error> {
+    let mut pages = export.pages;
+    let mut count = 0;
+
+    while let Some(page) = pages.try_next().await? {
+        serde_json::to_writer(&mut writer, &page)?;
+        writeln!(writer)?;
+        count += 1;
+    }
+
+    writer.flush()?;
+
+    Ok(count)
+}
+
+/// A value as it goes into a single CSV cell.
+fn csv_cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(string)) => string.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+async fn write_csv<W: Write>(export: WaveExport, writer: W) -> Result<usize, anyhow::Error> {
+    check_column_names(&export.analyses)?;
+
+    let mut writer = csv::Writer::from_writer(writer);
+    let mut pages = export.pages;
+    let mut count = 0;
+
+    writer.write_record(
+        PAGE_COLUMNS
+            .iter()
+            .copied()
+            .chain(export.analyses.iter().map(|(name, _)| name.as_str())),
+    )?;
+
+    while let Some(page) = pages.try_next().await? {
+        let mut record = vec![
+            page.url.to_string(),
+            page.status_code.to_string(),
+            page.depth.to_string(),
+            page.rank.map(|rank| rank.to_string()).unwrap_or_default(),
+        ];
+        record.extend(
+            export
+                .analyses
+                .iter()
+                .map(|(name, _)| csv_cell(page.analyses.get(name))),
+        );
+        writer.write_record(&record)?;
+        count += 1;
+    }
+
+    writer.flush()?;
+
+    Ok(count)
+}
+
+/// The parquet column for an analysis. Scalars get their own types; anything
+/// else is stored as JSON.
+fn parquet_column(name: &str, typ: &Type) -> Result<SchemaType, anyhow::Error> {
+    let builder = match typ {
+        Type::Bool => SchemaType::primitive_type_builder(name, PhysicalType::BOOLEAN),
+        Type::Number => SchemaType::primitive_type_builder(name, PhysicalType::DOUBLE),
+        Type::String => SchemaType::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
+            .with_logical_type(Some(LogicalType::String)),
+        _ => SchemaType::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
+            .with_logical_type(Some(LogicalType::Json)),
+    };
+
+    Ok(builder.with_repetition(Repetition::OPTIONAL).build()?)
+}
+
+fn parquet_schema(analyses: &[(String, Type)]) -> Result<SchemaType, anyhow::Error> {
+    let mut fields = vec![
+        SchemaType::primitive_type_builder("url", PhysicalType::BYTE_ARRAY)
+            .with_repetition(Repetition::REQUIRED)
+            .with_logical_type(Some(LogicalType::String))
+            .build()?,
+        SchemaType::primitive_type_builder("status_code", PhysicalType::INT32)
+            .with_repetition(Repetition::REQUIRED)
+            .build()?,
+        SchemaType::primitive_type_builder("depth", PhysicalType::INT32)
+            .with_repetition(Repetition::REQUIRED)
+            .build()?,
+        SchemaType::primitive_type_builder("rank", PhysicalType::DOUBLE)
+            .with_repetition(Repetition::OPTIONAL)
+            .build()?,
+    ];
+
+    for (name, typ) in analyses {
+        fields.push(parquet_column(name, typ)?);
+    }
+
+    Ok(SchemaType::group_type_builder("page")
+        .with_fields(fields.into_iter().map(Arc::new).collect())
+        .build()?)
+}
+
+/// Splits optional values into what parquet wants: the non-null values and a
+/// definition level for every row.
+fn definition_levels<T>(values: impl Iterator<Item = Option<T>>) -> (Vec<T>, Vec<i16>) {
+    let mut present = vec![];
+    let mut levels = vec![];
+
+    for value in values {
+        if let Some(value) = value {
+            present.push(value);
+            levels.push(1);
+        } else {
+            levels.push(0);
+        }
+    }
+
+    (present, levels)
+}
+
+fn write_row_group<W: Write + Send>(
+    writer: &mut SerializedFileWriter<W>,
+    analyses: &[(String, Type)],
+    rows: &[ExportedPage],
+) -> Result<(), anyhow::Error> {
+    let mut row_group = writer.next_row_group()?;
+
+    macro_rules! write_column {
+        ($data_type:ty, $values:expr, $levels:expr) => {{
+            let mut column = row_group
+                .next_column()?
+                .expect("there is a column for every field in the schema");
+            column
+                .typed::<$data_type>()
+                .write_batch(&$values, $levels, None)?;
+            column.close()?;
+        }};
+    }
+
+    let urls = rows
+        .iter()
+        .map(|page| ByteArray::from(page.url.as_str()))
+        .collect::<Vec<_>>();
+    write_column!(ByteArrayType, urls, None);
+
+    let status_codes = rows
+        .iter()
+        .map(|page| page.status_code as i32)
+        .collect::<Vec<_>>();
+    write_column!(Int32Type, status_codes, None);
+
+    let depths = rows
+        .iter()
+        .map(|page| page.depth as i32)
+        .collect::<Vec<_>>();
+    write_column!(Int32Type, depths, None);
+
+    let (ranks, levels) = definition_levels(rows.iter().map(|page| page.rank));
+    write_column!(DoubleType, ranks, Some(&levels));
+
+    for (name, typ) in analyses {
+        let column = rows
+            .iter()
+            .map(|page| page.analyses.get(name).filter(|value| !value.is_null()));
+
+        match typ {
+            Type::Bool => {
+                let (values, levels) = definition_levels(column.map(|value| value?.as_bool()));
+                write_column!(BoolType, values, Some(&levels));
+            }
+            Type::Number => {
+                let (values, levels) = definition_levels(column.map(|value| value?.as_f64()));
+                write_column!(DoubleType, values, Some(&levels));
+            }
+            Type::String => {
+                let (values, levels) =
+                    definition_levels(column.map(|value| Some(ByteArray::from(value?.as_str()?))));
+                write_column!(ByteArrayType, values, Some(&levels));
+            }
+            _ => {
+                let (values, levels) = definition_levels(
+                    column.map(|value| Some(ByteArray::from(value?.to_string().into_bytes()))),
+                );
+                write_column!(ByteArrayType, values, Some(&levels));
+            }
+        }
+    }
+
+    row_group.close()?;
+
+    Ok(())
+}
+
+async fn write_parquet<W: 'static + Write + Send>(
+    export: WaveExport,
+    writer: W,
+) -> Result<usize, anyhow::Error> {
+    check_column_names(&export.analyses)?;
+
+    let schema = Arc::new(parquet_schema(&export.analyses)?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(writer, schema, properties)?;
+    let mut pages = export.pages;
+    let mut rows = Vec::with_capacity(ROW_GROUP_SIZE);
+    let mut count = 0;
+
+    while let Some(page) = pages.try_next().await? {
+        rows.push(page);
+        count += 1;
+
+        if rows.len() == ROW_GROUP_SIZE {
+            write_row_group(&mut writer, &export.analyses, &rows)?;
+            rows.clear();
+        }
+    }
+
+    if !rows.is_empty() {
+        write_row_group(&mut writer, &export.analyses, &rows)?;
+    }
+
+    writer.close()?;
+
+    Ok(count)
+}
+
+#[test]
+fn write_export_test() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use serde_json::json;
+
+    use crate::backend::{stream, StreamExt};
+
+    let export = || WaveExport {
+        analyses: vec![
+            ("title".to_owned(), Type::String),
+            ("words".to_owned(), Type::Number),
+            ("tags".to_owned(), Type::Array(Box::new(Type::String))),
+        ],
+        pages: stream::iter(vec![
+            Ok(ExportedPage {
+                url: "https://example.foo/".parse().unwrap(),
+                status_code: 200,
+                depth: 0,
+                rank: Some(0.5),
+                analyses: vec![
+                    ("title".to_owned(), json!("Home, sweet home")),
+                    ("words".to_owned(), json!(42)),
+                    ("tags".to_owned(), json!(["a", "b"])),
+                ]
+                .into_iter()
+                .collect(),
+            }),
+            Ok(ExportedPage {
+                url: "https://example.foo/missing".parse().unwrap(),
+                status_code: 404,
+                depth: 1,
+                rank: None,
+                analyses: vec![("words".to_owned(), Value::Null)]
+                    .into_iter()
+                    .collect(),
+            }),
+        ])
+        .boxed_local(),
+    };
+
+    let dir = std::env::temp_dir().join(format!("lopez-export-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let write = |format: ExportFormat| {
+        let out = dir.join(format!("out.{format}"));
+        let count = futures::executor::block_on(write_export(export(), format, &out)).unwrap();
+        assert_eq!(count, 2);
+        out
+    };
+
+    let jsonl = std::fs::read_to_string(write(ExportFormat::Jsonl)).unwrap();
+    let first: Value = serde_json::from_str(jsonl.lines().next().unwrap()).unwrap();
+    assert_eq!(first["analyses"]["tags"], json!(["a", "b"]));
+
+    let csv = std::fs::read_to_string(write(ExportFormat::Csv)).unwrap();
+    assert_eq!(
+        csv,
+        "url,status_code,depth,rank,title,words,tags\n\
+        https://example.foo/,200,0,0.5,\"Home, sweet home\",42,\"[\"\"a\"\",\"\"b\"\"]\"\n\
+        https://example.foo/missing,404,1,,,,\n"
+    );
+
+    let reader =
+        SerializedFileReader::new(File::open(write(ExportFormat::Parquet)).unwrap()).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            "{url: \"https://example.foo/\", status_code: 200, depth: 0, rank: 0.5, \
+            title: \"Home, sweet home\", words: 42.0, tags: \"[\"a\",\"b\"]\"}",
+            "{url: \"https://example.foo/missing\", status_code: 404, depth: 1, rank: null, \
+            title: null, words: null, tags: null}",
+        ]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
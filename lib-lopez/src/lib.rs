@@ -6,14 +6,17 @@ mod crawler;
 #[macro_use]
 pub mod backend;
 mod cancel;
+mod diff;
 mod directives;
 mod env;
+mod export;
 mod hash;
 mod page_rank;
 mod panic;
 #[macro_use]
 mod cli;
 mod logger;
+mod lsp;
 mod server;
 
 pub mod pretty_print;
@@ -23,9 +26,15 @@ pub use ansi_term;
 pub use anyhow;
 pub use cli::{Mode, Profile};
 pub use crawler::{CrawlMaster, DummyConfiguration, LocalHandlerFactory};
-pub use directives::{Directives, DirectivesConfiguration};
+pub use diff::{diff_waves, AnalysisChange, RedirectChange, StatusCodeChange, WaveDiff};
+pub use directives::{
+    format_file, Diagnostic, Diagnostics, Directives, DirectivesConfiguration,
+    DirectivesTestReport,
+};
+pub use export::{write_export, ExportFormat};
 pub use hash::hash;
 pub use logger::init_logger;
+pub use lsp::serve_lsp;
 pub use r#type::Type;
 pub use serde::Serialize;
 pub use server::{serve, RemoteWorkerHandlerFactory};
@@ -67,6 +76,22 @@ macro_rules! main {
             );
         }
 
+        /// Shows the diagnostics in a configuration error, if that is what it
+        /// is, and returns the error to be reported.
+        fn report_diagnostics(err: $crate::anyhow::Error, json: bool) -> $crate::anyhow::Error {
+            match err.downcast::<$crate::Diagnostics>() {
+                Ok(diagnostics) if json => {
+                    print_json(&Err(diagnostics) as &Result<(), _>);
+                    std::process::exit(1)
+                }
+                Ok(diagnostics) => {
+                    diagnostics.pretty_print();
+                    $crate::anyhow::anyhow!("{} issues found in configuration", diagnostics.len())
+                }
+                Err(err) => err,
+            }
+        }
+
         #[tokio::main(flavor = "current_thread")]
         pub async fn main() {
             use $crate::ansi_term::Color::{Green, Red};
@@ -94,7 +119,7 @@ macro_rules! main {
                     }
                     Ok(None) => std::process::exit(0),
                     Err(err) => {
-                        println!("{}: {err}", Red.bold().paint("error"));
+                        eprintln!("{}: {err}", Red.bold().paint("error"));
                         std::process::exit(1)
                     }
                 }
@@ -119,12 +144,35 @@ macro_rules! main {
                     }
 
                     // Open directives:
-                    Directives::load(source, cli.import_path)
-                        .map(|_| Some("valid configuration".to_owned()))
+                    match Directives::load(source, cli.import_path) {
+                        Ok(_) => Ok(Some("valid configuration".to_owned())),
+                        Err(err) => Err(report_diagnostics(err, cli.json)),
+                    }
+                }
+                LopezApp::Fmt { source, check } => {
+                    // Conditionally init logging:
+                    if cli.verbose {
+                        $crate::init_logger(cli.verbose);
+                    }
+
+                    match $crate::format_file(&source) {
+                        Ok(None) => Ok(Some(format!("{} is formatted", source.display()))),
+                        Ok(Some(_)) if check => Err($crate::anyhow::anyhow!(
+                            "{} is not formatted",
+                            source.display()
+                        )),
+                        Ok(Some(formatted)) => {
+                            std::fs::write(&source, formatted)?;
+                            Ok(Some(format!("formatted {}", source.display())))
+                        }
+                        Err(err) => Err(report_diagnostics(err, cli.json)),
+                    }
                 }
                 LopezApp::Test {
                     source,
                     test_url,
+                    from_file,
+                    as_url,
                     profile,
                 } => {
                     // Conditionally init logging:
@@ -133,60 +181,113 @@ macro_rules! main {
                     }
 
                     let profile = Arc::new(profile);
+                    let import_path = cli.import_path;
+                    let json = cli.json;
+
+                    let outcome = async move {
+                        let url = Url::parse(
+                            &as_url.or(test_url).expect("either is required by the cli"),
+                        )?;
+
+                        // Read the local page, if testing offline:
+                        let content = match from_file {
+                            Some(path) if path.as_os_str() == "-" => {
+                                let mut content = vec![];
+                                std::io::Read::read_to_end(&mut std::io::stdin(), &mut content)?;
+                                Some(content)
+                            }
+                            Some(path) => Some(std::fs::read(&path).map_err(|err| {
+                                $crate::anyhow::anyhow!("could not read `{}`: {err}", path.display())
+                            })?),
+                            None => None,
+                        };
+
+                        // Open directives:
+                        let directives = Directives::load(source, import_path)?;
+                        let configuration = $crate::DirectivesConfiguration::new(
+                            directives,
+                            profile.clone()
+                        );
+                        let crawl_master = $crate::CrawlMaster::new(
+                            configuration,
+                            $crate::backend::MemoryBackend::new("test"),
+                            $crate::LocalHandlerFactory
+                        );
+
+                        // Create report:
+                        let report = if let Some(content) = content {
+                            crawl_master.test_content(profile, url, content).await
+                        } else {
+                            crawl_master.test_url(profile, url).await
+                        };
+
+                        // Show report:
+                        if json {
+                            print_json(&Ok(report) as &Result<_, ()>);
+                        } else {
+                            report.pretty_print();
+                        }
 
-                    match Url::parse(&test_url) {
+                        Ok(()) as Result<_, $crate::anyhow::Error>
+                    };
+
+                    match outcome.await {
                         // TODO: (known issue) structured output messes the expected return status...
                         Err(err) => {
                             if cli.json {
                                 print_json(&Err(format!("{}", err)) as &Result<(), _>);
                                 Ok(None)
                             } else {
-                                Err(err.into())
+                                Err(err)
                             }
                         }
-                        Ok(url) => {
-                            // Open directives:
-                            match Directives::load(source, cli.import_path) {
-                                Err(err) => {
-                                    if cli.json {
-                                        print_json(&Err(format!("{}", err)) as &Result<(), _>);
-                                        Ok(None)
-                                    } else {
-                                        Err(err)
-                                    }
-                                }
-                                Ok(directives) => {
-                                    let directives = directives;
-                                    let configuration = $crate::DirectivesConfiguration::new(
-                                        directives,
-                                        profile.clone()
-                                    );
-                                    let crawl_master = $crate::CrawlMaster::new(
-                                        configuration,
-                                        $crate::backend::DummyBackend::default(),
-                                        $crate::LocalHandlerFactory
-                                    );
-
-                                    // Create report:
-                                    let report = crawl_master.test_url(profile, url)
-                                        .await;
-
-                                    // Show report:
-                                    if cli.json {
-                                        print_json(&Ok(report) as &Result<_, ()>);
-                                    } else {
-                                        report.pretty_print();
-                                    }
-
-                                    Ok(None)
-                                }
-                            }
+                        Ok(()) => Ok(None),
+                    }
+                }
+                LopezApp::TestDirectives { source } => {
+                    // Conditionally init logging:
+                    if cli.verbose {
+                        $crate::init_logger(cli.verbose);
+                    }
+
+                    // Open directives and run the thing:
+                    let report = Directives::load(source, cli.import_path)?.run_tests();
+
+                    if cli.json {
+                        print_json(&report);
+
+                        // Still, the exit status must signal failures:
+                        if !report.is_success() {
+                            std::process::exit(1);
+                        }
+
+                        Ok(None)
+                    } else {
+                        report.pretty_print();
+
+                        if report.is_success() {
+                            Ok(Some(format!("{} directive tests passed", report.n_tests())))
+                        } else {
+                            Err($crate::anyhow::anyhow!(
+                                "{} of {} directive tests failed",
+                                report.n_tests() - report.n_passed(),
+                                report.n_tests(),
+                            ))
                         }
                     }
                 }
+                LopezApp::Lsp => {
+                    // No logging here: stdout belongs to the protocol.
+                    $crate::serve_lsp(cli.import_path)?;
+
+                    Ok(None)
+                }
                 LopezApp::Run {
                     source,
                     wave_name,
+                    allow_config_change,
+                    based_on,
+                    prioritize_by,
                     config,
                     profile,
                     mode,
@@ -202,6 +303,7 @@ macro_rules! main {
 
                     // Create backend:
                     let backend = <$backend_ty>::init(config, &wave_name).await?;
+                    let writes_to_stdout = backend.writes_to_stdout();
 
                     // Do the thing!
                     match mode.unwrap_or_default() {
@@ -210,7 +312,7 @@ macro_rules! main {
                                 configuration,
                                 backend,
                                 $crate::LocalHandlerFactory
-                            ).start(profile).await?
+                            ).start(profile, allow_config_change, based_on.as_deref(), prioritize_by).await?
                         },
                         $crate::Mode::Cluster { token, pool, max_retries } => {
                             $crate::CrawlMaster::new(
@@ -221,11 +323,16 @@ macro_rules! main {
                                     max_retries,
                                     &pool
                                 ).await?,
-                            ).start(profile).await?
+                            ).start(profile, allow_config_change, based_on.as_deref(), prioritize_by).await?
                         }
                     };
 
-                    Ok(Some("crawl complete".to_owned()))
+                    // Don't mix messages with the data:
+                    if writes_to_stdout {
+                        Ok(None)
+                    } else {
+                        Ok(Some("crawl complete".to_owned()))
+                    }
                 },
                 LopezApp::Serve { token, bind, max_connections } => {
                     // Init logging:
@@ -266,6 +373,105 @@ macro_rules! main {
                         }
                     }
                 }
+                LopezApp::Ls { config } => {
+                    if cli.verbose {
+                        $crate::init_logger(cli.verbose);
+                    }
+
+                    // No wave in particular:
+                    let mut backend = <$backend_ty>::init(config, "").await?;
+                    let waves = backend.list_waves().await?;
+
+                    if cli.json {
+                        print_json(&waves);
+                    } else {
+                        $crate::pretty_print::print_waves(&waves);
+                    }
+
+                    Ok(None)
+                }
+                LopezApp::Status { wave_name, config } => {
+                    if cli.verbose {
+                        $crate::init_logger(cli.verbose);
+                    }
+
+                    let mut backend = <$backend_ty>::init(config, &wave_name).await?;
+                    let status = backend.wave_status().await?.ok_or_else(|| {
+                        $crate::anyhow::anyhow!("wave `{wave_name}` does not exist")
+                    })?;
+
+                    if cli.json {
+                        print_json(&status);
+                    } else {
+                        status.pretty_print();
+                    }
+
+                    Ok(None)
+                }
+                LopezApp::ShowConfig { wave_name, config } => {
+                    if cli.verbose {
+                        $crate::init_logger(cli.verbose);
+                    }
+
+                    let mut backend = <$backend_ty>::init(config, &wave_name).await?;
+                    let configuration = backend.wave_configuration().await?.ok_or_else(|| {
+                        $crate::anyhow::anyhow!(
+                            "no configuration stored for wave `{wave_name}` (does it exist?)"
+                        )
+                    })?;
+
+                    if cli.json {
+                        print_json(&configuration);
+                    } else {
+                        configuration.pretty_print();
+                    }
+
+                    Ok(None)
+                }
+                LopezApp::Diff {
+                    wave_a,
+                    wave_b,
+                    config,
+                } => {
+                    if cli.verbose {
+                        $crate::init_logger(cli.verbose);
+                    }
+
+                    let mut backend = <$backend_ty>::init(config, &wave_a).await?;
+                    let pages = backend.compare(&wave_b).await?;
+                    let diff = $crate::diff_waves(&wave_a, &wave_b, pages).await?;
+
+                    if cli.json {
+                        print_json(&diff);
+                    } else {
+                        diff.pretty_print();
+                    }
+
+                    Ok(None)
+                }
+                LopezApp::Export {
+                    wave_name,
+                    format,
+                    out,
+                    config,
+                } => {
+                    if cli.verbose {
+                        $crate::init_logger(cli.verbose);
+                    }
+
+                    let mut backend = <$backend_ty>::init(config, &wave_name).await?;
+                    let export = backend.export().await?.ok_or_else(|| {
+                        $crate::anyhow::anyhow!("wave `{wave_name}` does not exist")
+                    })?;
+                    let count = $crate::write_export(export, format, &out).await?;
+
+                    // Don't mix messages with the data:
+                    if out == std::path::Path::new("-") {
+                        Ok(None)
+                    } else {
+                        Ok(Some(format!("{count} pages exported to `{}`", out.display())))
+                    }
+                }
                 LopezApp::PageRank { wave_name, config } => {
                     // Init logging:
                     $crate::init_logger(cli.verbose);
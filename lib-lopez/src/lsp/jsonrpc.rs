@@ -0,0 +1,63 @@
+//! The base protocol of the Language Server Protocol: JSON-RPC messages
+//! preceded by HTTP-like headers.
+
+use serde_json::Value;
+use std::io::{self, BufRead, Write};
+
+/// Reads the next message. Returns `None` at the end of the input.
+pub fn read_message<R: BufRead>(reader: &mut R) -> Result<Option<Value>, anyhow::Error> {
+    let mut content_length = None;
+
+    // Read headers until the empty line:
+    loop {
+        let mut header = String::new();
+
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+
+    let content_length =
+        content_length.ok_or_else(|| anyhow::anyhow!("message without `Content-Length`"))?;
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content)?;
+
+    Ok(Some(serde_json::from_slice(&content)?))
+}
+
+/// Writes a message.
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> Result<(), io::Error> {
+    let content = message.to_string();
+    write!(
+        writer,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    writer.flush()
+}
+
+#[test]
+fn message_test() {
+    let message = serde_json::json!({ "jsonrpc": "2.0", "method": "exit" });
+    let mut buffer = vec![];
+    write_message(&mut buffer, &message).unwrap();
+    write_message(&mut buffer, &message).unwrap();
+
+    let mut reader = io::Cursor::new(buffer);
+    assert_eq!(read_message(&mut reader).unwrap(), Some(message.clone()));
+    assert_eq!(read_message(&mut reader).unwrap(), Some(message));
+    assert_eq!(read_message(&mut reader).unwrap(), None);
+}
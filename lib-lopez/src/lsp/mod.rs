@@ -0,0 +1,839 @@
+//! A language server for crawl directives, speaking the Language Server
+//! Protocol over stdio. Each open document is checked as the main module of
+//! a crawl, together with everything it imports.
+
+mod jsonrpc;
+
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::directives::{
+    Diagnostic, Directives, Variable, AGGREGATOR_KEYWORDS, EXTRACTOR_KEYWORDS, ITEM_KEYWORDS,
+    TRANSFORMER_KEYWORDS,
+};
+
+use self::jsonrpc::{read_message, write_message};
+
+/// A short description of each keyword, shown on completion and on hover.
+/// The keywords themselves come from the parser; a test checks that this
+/// covers exactly them.
+const DESCRIPTIONS: &[(&str, &str)] = &[
+    // Items:
+    ("import", "imports a module: `import \"module.path\";`"),
+    (
+        "seed",
+        "starts the crawl from a URL: `seed \"https://...\";`",
+    ),
+    (
+        "allow",
+        "allows URLs matching a regex: `allow \"^https://...\";`",
+    ),
+    (
+        "disallow",
+        "disallows URLs matching a regex: `disallow \"...\";`",
+    ),
+    (
+        "frontier",
+        "does not follow links from URLs matching a regex: `frontier \"...\";`",
+    ),
+    (
+        "use param",
+        "keeps a query parameter: `use param \"page\";` or `use param *;`",
+    ),
+    (
+        "ignore param",
+        "strips a query parameter: `ignore param \"utm_source\";`",
+    ),
+    (
+        "use webdriver on",
+        "renders URLs matching a regex with WebDriver",
+    ),
+    ("set", "sets a variable: `set quota = 1000;`"),
+    (
+        "define",
+        "names a transformer expression: `define price-of = capture \"[0-9.]+\" get \"0\" as-number;`",
+    ),
+    (
+        "select",
+        "declares rules over a CSS selector: `select h1 { ... }`",
+    ),
+    (
+        "xpath",
+        "declares rules over an XPath instead: `select xpath \"//dt\" { ... }`",
+    ),
+    (
+        "when",
+        "restricts a rule set to pages where a condition holds: `select when status-code == 200 h1 { ... }`",
+    ),
+    (
+        "exists",
+        "condition: whether a selector matches anything in the page: `exists(\".price\")`",
+    ),
+    (
+        "and",
+        "condition: both conditions hold: `status-code == 200 and exists(\".price\")`",
+    ),
+    (
+        "or",
+        "condition: either condition holds: `page-depth == 0 or exists(\".price\")`",
+    ),
+    ("test", "declares a test: `test \"name\" { ... }`"),
+    (
+        "fixture",
+        "the page of a test: `fixture \"page.html\" as \"https://...\";`",
+    ),
+    (
+        "expect",
+        "an expected rule value in a test: `expect rule == \"value\";`",
+    ),
+    // Extractors:
+    ("name", "extractor: the tag name of the element"),
+    ("text", "extractor: the text of the element"),
+    ("html", "extractor: the HTML of the element"),
+    ("inner-html", "extractor: the inner HTML of the element"),
+    (
+        "attrs",
+        "extractor: the attributes of the element, as a map",
+    ),
+    ("classes", "extractor: the classes of the element"),
+    ("id", "extractor: the id of the element"),
+    ("page-url", "extractor: the URL of the page"),
+    (
+        "page-depth",
+        "extractor: how many links away from the seeds the page is",
+    ),
+    ("status-code", "extractor: the HTTP status code of the page"),
+    (
+        "outgoing-links-count",
+        "extractor: how many links the page has",
+    ),
+    (
+        "internal-links-count",
+        "extractor: how many links the page has to its own host",
+    ),
+    (
+        "external-links-count",
+        "extractor: how many links the page has to other hosts",
+    ),
+    (
+        "attr",
+        "extractor: an attribute of the element: `attr \"href\"`",
+    ),
+    (
+        "parent",
+        "extractor: applies an extractor to the parent element",
+    ),
+    (
+        "children",
+        "extractor: applies an extractor to all children elements",
+    ),
+    (
+        "select-any",
+        "extractor: applies an extractor to the first match of a selector",
+    ),
+    (
+        "select-all",
+        "extractor: applies an extractor to all matches of a selector",
+    ),
+    (
+        "select-any-xpath",
+        "extractor: applies an extractor to the first match of an XPath",
+    ),
+    (
+        "select-all-xpath",
+        "extractor: applies an extractor to all matches of an XPath",
+    ),
+    // Transformers:
+    ("is-null", "transformer: whether the value is null"),
+    ("is-not-null", "transformer: whether the value is not null"),
+    ("hash", "transformer: a hash of the value"),
+    (
+        "not",
+        "transformer: boolean negation; condition: the negation of a condition",
+    ),
+    (
+        "as-number",
+        "transformer: parses a string as a number, as written in a locale if given: `as-number \"pt-BR\"`",
+    ),
+    ("add", "transformer: adds a number: `add 1`"),
+    ("mul", "transformer: multiplies by a number: `mul 100`"),
+    ("div", "transformer: divides by a number: `div 100`"),
+    (
+        "round",
+        "transformer: rounds a number, to a number of decimal places if given: `round 2`",
+    ),
+    ("greater-than", "transformer: compares with a number"),
+    ("lesser-than", "transformer: compares with a number"),
+    ("greater-or-equal", "transformer: compares with a number"),
+    ("lesser-or-equal", "transformer: compares with a number"),
+    (
+        "between",
+        "transformer: whether a number is between two numbers",
+    ),
+    ("equals", "transformer: compares with a number or a string"),
+    (
+        "in",
+        "transformer: whether the value is in a list: `in [1, 2]`; \
+         restricts a rule set to pages matching a regex: `select in \"...\" h1 { ... }`",
+    ),
+    (
+        "length",
+        "transformer: the length of a string, array or map",
+    ),
+    (
+        "is-empty",
+        "transformer: whether a string, array or map is empty",
+    ),
+    ("get", "transformer: an element of an array or a map"),
+    ("flatten", "transformer: flattens an array of arrays"),
+    ("each", "transformer: transforms each element of an array"),
+    ("filter", "transformer: filters the elements of an array"),
+    (
+        "any",
+        "transformer: whether any element of an array passes a test; \
+         aggregator: whether any extracted value is true",
+    ),
+    (
+        "all",
+        "transformer: whether all elements of an array pass a test; \
+         aggregator: whether all extracted values are true",
+    ),
+    ("sort", "transformer: sorts an array"),
+    (
+        "sort-by",
+        "transformer: sorts an array by a key: `sort-by(get \"price\")`",
+    ),
+    ("as-string", "transformer: the value as a JSON string"),
+    ("pretty", "transformer: the value as a pretty JSON string"),
+    ("lower", "transformer: a string in lower case"),
+    ("upper", "transformer: a string in upper case"),
+    ("trim", "transformer: a string without leading and trailing whitespace"),
+    ("split", "transformer: splits a string: `split \",\"`"),
+    ("join", "transformer: joins an array of strings: `join \", \"`"),
+    (
+        "starts-with",
+        "transformer: whether a string starts with another: `starts-with \"http\"`",
+    ),
+    (
+        "substring",
+        "transformer: part of a string, by start and maybe length: `substring 0 10`",
+    ),
+    ("url-decode", "transformer: decodes `%`-escapes in a string"),
+    (
+        "absolute-url",
+        "transformer: resolves a (maybe relative) link against the page URL",
+    ),
+    (
+        "url-parts",
+        "transformer: breaks a (maybe relative) link into an object with `host`, `path` and `query`",
+    ),
+    (
+        "parse-date",
+        "transformer: parses a date into ISO 8601: `parse-date \"%d/%m/%Y\"`",
+    ),
+    ("capture", "transformer: the first capture of a regex"),
+    ("all-captures", "transformer: all captures of a regex"),
+    ("matches", "transformer: whether a string matches a regex"),
+    (
+        "replace",
+        "transformer: replaces a regex: `replace \"...\" with \"...\"`",
+    ),
+    (
+        "parse-json",
+        "transformer: parses a string as JSON, of type `any`",
+    ),
+    (
+        "path",
+        "transformer: digs into JSON with a JSONPath: `path \"$.offers.price\"`",
+    ),
+    (
+        "as",
+        "transformer: casts `any` to a type, e.g. `as array[string]`",
+    ),
+    // Aggregators:
+    ("count", "aggregator: the number of matched elements"),
+    ("first", "aggregator: the first extracted value"),
+    ("collect", "aggregator: all extracted values, as an array"),
+    (
+        "distinct",
+        "aggregator: all distinct extracted values, as an array",
+    ),
+    ("sum", "aggregator: the sum of all extracted numbers"),
+    ("min", "aggregator: the least extracted number or string"),
+    ("max", "aggregator: the greatest extracted number or string"),
+    ("mean", "aggregator: the mean of all extracted numbers"),
+    ("last", "aggregator: the last extracted value"),
+    (
+        "nth",
+        "aggregator: the n-th extracted value, from zero: `nth(2, text)`",
+    ),
+    (
+        "percentile",
+        "aggregator: a percentile of the extracted numbers: `percentile(90, ...)`",
+    ),
+    (
+        "count-by",
+        "aggregator: how many times each extracted string was seen, as a map",
+    ),
+    ("group", "aggregator: groups values by a key, as a map"),
+];
+
+/// All keywords of the language, from the parser, each with whether it
+/// starts an expression (an extractor, a transformer or an aggregator).
+fn keywords() -> impl Iterator<Item = (&'static str, bool)> {
+    let expressions = EXTRACTOR_KEYWORDS
+        .iter()
+        .chain(TRANSFORMER_KEYWORDS)
+        .chain(AGGREGATOR_KEYWORDS)
+        .filter(|keyword| !ITEM_KEYWORDS.contains(keyword));
+    let mut seen = HashSet::new();
+
+    ITEM_KEYWORDS
+        .iter()
+        .map(|keyword| (*keyword, false))
+        .chain(expressions.map(|keyword| (*keyword, true)))
+        .filter(move |(keyword, _)| seen.insert(*keyword))
+}
+
+fn description(keyword: &str) -> &'static str {
+    DESCRIPTIONS
+        .iter()
+        .find(|(described, _)| *described == keyword)
+        .map(|(_, description)| *description)
+        .unwrap_or_default()
+}
+
+#[test]
+fn descriptions_test() {
+    let keywords = keywords().map(|(keyword, _)| keyword).collect::<Vec<_>>();
+
+    for keyword in &keywords {
+        assert!(
+            !description(keyword).is_empty(),
+            "`{keyword}` is not described"
+        );
+    }
+
+    for (described, _) in DESCRIPTIONS {
+        assert!(
+            keywords.contains(described),
+            "`{described}` is not a keyword"
+        );
+    }
+}
+
+/// Whether a character can be part of an identifier (the same as in the
+/// parser).
+fn is_identifier_char(ch: char) -> bool {
+    !"\\/:;.()[]{}'\" \n\t\r\0".contains(ch)
+}
+
+/// Converts a column counted in `char`s to one counted in UTF-16 code units,
+/// which is how LSP counts them.
+fn utf16_column(line: &str, column: usize) -> usize {
+    line.chars().take(column).map(char::len_utf16).sum()
+}
+
+/// Converts a column counted in UTF-16 code units to one counted in `char`s.
+fn char_column(line: &str, column: usize) -> usize {
+    let mut units = 0;
+
+    line.chars()
+        .take_while(|ch| {
+            units += ch.len_utf16();
+            units <= column
+        })
+        .count()
+}
+
+#[test]
+fn column_test() {
+    let line = "a\u{1F577}b\u{e9}c";
+    assert_eq!(utf16_column(line, 2), 3);
+    assert_eq!(utf16_column(line, 4), 5);
+    assert_eq!(char_column(line, 3), 2);
+    assert_eq!(char_column(line, 5), 4);
+    assert_eq!(char_column(line, 2), 1);
+}
+
+/// Finds the word under a position of a text, with the character counted in
+/// UTF-16 code units. If `dotted`, paths like `module.rule` are taken as a
+/// single word.
+fn word_at(text: &str, line: usize, character: usize, dotted: bool) -> Option<&str> {
+    let line = text.lines().nth(line)?;
+    let character = char_column(line, character);
+    let is_word_char = |ch: char| is_identifier_char(ch) || (dotted && ch == '.');
+    let chars = line.char_indices().collect::<Vec<_>>();
+    let character = usize::min(character, chars.len());
+
+    let start = chars[..character]
+        .iter()
+        .rev()
+        .take_while(|(_, ch)| is_word_char(*ch))
+        .last()
+        .map(|(pos, _)| *pos)
+        .unwrap_or_else(|| {
+            chars
+                .get(character)
+                .map(|(pos, _)| *pos)
+                .unwrap_or(line.len())
+        });
+    let end = chars[character..]
+        .iter()
+        .find(|(_, ch)| !is_word_char(*ch))
+        .map(|(pos, _)| *pos)
+        .unwrap_or(line.len());
+
+    Some(&line[start..end]).filter(|word| !word.is_empty())
+}
+
+#[test]
+fn word_at_test() {
+    let text = "select h1 {\n    title: first(text);\n}\nexpect sub.title == 1;";
+    assert_eq!(word_at(text, 1, 6, false), Some("title"));
+    assert_eq!(word_at(text, 1, 4, false), Some("title"));
+    assert_eq!(word_at(text, 1, 17, false), Some("text"));
+    assert_eq!(word_at(text, 3, 9, false), Some("sub"));
+    assert_eq!(word_at(text, 3, 9, true), Some("sub.title"));
+    assert_eq!(word_at(text, 2, 0, false), None);
+    assert_eq!(word_at("\"\u{1F577}\" title", 0, 6, false), Some("title"));
+}
+
+/// Finds the module imported in a line of source, if any.
+fn import_on_line(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix("import")?;
+    let start = rest.find('"')? + 1;
+    let end = start + rest[start..].find('"')?;
+
+    Some(&rest[start..end])
+}
+
+/// Finds where a rule is declared in a source: the first line that looks like
+/// `rule-name: ...`.
+fn rule_position(source: &str, rule_name: &str) -> Option<(usize, usize)> {
+    source.lines().enumerate().find_map(|(line, text)| {
+        let column = text.len() - text.trim_start().len();
+        let rest = text.trim_start().strip_prefix(rule_name)?;
+
+        if rest.trim_start().starts_with(':') {
+            Some((line, text[..column].encode_utf16().count()))
+        } else {
+            None
+        }
+    })
+}
+
+fn location(path: &Path, line: usize, character: usize) -> Value {
+    json!({
+        "uri": Url::from_file_path(path).map(String::from).unwrap_or_default(),
+        "range": {
+            "start": { "line": line, "character": character },
+            "end": { "line": line, "character": character },
+        },
+    })
+}
+
+/// Converts a diagnostic to the LSP representation (lines and characters
+/// starting from 0). The range goes until the end of the line.
+fn lsp_diagnostic(diagnostic: &Diagnostic) -> Value {
+    let line = diagnostic.line - 1;
+    let character = utf16_column(&diagnostic.snippet, diagnostic.column - 1);
+    let end = usize::max(diagnostic.snippet.encode_utf16().count(), character);
+
+    json!({
+        "range": {
+            "start": { "line": line, "character": character },
+            "end": { "line": line, "character": end },
+        },
+        "severity": 1,
+        "source": "lopez",
+        "message": diagnostic.message,
+    })
+}
+
+/// The path of the document a request refers to.
+fn document_path(params: &Value) -> Option<PathBuf> {
+    Url::parse(params["textDocument"]["uri"].as_str()?)
+        .ok()?
+        .to_file_path()
+        .ok()
+}
+
+/// The position a request refers to. Characters are counted in UTF-16 code
+/// units.
+fn document_position(params: &Value) -> Option<(usize, usize)> {
+    Some((
+        params["position"]["line"].as_u64()? as usize,
+        params["position"]["character"].as_u64()? as usize,
+    ))
+}
+
+struct Server {
+    import_path: PathBuf,
+    /// The contents of all open documents.
+    documents: HashMap<PathBuf, String>,
+    /// The directives loaded with each open document as the main module.
+    directives: HashMap<PathBuf, Directives>,
+    /// The files which got diagnostics when checking each open document.
+    published: HashMap<PathBuf, HashSet<PathBuf>>,
+    /// Messages to be sent to the client.
+    outbox: Vec<Value>,
+}
+
+impl Server {
+    fn new(import_path: PathBuf) -> Server {
+        Server {
+            import_path,
+            documents: HashMap::new(),
+            directives: HashMap::new(),
+            published: HashMap::new(),
+            outbox: vec![],
+        }
+    }
+
+    fn publish_diagnostics(&mut self, path: &Path, diagnostics: Vec<Value>) {
+        if let Ok(uri) = Url::from_file_path(path) {
+            self.outbox.push(json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": uri.as_str(), "diagnostics": diagnostics },
+            }));
+        }
+    }
+
+    /// Checks an open document and all its imports, publishing diagnostics.
+    fn check(&mut self, path: &Path) {
+        let mut by_file = HashMap::<PathBuf, Vec<Value>>::new();
+
+        match Directives::load_with_diagnostics(path, &self.import_path, &self.documents) {
+            Ok((directives, diagnostics)) => {
+                for diagnostic in &diagnostics {
+                    by_file
+                        .entry(diagnostic.file.clone())
+                        .or_default()
+                        .push(lsp_diagnostic(diagnostic));
+                }
+
+                self.directives.insert(path.to_owned(), directives);
+            }
+            Err(err) => {
+                self.directives.remove(path);
+                by_file.insert(
+                    path.to_owned(),
+                    vec![json!({
+                        "range": {
+                            "start": { "line": 0, "character": 0 },
+                            "end": { "line": 0, "character": 0 },
+                        },
+                        "severity": 1,
+                        "source": "lopez",
+                        "message": err.to_string(),
+                    })],
+                );
+            }
+        }
+
+        // Clear files which are now ok:
+        for file in self.published.remove(path).unwrap_or_default() {
+            if !by_file.contains_key(&file) {
+                self.publish_diagnostics(&file, vec![]);
+            }
+        }
+
+        self.published
+            .insert(path.to_owned(), by_file.keys().cloned().collect());
+
+        for (file, diagnostics) in by_file {
+            self.publish_diagnostics(&file, diagnostics);
+        }
+    }
+
+    fn close(&mut self, path: &Path) {
+        self.documents.remove(path);
+        self.directives.remove(path);
+
+        for file in self.published.remove(path).unwrap_or_default() {
+            self.publish_diagnostics(&file, vec![]);
+        }
+    }
+
+    /// The name of the module of a file, when the document `root` is the
+    /// main module.
+    fn module_name(&self, root: &Path, path: &Path) -> Option<(&Directives, String)> {
+        let directives = self.directives.get(root)?;
+        let module_name = directives
+            .module_files()
+            .into_iter()
+            .find(|(_, module_path)| *module_path == path)
+            .map(|(module_name, _)| module_name.to_owned())?;
+
+        Some((directives, module_name))
+    }
+
+    fn hover(&self, params: &Value) -> Option<Value> {
+        let path = document_path(params)?;
+        let (line, character) = document_position(params)?;
+        let word = word_at(self.documents.get(&path)?, line, character, false)?;
+        let (directives, module_name) = self.module_name(&path, &path)?;
+
+        let rule_type = directives
+            .rule_types()
+            .into_iter()
+            .find(|(rule_module, rule_name, _)| *rule_module == module_name && *rule_name == word)
+            .map(|(_, _, rule_type)| rule_type);
+
+        let contents = match rule_type {
+            Some(Ok(rule_type)) => format!("`{word}`: `{rule_type}`"),
+            Some(Err(err)) => format!("`{word}`: {err}"),
+            None => {
+                let (keyword, _) =
+                    keywords().find(|(keyword, _)| keyword.split(' ').any(|part| part == word))?;
+                format!("`{keyword}`: {}", description(keyword))
+            }
+        };
+
+        Some(json!({ "contents": { "kind": "markdown", "value": contents } }))
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let keywords = keywords().map(|(keyword, is_expression)| {
+            json!({
+                "label": keyword,
+                // Function or keyword:
+                "kind": if is_expression { 3 } else { 14 },
+                "detail": description(keyword),
+            })
+        });
+        let variables = Variable::ALL.iter().map(|variable| {
+            json!({ "label": variable.to_string(), "kind": 6, "detail": "set-variable" })
+        });
+        let rules = document_path(params)
+            .and_then(|path| self.module_name(&path, &path))
+            .map(|(directives, module_name)| {
+                directives
+                    .rule_types()
+                    .into_iter()
+                    .filter(|(rule_module, _, _)| *rule_module == module_name)
+                    .map(|(_, rule_name, _)| json!({ "label": rule_name, "kind": 5, "detail": "rule" }))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        keywords.chain(variables).chain(rules).collect()
+    }
+
+    fn definition(&self, params: &Value) -> Option<Value> {
+        let path = document_path(params)?;
+        let (line, character) = document_position(params)?;
+        let text = self.documents.get(&path)?;
+        let (directives, module_name) = self.module_name(&path, &path)?;
+
+        // Imports go to the imported module:
+        if let Some(import) = import_on_line(text.lines().nth(line)?) {
+            let module_path = directives.resolve_import(&module_name, import)?;
+            return Some(location(module_path, 0, 0));
+        }
+
+        // Everything else might be a rule (e.g., in `expect` statements):
+        let rule_path = word_at(text, line, character, true)?;
+        let (module_path, rule_name) = directives.find_rule(&module_name, rule_path)?;
+        let source = self
+            .documents
+            .get(module_path)
+            .cloned()
+            .or_else(|| fs::read_to_string(module_path).ok())?;
+        let (rule_line, rule_character) = rule_position(&source, rule_name)?;
+
+        Some(location(module_path, rule_line, rule_character))
+    }
+
+    /// Handles a request, returning its result or an error code and message.
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    // Full document sync:
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                    "definitionProvider": true,
+                },
+                "serverInfo": { "name": "lopez", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/hover" => Ok(self.hover(params).unwrap_or(Value::Null)),
+            "textDocument/completion" => Ok(self.completion(params)),
+            "textDocument/definition" => Ok(self.definition(params).unwrap_or(Value::Null)),
+            _ => Err((-32601, format!("method `{method}` not supported"))),
+        }
+    }
+
+    /// Handles a notification.
+    fn notification(&mut self, method: &str, params: &Value) {
+        let path = if let Some(path) = document_path(params) {
+            path
+        } else {
+            return;
+        };
+
+        match method {
+            "textDocument/didOpen" => {
+                if let Some(text) = params["textDocument"]["text"].as_str() {
+                    self.documents.insert(path.clone(), text.to_owned());
+                }
+
+                self.check(&path);
+            }
+            "textDocument/didChange" => {
+                // Full sync: the last change has the whole text.
+                let last_change = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last());
+
+                if let Some(text) = last_change.and_then(|change| change["text"].as_str()) {
+                    self.documents.insert(path.clone(), text.to_owned());
+                }
+
+                self.check(&path);
+            }
+            "textDocument/didSave" => {
+                // Imported modules may have changed on disk:
+                let open = self.directives.keys().cloned().collect::<Vec<_>>();
+
+                for root in open {
+                    self.check(&root);
+                }
+            }
+            "textDocument/didClose" => self.close(&path),
+            _ => {}
+        }
+    }
+}
+
+/// Runs the language server over stdio until the client says `exit`.
+pub fn serve_lsp(import_path: PathBuf) -> Result<(), anyhow::Error> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut input = stdin.lock();
+    let mut output = stdout.lock();
+    let mut server = Server::new(import_path);
+
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        if method == "exit" {
+            break;
+        }
+
+        match message.get("id") {
+            // Responses to our requests (we make none) have no method.
+            Some(_) if method.is_empty() => {}
+            Some(id) => {
+                let response = match server.request(method, params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, error)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": error },
+                    }),
+                };
+                write_message(&mut output, &response)?;
+            }
+            None => server.notification(method, params),
+        }
+
+        for message in server.outbox.drain(..) {
+            write_message(&mut output, &message)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn open_test_document(files: &[(&str, &str)]) -> (Server, PathBuf) {
+    let dir = crate::directives::TestDir::new(files);
+    let path = dir.main().to_owned();
+    let mut server = Server::new(dir.path().to_owned());
+    server.notification(
+        "textDocument/didOpen",
+        &json!({
+            "textDocument": {
+                "uri": Url::from_file_path(&path).unwrap().as_str(),
+                "text": files[0].1,
+            },
+        }),
+    );
+
+    (server, path)
+}
+
+#[test]
+fn diagnostics_test() {
+    let (server, path) = open_test_document(&[("main.lcd", "allow \"\u{1F577}\"; allow \"(\";\n")]);
+
+    assert_eq!(server.outbox.len(), 1);
+    let params = &server.outbox[0]["params"];
+    assert_eq!(params["uri"], Url::from_file_path(&path).unwrap().as_str());
+    assert_eq!(
+        params["diagnostics"][0]["range"],
+        json!({
+            "start": { "line": 0, "character": 12 },
+            "end": { "line": 0, "character": 22 },
+        })
+    );
+}
+
+#[test]
+fn requests_test() {
+    let (mut server, path) = open_test_document(&[
+        (
+            "main.lcd",
+            "import \"sub\";\nselect h1 {\n    title: first(text);\n}\n",
+        ),
+        ("sub.lcd", "select h2 {\n    heading: first(text);\n}\n"),
+    ]);
+    let uri = Url::from_file_path(&path).unwrap();
+    let at = |line: usize, character: usize| {
+        json!({
+            "textDocument": { "uri": uri.as_str() },
+            "position": { "line": line, "character": character },
+        })
+    };
+
+    let hover = server.request("textDocument/hover", &at(2, 6)).unwrap();
+    assert_eq!(hover["contents"]["value"], "`title`: `string`");
+    let hover = server.request("textDocument/hover", &at(2, 12)).unwrap();
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .starts_with("`first`: aggregator:"));
+
+    let completion = server
+        .request("textDocument/completion", &at(2, 0))
+        .unwrap();
+    let labels = completion
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    for label in ["select", "sort-by", "percentile", "title"] {
+        assert!(labels.contains(&label), "{label}");
+    }
+
+    let definition = server
+        .request("textDocument/definition", &at(0, 9))
+        .unwrap();
+    assert_eq!(
+        definition["uri"],
+        Url::from_file_path(path.with_file_name("sub.lcd"))
+            .unwrap()
+            .as_str()
+    );
+}
@@ -6,7 +6,12 @@ use hyper::StatusCode;
 use serde::ser::{Serialize, SerializeStructVariant, SerializeTupleVariant, Serializer};
 use url::Url;
 
-use crate::crawler::{Crawled, ReportType, TestRunReport};
+use crate::backend::{WaveConfiguration, WaveStatus, WaveSummary};
+use crate::crawler::{
+    BoundariesExplanation, Configuration, Crawled, ReportType, RuleOrigin, TestRunReport,
+};
+use crate::diff::WaveDiff;
+use crate::directives::{Diagnostics, DirectivesTestReport, ExpectationFailure, TestOutcome};
 
 fn color_for_code(code: &StatusCode) -> Color {
     if code.is_informational() {
@@ -64,6 +69,44 @@ fn print_list_of_url<'a, I: IntoIterator<Item = &'a Url>>(list: I, color: Color,
     }
 }
 
+fn print_rule_origins(title: &str, origins: &[RuleOrigin], color: Color) {
+    println!("{}:", title);
+
+    if origins.is_empty() {
+        println!("    <none>");
+    }
+
+    for origin in origins {
+        println!("    {}", color.paint(origin.to_string()));
+    }
+}
+
+impl BoundariesExplanation {
+    pub fn pretty_print(&self) {
+        print_rule_origins("Allowed by", &self.allowed_by, Green);
+
+        if !self.disallowed_by.is_empty() {
+            print_rule_origins("Disallowed by", &self.disallowed_by, Yellow);
+        }
+
+        if !self.frontier_by.is_empty() {
+            print_rule_origins("Frontier by", &self.frontier_by, Blue);
+        }
+
+        if !self.stripped_params.is_empty() {
+            println!("Stripped query params:");
+
+            for param in &self.stripped_params {
+                if let Some(origin) = &param.ignored_by {
+                    println!("    {}: ignored by {}", param.name, origin);
+                } else {
+                    println!("    {}: not used by any `use param`", param.name);
+                }
+            }
+        }
+    }
+}
+
 impl TestRunReport {
     pub fn pretty_print(&self) {
         println!(
@@ -71,6 +114,8 @@ impl TestRunReport {
             White.bold().paint(self.actual_url.to_string())
         );
 
+        self.boundaries.pretty_print();
+
         match &self.report {
             ReportType::DisallowedByDirectives => println!(
                 "Status: {}",
@@ -143,6 +188,54 @@ impl TestRunReport {
     }
 }
 
+fn print_expectation_failure(failure: &ExpectationFailure) {
+    let expected = to_colored_json_auto(&failure.expected).expect("can serialize");
+
+    if let Some(got) = &failure.got {
+        println!(
+            "    {}: expected {} and got {}",
+            failure.rule_name,
+            expected,
+            to_colored_json_auto(got).expect("can serialize")
+        );
+    } else {
+        println!(
+            "    {}: expected {} but there is no such rule",
+            failure.rule_name, expected,
+        );
+    }
+}
+
+impl DirectivesTestReport {
+    pub fn pretty_print(&self) {
+        for result in &self.results {
+            let name = if result.module.is_empty() {
+                result.name.clone()
+            } else {
+                format!("{} ({})", result.name, result.module)
+            };
+
+            match &result.outcome {
+                TestOutcome::Passed => println!("test {} ... {}", name, Green.bold().paint("ok")),
+                TestOutcome::Failed(failures) => {
+                    println!("test {} ... {}", name, Red.bold().paint("FAILED"));
+                    failures.iter().for_each(print_expectation_failure);
+                }
+                TestOutcome::Error(error) => {
+                    println!("test {} ... {}", name, Red.bold().paint("error"));
+                    println!("    {}", error);
+                }
+            }
+        }
+
+        println!(
+            "\n{} passed; {} failed",
+            self.n_passed(),
+            self.n_tests() - self.n_passed()
+        );
+    }
+}
+
 impl Serialize for Crawled {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -186,3 +279,207 @@ impl Serialize for Crawled {
         }
     }
 }
+
+impl Diagnostics {
+    pub fn pretty_print(&self) {
+        for diagnostic in &self.diagnostics {
+            println!("{:#}\n", diagnostic);
+        }
+    }
+}
+
+/// Prints one wave per line, as in `lopez ls`.
+pub fn print_waves(waves: &[WaveSummary]) {
+    if waves.is_empty() {
+        println!("<no waves>");
+    }
+
+    let width = waves.iter().map(|wave| wave.name.len()).max().unwrap_or(0);
+
+    for wave in waves {
+        println!(
+            "{}  started at {}",
+            White.bold().paint(format!("{:width$}", wave.name)),
+            wave.started_at
+        );
+    }
+}
+
+fn print_histogram<K: ToString>(title: &str, histogram: &[(K, Color, usize)]) {
+    println!("{}:", title);
+
+    if histogram.is_empty() {
+        println!("    <none>");
+    }
+
+    let width = histogram
+        .iter()
+        .map(|(key, _, _)| key.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    for (key, color, count) in histogram {
+        println!(
+            "    {} {}",
+            color.paint(format!("{:width$}", key.to_string())),
+            count
+        );
+    }
+}
+
+impl WaveStatus {
+    pub fn pretty_print(&self) {
+        println!("Wave: {}", White.bold().paint(&self.name));
+        println!("Started at: {}", self.started_at);
+
+        print_histogram(
+            "Pages",
+            &[
+                ("open", White, self.n_open),
+                ("taken", Blue, self.n_taken),
+                ("closed", Green, self.n_closed),
+                ("error", Red, self.n_error),
+            ],
+        );
+
+        print_histogram(
+            "Status codes",
+            &self
+                .status_codes
+                .iter()
+                .map(|(&code, &count)| {
+                    let color = StatusCode::from_u16(code)
+                        .map(|code| color_for_code(&code))
+                        .unwrap_or(Purple);
+                    (format!("⏺ {}", code), color, count)
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        print_histogram(
+            "Depths",
+            &self
+                .depths
+                .iter()
+                .map(|(&depth, &count)| (depth, White, count))
+                .collect::<Vec<_>>(),
+        );
+
+        if self.has_page_rank {
+            println!("Page rank: {}", Green.paint("computed"));
+        } else {
+            println!("Page rank: {}", Yellow.paint("not computed"));
+        }
+    }
+}
+
+impl WaveConfiguration {
+    pub fn pretty_print(&self) {
+        println!("Content hash: {}", White.bold().paint(&self.content_hash));
+
+        let source = serde_json::from_value::<Box<dyn Configuration>>(self.configuration.clone())
+            .map(|configuration| configuration.source());
+
+        match source {
+            Ok(source) if !source.is_empty() => print!("{}", source),
+            // Stored by a version of Lopez that did not keep (or know) this.
+            _ => println!(
+                "{}",
+                to_colored_json_auto(&self.configuration).expect("can serialize")
+            ),
+        }
+    }
+}
+
+/// Prints at most `limit` lines of a section, followed by how many were left
+/// out.
+fn print_limited<I: IntoIterator<Item = String>>(title: &str, lines: I, limit: usize) {
+    let lines = lines.into_iter().collect::<Vec<_>>();
+    println!("{} ({}):", title, lines.len());
+
+    if lines.is_empty() {
+        println!("    <empty>");
+    }
+
+    for line in lines.iter().take(limit) {
+        println!("    {}", line);
+    }
+
+    if lines.len() > limit {
+        println!("    ... and {} more.", lines.len() - limit);
+    }
+}
+
+fn display_or_none<T: ToString>(value: Option<T>) -> String {
+    value
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| "<none>".to_owned())
+}
+
+impl WaveDiff {
+    pub fn pretty_print(&self) {
+        const LIMIT: usize = 20;
+
+        println!(
+            "Comparing {} to {}",
+            White.bold().paint(&self.wave_a),
+            White.bold().paint(&self.wave_b)
+        );
+
+        if self.is_empty() {
+            println!("{}", Green.paint("No changes."));
+            return;
+        }
+
+        println!("New pages ({}):", self.new_pages.len());
+        print_list_of_url(&self.new_pages, Green, LIMIT);
+        println!("Removed pages ({}):", self.removed_pages.len());
+        print_list_of_url(&self.removed_pages, Red, LIMIT);
+
+        print_limited(
+            "Status code changes",
+            self.status_code_changes.iter().map(|change| {
+                let paint = |code: u16| {
+                    let color = StatusCode::from_u16(code)
+                        .map(|code| color_for_code(&code))
+                        .unwrap_or(Purple);
+                    color.paint(code.to_string()).to_string()
+                };
+                format!(
+                    "{}: {} => {}",
+                    change.url,
+                    paint(change.from),
+                    paint(change.to)
+                )
+            }),
+            LIMIT,
+        );
+
+        print_limited(
+            "Redirect changes",
+            self.redirect_changes.iter().map(|change| {
+                format!(
+                    "{}: {} => {}",
+                    change.url,
+                    Red.paint(display_or_none(change.from.as_ref())),
+                    Green.paint(display_or_none(change.to.as_ref()))
+                )
+            }),
+            LIMIT,
+        );
+
+        print_limited(
+            "Analysis changes",
+            self.analysis_changes.iter().map(|change| {
+                format!(
+                    "{} [{}]: {} => {}",
+                    change.url,
+                    Blue.paint(&change.analysis),
+                    Red.paint(display_or_none(change.from.as_ref())),
+                    Green.paint(display_or_none(change.to.as_ref()))
+                )
+            }),
+            LIMIT,
+        );
+    }
+}
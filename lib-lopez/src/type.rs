@@ -1,10 +1,17 @@
 //! All modules are dependent on a notion of a JSON-like type. Therefore, this impl is extracted to this module.
 
 use nom::{
-    branch::alt, bytes::complete::tag, character::complete::multispace1, combinator::map,
-    multi::many0, sequence::tuple, IResult,
+    branch::alt,
+    bytes::complete::{is_not, tag},
+    character::complete::multispace1,
+    combinator::map,
+    multi::{many0, separated_list0},
+    sequence::tuple,
+    IResult,
 };
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::str::FromStr;
 
@@ -16,6 +23,8 @@ pub enum Type {
     String,
     Array(Box<Type>),
     Map(Box<Type>),
+    /// A map with known fields, each with its own type.
+    Object(BTreeMap<String, Type>),
 }
 
 impl fmt::Display for Type {
@@ -27,6 +36,15 @@ impl fmt::Display for Type {
             Type::String => write!(f, "string"),
             Type::Array(typ) => write!(f, "array[{}]", typ),
             Type::Map(typ) => write!(f, "map[string, {}]", typ),
+            Type::Object(fields) => write!(
+                f,
+                "{{{}}}",
+                fields
+                    .iter()
+                    .map(|(name, typ)| format!("{}: {}", name, typ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
         }
     }
 }
@@ -56,6 +74,28 @@ impl Type {
             false
         }
     }
+
+    pub fn is_object(&self) -> bool {
+        matches!(self, Type::Object(_))
+    }
+
+    /// Tells whether a JSON value is of this type. Nulls are of all types.
+    pub fn accepts(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Type::Any, _) | (_, Value::Null) => true,
+            (Type::Bool, Value::Bool(_)) => true,
+            (Type::Number, Value::Number(_)) => true,
+            (Type::String, Value::String(_)) => true,
+            (Type::Array(typ), Value::Array(array)) => array.iter().all(|value| typ.accepts(value)),
+            (Type::Map(typ), Value::Object(object)) => {
+                object.values().all(|value| typ.accepts(value))
+            }
+            (Type::Object(fields), Value::Object(object)) => fields
+                .iter()
+                .all(|(name, typ)| typ.accepts(object.get(name).unwrap_or(&Value::Null))),
+            _ => false,
+        }
+    }
 }
 
 /// Defines what is whitespace:
@@ -101,5 +141,39 @@ pub fn r#type(i: &str) -> IResult<&str, Type> {
             )),
             |(_, _, _, _, r#type, _)| Type::Map(Box::new(r#type)),
         ),
+        map(object_fields, Type::Object),
     ))(i)
 }
+
+/// The fields of an object type, like `{name: string, price: number}`.
+pub fn object_fields(i: &str) -> IResult<&str, BTreeMap<String, Type>> {
+    map(
+        tuple((
+            tag_whitespace("{"),
+            separated_list0(
+                tag_whitespace(","),
+                tuple((
+                    trailing_whitespace(is_not(":,{}[] \n\t\r")),
+                    tag_whitespace(":"),
+                    trailing_whitespace(r#type),
+                )),
+            ),
+            tag("}"),
+        )),
+        |(_, fields, _)| {
+            fields
+                .into_iter()
+                .map(|(name, _, r#type)| (name.to_owned(), r#type))
+                .collect()
+        },
+    )(i)
+}
+
+#[test]
+fn object_type_test() {
+    let typ = "{name: string, price: number}".parse::<Type>().unwrap();
+    assert_eq!(typ.to_string().parse::<Type>().unwrap(), typ);
+    assert!(typ.accepts(&serde_json::json!({ "name": "foo", "price": 1 })));
+    assert!(typ.accepts(&serde_json::json!({ "name": "foo" })));
+    assert!(!typ.accepts(&serde_json::json!({ "name": "foo", "price": "1" })));
+}
@@ -0,0 +1,8 @@
+//! The same `lopez`, streaming results as JSON lines instead of storing them.
+
+/// Trying to see if I can get fragmentation reduction using jemalloc.
+#[cfg(not(target_env = "musl"))]
+#[global_allocator]
+static ALLOCATOR: jemallocator::Jemalloc = jemallocator::Jemalloc;
+
+lib_lopez::main! { lib_lopez::backend::SinkBackend }
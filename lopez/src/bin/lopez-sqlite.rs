@@ -0,0 +1,8 @@
+//! The same `lopez`, storing everything in a single SQLite file.
+
+/// Trying to see if I can get fragmentation reduction using jemalloc.
+#[cfg(not(target_env = "musl"))]
+#[global_allocator]
+static ALLOCATOR: jemallocator::Jemalloc = jemallocator::Jemalloc;
+
+lib_lopez::main! { sqlite_lopez::SqliteBackend }
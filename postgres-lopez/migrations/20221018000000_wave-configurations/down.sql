@@ -0,0 +1,2 @@
+alter table waves drop column configuration;
+alter table waves drop column content_hash;
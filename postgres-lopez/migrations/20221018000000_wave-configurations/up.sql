@@ -0,0 +1,3 @@
+-- Waves created before this are left without a configuration.
+alter table waves add column content_hash text;
+alter table waves add column configuration jsonb;
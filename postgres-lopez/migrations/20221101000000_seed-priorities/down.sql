@@ -0,0 +1 @@
+alter table "status" drop column priority;
@@ -0,0 +1,3 @@
+-- Pages seeded from a previous wave may be crawled first. Higher goes first;
+-- null goes last.
+alter table "status" add column priority double precision;
@@ -2,15 +2,19 @@
 mod db;
 mod master;
 mod ranker;
+mod tls;
 mod worker;
 
 use serde_derive::{Deserialize as DeriveDeserialize, Serialize as DeriveSerialize};
+use std::collections::BTreeMap;
 use std::rc::Rc;
 use std::sync::Arc;
+use tokio_postgres::{Client, Row};
 
 use lib_lopez::backend::{
-    async_trait, typetag, Backend, MasterBackend, WaveRemoveReport, WorkerBackend,
-    WorkerBackendFactory,
+    async_trait, typetag, Backend, ComparedPage, ExportedPage, LocalBoxStream, MasterBackend,
+    NaiveDateTime, PageSnapshot, StreamExt, Type, Url, Value, WaveConfiguration, WaveExport,
+    WaveRemoveReport, WaveStatus, WaveSummary, WorkerBackend, WorkerBackendFactory,
 };
 
 use crate::db::DbConfig;
@@ -20,6 +24,16 @@ use self::ranker::PostgresPageRanker;
 use self::worker::PostgresWorkerBackend;
 
 const REMOVE_WAVE: &str = include_str!("sql/remove_wave.sql");
+const FIND_WAVE: &str = include_str!("sql/find_wave.sql");
+const LIST_WAVES: &str = include_str!("sql/list_waves.sql");
+const COUNT_SEARCH_STATUS: &str = include_str!("sql/count_search_status.sql");
+const COUNT_STATUS_CODES: &str = include_str!("sql/count_status_codes.sql");
+const COUNT_DEPTHS: &str = include_str!("sql/count_depths.sql");
+const HAS_PAGE_RANK: &str = include_str!("sql/has_page_rank.sql");
+const NAMED_WAVE_CONFIGURATION: &str = include_str!("sql/named_wave_configuration.sql");
+const COMPARE_WAVES: &str = include_str!("sql/compare_waves.sql");
+const EXPORT_ANALYSES: &str = include_str!("sql/export_analyses.sql");
+const EXPORT_PAGES: &str = include_str!("sql/export_pages.sql");
 
 pub struct PostgresBackend {
     config: Arc<DbConfig>,
@@ -36,6 +50,70 @@ impl PostgresBackend {
     }
 }
 
+/// Counts pages of a wave in buckets, given a query returning `bucket` and
+/// `n_pages`.
+async fn histogram(
+    client: &Client,
+    query: &str,
+    wave_id: i32,
+) -> Result<BTreeMap<u16, usize>, anyhow::Error> {
+    Ok(client
+        .query(query, &[&wave_id])
+        .await?
+        .into_iter()
+        .map(|row| {
+            (
+                row.get::<_, i32>("bucket") as u16,
+                row.get::<_, i64>("n_pages") as usize,
+            )
+        })
+        .collect())
+}
+
+fn exported_page(row: Row) -> Result<ExportedPage, anyhow::Error> {
+    Ok(ExportedPage {
+        url: row.get::<_, String>("page_url").parse::<Url>()?,
+        status_code: row.get::<_, i32>("status_code") as u16,
+        depth: row.get::<_, i16>("depth") as u16,
+        rank: row.get("rank"),
+        analyses: match row.get("analyses") {
+            Value::Object(analyses) => analyses.into_iter().collect::<BTreeMap<_, _>>(),
+            other => anyhow::bail!("expected analyses to be an object, got {other}"),
+        },
+    })
+}
+
+/// Reads one side of a row of `compare_waves.sql`, whose columns are prefixed
+/// by `side`.
+fn page_snapshot(row: &Row, side: &str) -> Result<Option<PageSnapshot>, anyhow::Error> {
+    let status_code =
+        if let Some(status_code) = row.get::<_, Option<i32>>(&*format!("{side}_status_code")) {
+            status_code as u16
+        } else {
+            return Ok(None);
+        };
+
+    Ok(Some(PageSnapshot {
+        status_code,
+        redirect: row
+            .get::<_, Option<String>>(&*format!("{side}_redirect"))
+            .map(|redirect| redirect.parse::<Url>())
+            .transpose()?,
+        analyses: match row.get(&*format!("{side}_analyses")) {
+            Value::Object(analyses) => analyses.into_iter().collect::<BTreeMap<_, _>>(),
+            other => anyhow::bail!("expected analyses to be an object, got {other}"),
+        },
+    }))
+}
+
+fn compared_page(row: Row) -> Result<ComparedPage, anyhow::Error> {
+    Ok(ComparedPage {
+        url: row.get::<_, String>("page_url").parse::<Url>()?,
+        a: page_snapshot(&row, "a")?,
+        b: page_snapshot(&row, "b")?,
+    })
+}
+
 #[async_trait(?Send)]
 impl Backend for PostgresBackend {
     type Config = DbConfig;
@@ -46,10 +124,8 @@ impl Backend for PostgresBackend {
         let config = Arc::new(config);
 
         // Make sure db exists and is up to date:
-        // This thing blocks, but no problemo: it is not critical.
         config.ensure_create_db().await?;
         config
-            .clone()
             .sync_migrations()
             .await
             .map_err(|err| anyhow::anyhow!("Migration error: {err}"))?;
@@ -90,6 +166,128 @@ impl Backend for PostgresBackend {
 
         Ok(report)
     }
+
+    async fn list_waves(&mut self) -> Result<Vec<WaveSummary>, anyhow::Error> {
+        Ok(self
+            .connect()
+            .await?
+            .query(LIST_WAVES, &[])
+            .await?
+            .into_iter()
+            .map(|row| WaveSummary {
+                name: row.get("wave_name"),
+                started_at: row.get("started_at"),
+            })
+            .collect())
+    }
+
+    async fn wave_status(&mut self) -> Result<Option<WaveStatus>, anyhow::Error> {
+        let client = self.connect().await?;
+        let (wave_id, started_at): (i32, NaiveDateTime) =
+            if let Some(row) = client.query_opt(FIND_WAVE, &[&self.wave]).await? {
+                (row.get("wave_id"), row.get("started_at"))
+            } else {
+                return Ok(None);
+            };
+
+        let mut status = WaveStatus {
+            name: self.wave.clone(),
+            started_at,
+            n_open: 0,
+            n_taken: 0,
+            n_closed: 0,
+            n_error: 0,
+            status_codes: histogram(&client, COUNT_STATUS_CODES, wave_id).await?,
+            depths: histogram(&client, COUNT_DEPTHS, wave_id).await?,
+            has_page_rank: client
+                .query_one(HAS_PAGE_RANK, &[&wave_id])
+                .await?
+                .get("has_page_rank"),
+        };
+
+        for row in client.query(COUNT_SEARCH_STATUS, &[&wave_id]).await? {
+            let n_pages = row.get::<_, i64>("n_pages") as usize;
+            match row.get::<_, &str>("search_status") {
+                "open" => status.n_open = n_pages,
+                "taken" => status.n_taken = n_pages,
+                "closed" => status.n_closed = n_pages,
+                "error" => status.n_error = n_pages,
+                other => anyhow::bail!("unknown search status `{other}`"),
+            }
+        }
+
+        Ok(Some(status))
+    }
+
+    async fn wave_configuration(&mut self) -> Result<Option<WaveConfiguration>, anyhow::Error> {
+        let stored = self
+            .connect()
+            .await?
+            .query_opt(NAMED_WAVE_CONFIGURATION, &[&self.wave])
+            .await?
+            .map(|row| WaveConfiguration {
+                content_hash: row.get("content_hash"),
+                configuration: row.get("configuration"),
+            });
+
+        Ok(stored)
+    }
+
+    async fn compare(
+        &mut self,
+        other_wave: &str,
+    ) -> Result<LocalBoxStream<'static, Result<ComparedPage, anyhow::Error>>, anyhow::Error> {
+        let client = self.connect().await?;
+        let mut wave_ids = vec![];
+
+        for name in [self.wave.as_str(), other_wave] {
+            if let Some(row) = client.query_opt(FIND_WAVE, &[&name]).await? {
+                wave_ids.push(row.get::<_, i32>("wave_id"));
+            } else {
+                anyhow::bail!("wave `{}` does not exist", name);
+            }
+        }
+
+        Ok(client
+            .query_raw(COMPARE_WAVES, wave_ids)
+            .await?
+            .map(|row| compared_page(row?))
+            .boxed_local())
+    }
+
+    async fn export(&mut self) -> Result<Option<WaveExport>, anyhow::Error> {
+        let client = self.connect().await?;
+        let wave_id: i32 = if let Some(row) = client.query_opt(FIND_WAVE, &[&self.wave]).await? {
+            row.get("wave_id")
+        } else {
+            return Ok(None);
+        };
+
+        let analyses = client
+            .query(EXPORT_ANALYSES, &[&wave_id])
+            .await?
+            .into_iter()
+            .map(|row| {
+                let name: String = row.get("analysis_name");
+                let typ = row
+                    .get::<_, String>("result_type")
+                    .parse::<Type>()
+                    .map_err(|err| {
+                        anyhow::anyhow!("bad result type for analysis `{name}`: {err}")
+                    })?;
+                Ok((name, typ))
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        // Rows are streamed by the server, so no need to read them in chunks.
+        let pages = client
+            .query_raw(EXPORT_PAGES, [wave_id])
+            .await?
+            .map(|row| exported_page(row?))
+            .boxed_local();
+
+        Ok(Some(WaveExport { analyses, pages }))
+    }
 }
 
 #[derive(Debug, DeriveSerialize, DeriveDeserialize)]
@@ -107,3 +305,15 @@ impl WorkerBackendFactory for PostgresWorkerFactory {
         ))
     }
 }
+
+#[test]
+#[ignore = "needs a running PostgreSQL server, configured by the `DB_*` variables"]
+fn conformance_test() {
+    use lib_lopez::backend::conformance;
+    use structopt::StructOpt;
+
+    conformance::block_on(conformance::run_all(|wave| async move {
+        <PostgresBackend as Backend>::init(DbConfig::from_iter(["test"]), &wave).await
+    }))
+    .unwrap();
+}
@@ -1,7 +1,7 @@
 use std::rc::Rc;
 use tokio_postgres::{Client, Statement};
 
-use lib_lopez::backend::{async_trait, MasterBackend, Type, Url};
+use lib_lopez::backend::{async_trait, MasterBackend, SeedPriority, Type, Url, WaveConfiguration};
 use lib_lopez::hash;
 
 const ENSURE_WAVE: &str = include_str!("sql/ensure_wave.sql");
@@ -12,6 +12,10 @@ const RESET_QUEUE: &str = include_str!("sql/reset_queue.sql");
 const FETCH: &str = include_str!("sql/fetch.sql");
 const COUNT_CRAWLED: &str = include_str!("sql/count_crawled.sql");
 const EXISTS_TAKEN: &str = include_str!("sql/exists_taken.sql");
+const ENSURE_CONFIGURATION: &str = include_str!("sql/ensure_configuration.sql");
+const WAVE_CONFIGURATION: &str = include_str!("sql/wave_configuration.sql");
+const FIND_WAVE: &str = include_str!("sql/find_wave.sql");
+const SEED_FROM: &str = include_str!("sql/seed_from.sql");
 
 pub struct PostgresMasterBackend {
     client: Rc<Client>,
@@ -90,6 +94,28 @@ impl MasterBackend for PostgresMasterBackend {
         Ok(())
     }
 
+    async fn ensure_seeded_from(
+        &mut self,
+        wave: &str,
+        priority: Option<SeedPriority>,
+    ) -> Result<Option<usize>, anyhow::Error> {
+        // Only runs once per crawl: no need to prepare these.
+        let wave_id: i32 = if let Some(row) = self.client.query_opt(FIND_WAVE, &[&wave]).await? {
+            row.get("wave_id")
+        } else {
+            return Ok(None);
+        };
+
+        let params = params![
+            self.wave_id,
+            wave_id,
+            priority.map(|priority| priority.as_str())
+        ];
+        let n_seeded = self.client.execute(SEED_FROM, params).await?;
+
+        Ok(Some(n_seeded as usize))
+    }
+
     async fn create_analyses(
         &mut self,
         analysis_names: &[(String, Type)],
@@ -160,6 +186,30 @@ impl MasterBackend for PostgresMasterBackend {
 
         Ok(batch)
     }
+
+    async fn ensure_configuration(
+        &mut self,
+        configuration: &WaveConfiguration,
+    ) -> Result<Option<WaveConfiguration>, anyhow::Error> {
+        // Only runs once per crawl: no need to prepare these.
+        let params = params![
+            self.wave_id,
+            configuration.content_hash,
+            configuration.configuration
+        ];
+        self.client.execute(ENSURE_CONFIGURATION, params).await?;
+
+        let stored = self
+            .client
+            .query_opt(WAVE_CONFIGURATION, &[&self.wave_id])
+            .await?
+            .map(|row| WaveConfiguration {
+                content_hash: row.get("content_hash"),
+                configuration: row.get("configuration"),
+            });
+
+        Ok(stored)
+    }
 }
 
 // #[tokio::test]
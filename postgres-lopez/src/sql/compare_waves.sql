@@ -0,0 +1,52 @@
+-- Pages closed in either wave `$1` or `$2`, side by side, joined on their
+-- `page_id`. Columns of a side are null when the page was not crawled in that
+-- wave.
+with snapshots as (
+    select
+        wave_id,
+        page_id,
+        status_code,
+        (
+            select
+                page_url
+            from
+                linkage join pages on pages.page_id = linkage.to_page_id
+            where
+                linkage.wave_id = "status".wave_id
+                    and linkage.from_page_id = "status".page_id
+                    and reason = 'redirect'
+            order by
+                linkage.to_page_id
+            limit
+                1
+        ) as redirect,
+        (
+            select
+                coalesce(jsonb_object_agg(analysis_name, result), '{}'::jsonb)
+            from
+                analysis_results join analyses using (wave_id, analysis_id)
+            where
+                analysis_results.wave_id = "status".wave_id
+                    and analysis_results.page_id = "status".page_id
+        ) as analyses
+    from
+        "status"
+    where
+        wave_id in ($1::integer, $2::integer)
+            and search_status = 'closed'
+)
+select
+    page_url,
+    a.status_code as a_status_code,
+    a.redirect as a_redirect,
+    a.analyses as a_analyses,
+    b.status_code as b_status_code,
+    b.redirect as b_redirect,
+    b.analyses as b_analyses
+from
+    (select * from snapshots where wave_id = $1::integer) as a
+        full join (select * from snapshots where wave_id = $2::integer) as b
+            using (page_id)
+        join pages using (page_id)
+order by
+    page_id
@@ -0,0 +1,9 @@
+select
+    depth::integer as bucket,
+    count(*) as n_pages
+from
+    "status"
+where
+    wave_id = $1::integer
+group by
+    depth
@@ -0,0 +1,9 @@
+select
+    status_code::integer as bucket,
+    count(*) as n_pages
+from
+    "status"
+where
+    wave_id = $1::integer and search_status = 'closed'
+group by
+    status_code
@@ -8,4 +8,5 @@ select
 from
     unnest($3::text[], $4::jsonb[]) as incoming (analysis_name, result)
         join analyses on incoming.analysis_name = analyses.analysis_name
-            and analyses.wave_id = $1::integer;
+            and analyses.wave_id = $1::integer
+on conflict do nothing;
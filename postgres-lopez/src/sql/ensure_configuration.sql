@@ -0,0 +1,7 @@
+update
+    waves
+set
+    content_hash = $2::text,
+    configuration = $3::jsonb
+where
+    wave_id = $1::integer and configuration is null
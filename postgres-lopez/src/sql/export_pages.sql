@@ -0,0 +1,23 @@
+select
+    page_url,
+    status_code,
+    depth,
+    rank,
+    (
+        select
+            coalesce(jsonb_object_agg(analysis_name, result), '{}'::jsonb)
+        from
+            analysis_results join analyses using (wave_id, analysis_id)
+        where
+            analysis_results.wave_id = "status".wave_id
+                and analysis_results.page_id = "status".page_id
+    ) as analyses
+from
+    "status"
+        join pages using (page_id)
+        left join page_rank using (wave_id, page_id)
+where
+    "status".wave_id = $1::integer
+        and search_status = 'closed'
+order by
+    page_id
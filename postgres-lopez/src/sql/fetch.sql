@@ -4,9 +4,10 @@ with numbered as (
         page_id,
         page_url,
         depth,
+        priority,
         count(*) over (
             partition by substring(page_url from '^https?://([^/]*)/')
-            order by depth
+            order by priority desc nulls last, depth
         ) as count
     from
         "status" join pages using (page_id)
@@ -24,6 +25,7 @@ with numbered as (
         numbered
     order by
         count,
+        priority desc nulls last,
         depth
     limit
         $2::bigint
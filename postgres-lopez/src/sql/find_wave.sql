@@ -0,0 +1,7 @@
+select
+    wave_id,
+    started_at
+from
+    waves
+where
+    wave_name = $1::text
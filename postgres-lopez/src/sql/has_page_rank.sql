@@ -0,0 +1,2 @@
+select
+    exists (select 1 from page_rank where wave_id = $1::integer) as has_page_rank
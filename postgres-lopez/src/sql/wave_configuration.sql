@@ -0,0 +1,7 @@
+select
+    content_hash,
+    configuration
+from
+    waves
+where
+    wave_id = $1::integer and configuration is not null
@@ -0,0 +1,400 @@
+//! TLS for connections to PostgreSQL, with rustls, following the `sslmode`s
+//! of libpq.
+
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName};
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio_postgres::config::SslMode as PostgresSslMode;
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+/// The signature algorithms that rustls accepts by default.
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// Whether and how to use TLS, as the `sslmode` of libpq (except for `allow`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SslMode {
+    /// Never use TLS.
+    Disable,
+    /// Use TLS if the server supports it, without verifying its certificate.
+    Prefer,
+    /// Always use TLS, without verifying the server certificate.
+    Require,
+    /// Always use TLS and verify that the server certificate is signed by a
+    /// trusted authority.
+    VerifyCa,
+    /// Like `VerifyCa`, and also verify that the certificate is for the host
+    /// connected to.
+    VerifyFull,
+}
+
+impl SslMode {
+    /// All that `tokio-postgres` needs to know: whether TLS is required.
+    fn to_postgres(self) -> PostgresSslMode {
+        match self {
+            SslMode::Disable => PostgresSslMode::Disable,
+            SslMode::Prefer => PostgresSslMode::Prefer,
+            SslMode::Require | SslMode::VerifyCa | SslMode::VerifyFull => PostgresSslMode::Require,
+        }
+    }
+}
+
+impl FromStr for SslMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<SslMode, String> {
+        match s {
+            "disable" => Ok(SslMode::Disable),
+            "prefer" => Ok(SslMode::Prefer),
+            "require" => Ok(SslMode::Require),
+            "verify-ca" => Ok(SslMode::VerifyCa),
+            "verify-full" => Ok(SslMode::VerifyFull),
+            _ => Err(format!(
+                "unknown ssl mode `{s}` (expected `disable`, `prefer`, `require`, \
+                 `verify-ca` or `verify-full`)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SslMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SslMode::Disable => write!(f, "disable"),
+            SslMode::Prefer => write!(f, "prefer"),
+            SslMode::Require => write!(f, "require"),
+            SslMode::VerifyCa => write!(f, "verify-ca"),
+            SslMode::VerifyFull => write!(f, "verify-full"),
+        }
+    }
+}
+
+/// The TLS settings of a connection, as the `ssl*` options of libpq.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsOptions {
+    pub mode: SslMode,
+    /// A PEM file with the authorities trusted to sign the server certificate.
+    /// Defaults to those of the system.
+    pub root_cert: Option<PathBuf>,
+    /// A PEM file with the certificate to present to the server.
+    pub cert: Option<PathBuf>,
+    /// A PEM file with the private key of `cert`.
+    pub key: Option<PathBuf>,
+}
+
+impl TlsOptions {
+    /// The mode actually in effect: like libpq, `require` verifies the
+    /// server certificate when given the authorities to verify it with.
+    fn effective_mode(&self) -> SslMode {
+        match self.mode {
+            SslMode::Require if self.root_cert.is_some() => SslMode::VerifyCa,
+            mode => mode,
+        }
+    }
+
+    /// What `tokio-postgres` needs to be told to negotiate TLS.
+    pub fn postgres_ssl_mode(&self) -> PostgresSslMode {
+        self.effective_mode().to_postgres()
+    }
+
+    /// Builds the connector to be passed to `tokio-postgres`.
+    pub fn connector(&self) -> Result<MakeRustlsConnect, anyhow::Error> {
+        let builder = ClientConfig::builder().with_safe_defaults();
+        let builder = match self.effective_mode() {
+            SslMode::Disable | SslMode::Prefer | SslMode::Require => {
+                builder.with_custom_certificate_verifier(Arc::new(NoVerification))
+            }
+            SslMode::VerifyCa => {
+                builder.with_custom_certificate_verifier(Arc::new(CaVerification {
+                    roots: self.root_certs()?,
+                }))
+            }
+            SslMode::VerifyFull => {
+                let mut store = RootCertStore::empty();
+                for root in self.root_certs()? {
+                    store.add(&Certificate(root))?;
+                }
+                builder.with_custom_certificate_verifier(Arc::new(WebPkiVerifier::new(store, None)))
+            }
+        };
+
+        let config = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => {
+                builder.with_single_cert(read_certs(cert)?, read_key(key)?)?
+            }
+            (None, None) => builder.with_no_client_auth(),
+            _ => anyhow::bail!("a client certificate needs both `sslcert` and `sslkey`"),
+        };
+
+        Ok(MakeRustlsConnect::new(config))
+    }
+
+    /// The DER of the trusted authorities, skipping those that cannot be used
+    /// as trust anchors.
+    fn root_certs(&self) -> Result<Vec<Vec<u8>>, anyhow::Error> {
+        let certs = if let Some(root_cert) = &self.root_cert {
+            read_certs(root_cert)?
+                .into_iter()
+                .map(|Certificate(der)| der)
+                .collect::<Vec<_>>()
+        } else {
+            rustls_native_certs::load_native_certs()
+                .map_err(|err| anyhow::anyhow!("could not load system certificates: {err}"))?
+                .into_iter()
+                .map(|rustls_native_certs::Certificate(der)| der)
+                .collect()
+        };
+
+        let roots = certs
+            .into_iter()
+            .filter(|der| webpki::TrustAnchor::try_from_cert_der(der).is_ok())
+            .collect::<Vec<_>>();
+
+        if roots.is_empty() {
+            anyhow::bail!("no trusted certificate authorities to verify the server with");
+        }
+
+        Ok(roots)
+    }
+}
+
+fn read_certs(path: &Path) -> Result<Vec<Certificate>, anyhow::Error> {
+    let mut reader = BufReader::new(
+        File::open(path).map_err(|err| anyhow::anyhow!("could not open {path:?}: {err}"))?,
+    );
+    let certs = rustls_pemfile::certs(&mut reader)?;
+
+    if certs.is_empty() {
+        anyhow::bail!("no certificates found in {path:?}");
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Reads the first RSA or PKCS#8 private key in a PEM file.
+fn read_key(path: &Path) -> Result<PrivateKey, anyhow::Error> {
+    let mut reader = BufReader::new(
+        File::open(path).map_err(|err| anyhow::anyhow!("could not open {path:?}: {err}"))?,
+    );
+
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::PKCS8Key(key) => {
+                return Ok(PrivateKey(key))
+            }
+            _ => {}
+        }
+    }
+
+    anyhow::bail!("no RSA or PKCS#8 private key found in {path:?}")
+}
+
+/// Accepts any server certificate, as libpq does up to `sslmode=require`. The
+/// handshake still checks that the server owns the certificate it presents.
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Verifies the chain of the server certificate, but not the name in it, as
+/// libpq does with `sslmode=verify-ca`.
+struct CaVerification {
+    roots: Vec<Vec<u8>>,
+}
+
+impl ServerCertVerifier for CaVerification {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let invalid = |err: webpki::Error| rustls::Error::InvalidCertificateData(err.to_string());
+
+        let cert = webpki::EndEntityCert::try_from(end_entity.0.as_ref()).map_err(invalid)?;
+        let anchors = self
+            .roots
+            .iter()
+            .map(|der| webpki::TrustAnchor::try_from_cert_der(der))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        let chain = intermediates
+            .iter()
+            .map(|cert| cert.0.as_ref())
+            .collect::<Vec<_>>();
+        let now = webpki::Time::try_from(now).map_err(|_| rustls::Error::FailedToGetCurrentTime)?;
+
+        cert.verify_is_valid_tls_server_cert(
+            SUPPORTED_SIG_ALGS,
+            &webpki::TlsServerTrustAnchors(&anchors),
+            &chain,
+            now,
+        )
+        .map_err(invalid)?;
+
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+#[test]
+fn handshake_test() {
+    use rcgen::{BasicConstraints, Certificate as Generated, CertificateParams, IsCa};
+    use rustls::server::{AllowAnyAuthenticatedClient, NoClientAuth};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_postgres::Config;
+
+    let authority = |name: &str| {
+        let mut params = CertificateParams::new(vec![]);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, name);
+        Generated::from_params(params).unwrap()
+    };
+    let (trusted, untrusted) = (authority("trusted"), authority("untrusted"));
+    let server = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+    let client = rcgen::generate_simple_self_signed(vec!["lopez".to_owned()]).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("lopez-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, pem) in [
+        ("trusted.pem", trusted.serialize_pem().unwrap()),
+        ("untrusted.pem", untrusted.serialize_pem().unwrap()),
+        (
+            "client.pem",
+            client.serialize_pem_with_signer(&trusted).unwrap(),
+        ),
+        ("client.key", client.serialize_private_key_pem()),
+    ] {
+        std::fs::write(dir.join(name), pem).unwrap();
+    }
+
+    let mut client_roots = RootCertStore::empty();
+    client_roots
+        .add(&Certificate(trusted.serialize_der().unwrap()))
+        .unwrap();
+    let acceptor = |client_auth| {
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(client_auth)
+            .with_single_cert(
+                vec![Certificate(
+                    server.serialize_der_with_signer(&trusted).unwrap(),
+                )],
+                PrivateKey(server.serialize_private_key_der()),
+            )
+            .unwrap();
+        tokio_rustls::TlsAcceptor::from(Arc::new(config))
+    };
+    let acceptors = [
+        acceptor(NoClientAuth::new()),
+        acceptor(AllowAnyAuthenticatedClient::new(client_roots)),
+    ];
+
+    let outcomes = lib_lopez::backend::conformance::block_on(async move {
+        // Agrees to TLS and, once the handshake is done, refuses the login
+        // over the encrypted stream:
+        let serve = |acceptor: tokio_rustls::TlsAcceptor, listener: TcpListener| async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let mut ssl_request = [0; 8];
+                    stream.read_exact(&mut ssl_request).await?;
+                    stream.write_all(b"S").await?;
+
+                    let mut stream = acceptor.accept(stream).await?;
+                    let length = stream.read_u32().await?;
+                    let mut startup = vec![0; length as usize - 4];
+                    stream.read_exact(&mut startup).await?;
+
+                    let fields = b"SFATAL\0C28000\0Mhandshake done\0\0";
+                    stream.write_u8(b'E').await?;
+                    stream.write_u32(fields.len() as u32 + 4).await?;
+                    stream.write_all(fields).await?;
+                    stream.flush().await
+                });
+            }
+        };
+        let mut ports = vec![];
+        for acceptor in acceptors {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            ports.push(listener.local_addr().unwrap().port());
+            tokio::spawn(serve(acceptor, listener));
+        }
+
+        let mut outcomes = vec![];
+        for (wants_client_cert, mode, root_cert, client_cert) in [
+            (false, SslMode::Require, None, false),
+            (false, SslMode::VerifyFull, Some("trusted.pem"), false),
+            (false, SslMode::VerifyCa, Some("untrusted.pem"), false),
+            (false, SslMode::Require, Some("untrusted.pem"), false),
+            (true, SslMode::Require, None, true),
+            (true, SslMode::Require, None, false),
+        ] {
+            let tls = TlsOptions {
+                mode,
+                root_cert: root_cert.map(|name| dir.join(name)),
+                cert: client_cert.then(|| dir.join("client.pem")),
+                key: client_cert.then(|| dir.join("client.key")),
+            };
+            let mut config = Config::new();
+            config
+                .host("localhost")
+                .port(ports[wants_client_cert as usize])
+                .user("lopez")
+                .ssl_mode(tls.postgres_ssl_mode());
+
+            let err = config
+                .connect(tls.connector().unwrap())
+                .await
+                .err()
+                .unwrap();
+            let message = err.as_db_error().map(|err| err.message().to_owned());
+            outcomes.push(message.as_deref() == Some("handshake done"));
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        outcomes
+    });
+
+    // Only unknown authorities are refused, and `require` verifies when given
+    // some to trust, as libpq does. Servers asking for a client certificate
+    // get the one given:
+    assert_eq!(outcomes, vec![true, true, false, false, true, false]);
+}
@@ -0,0 +1,116 @@
+-- SQLite has no enums, no `jsonb` and no sequences. Everything else is as in
+-- the PostgreSQL backend, minus the old canonical stuff.
+
+create table pages (
+    -- use SipHash to define page_id. It will save some power.
+    page_id integer primary key,
+    page_url text unique not null
+);
+
+create table waves (
+    wave_id integer primary key autoincrement,
+    started_at text not null default current_timestamp,
+    wave_name text unique not null
+);
+
+create table linkage (
+    wave_id integer not null references waves (wave_id) on delete cascade,
+    from_page_id integer not null,
+    to_page_id integer not null,
+    reason text not null check (reason in ('ahref', 'redirect', 'canonical')),
+    primary key (wave_id, from_page_id, to_page_id, reason)
+);
+
+create index linkage_to_page_id_idx on linkage (wave_id, to_page_id);
+
+create table "status" (
+    wave_id integer not null references waves (wave_id) on delete cascade,
+    page_id integer not null,
+    status_code integer,
+    search_status text not null
+        check (search_status in ('open', 'taken', 'closed', 'error')),
+    depth integer not null,
+    constraint closing_criterion check (
+        search_status != 'closed' and status_code is null
+            or search_status = 'closed' and status_code is not null
+    ),
+    primary key (wave_id, page_id)
+);
+
+create index status_search_status_idx on "status" (wave_id, search_status, depth);
+create index status_page_id_idx on "status" (page_id);
+
+create table analyses (
+    wave_id integer not null references waves (wave_id) on delete cascade,
+    analysis_id integer primary key autoincrement,
+    analysis_name text not null,
+    result_type text not null,
+    unique (wave_id, analysis_name)
+);
+
+create table analysis_results (
+    wave_id integer not null references waves (wave_id) on delete cascade,
+    page_id integer not null,
+    analysis_id integer not null references analyses (analysis_id)
+        on delete cascade,
+    -- JSON, as text.
+    result text not null,
+    primary key (wave_id, page_id, analysis_id)
+);
+
+create index analysis_results_page_id_idx on analysis_results (page_id, analysis_id);
+
+create table page_rank (
+    wave_id integer not null references waves (wave_id) on delete cascade,
+    page_id integer not null,
+    rank real not null,
+    primary key (wave_id, page_id)
+);
+
+create index page_rank_rank_idx on page_rank (rank desc);
+
+create view named_analyses as
+    select
+        wave_name,
+        page_url,
+        analysis_name,
+        result
+    from
+        waves
+            join analyses using (wave_id)
+            join analysis_results using (wave_id, analysis_id)
+            join pages using (page_id);
+
+create view named_linkage as
+    select
+        wave_name,
+        from_pages.page_url as from_page_url,
+        to_pages.page_url as to_page_url,
+        reason
+    from
+        linkage
+            join waves on linkage.wave_id = waves.wave_id
+            join pages as from_pages on from_page_id = from_pages.page_id
+            join pages as to_pages on to_page_id = to_pages.page_id;
+
+create view named_status as
+    select
+        wave_name,
+        page_url,
+        status_code,
+        search_status,
+        depth
+    from
+        "status"
+            join waves on "status".wave_id = waves.wave_id
+            join pages on "status".page_id = pages.page_id;
+
+create view named_page_rank as
+    select
+        wave_name,
+        page_url,
+        rank
+    from
+        page_rank
+            join waves on page_rank.wave_id = waves.wave_id
+            join pages on page_rank.page_id = pages.page_id;
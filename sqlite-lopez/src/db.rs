@@ -0,0 +1,83 @@
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+use serde_derive::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use structopt::StructOpt;
+
+/// The migrations, in order. The database `user_version` is the number of
+/// migrations already applied.
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "20220301000000_init",
+        include_str!("../migrations/20220301000000_init/up.sql"),
+    ),
+    (
+        "20221018000000_wave-configurations",
+        include_str!("../migrations/20221018000000_wave-configurations/up.sql"),
+    ),
+    (
+        "20221101000000_seed-priorities",
+        include_str!("../migrations/20221101000000_seed-priorities/up.sql"),
+    ),
+];
+
+#[derive(Debug, StructOpt, Serialize, Deserialize)]
+pub struct DbConfig {
+    /// The path to the SQLite database file. It is created if it does not
+    /// exist.
+    #[structopt(long, env = "DB_PATH", default_value = "lopez.sqlite3")]
+    db_path: PathBuf,
+    /// How long, in milliseconds, to wait for another connection to release
+    /// the database before giving up.
+    #[structopt(long, env = "DB_BUSY_TIMEOUT", default_value = "60000")]
+    busy_timeout: u64,
+}
+
+impl DbConfig {
+    pub fn connect(&self) -> Result<Rc<Connection>, anyhow::Error> {
+        let connection = Connection::open(&self.db_path)?;
+
+        // WAL lets the workers write while the master reads (and vice-versa).
+        // Writers still take turns, so they need to be patient.
+        let journal_mode: String =
+            connection.query_row("pragma journal_mode = wal", [], |row| row.get(0))?;
+        if journal_mode != "wal" {
+            log::warn!("could not set WAL mode on database; got `{journal_mode}`");
+        }
+
+        connection.busy_timeout(Duration::from_millis(self.busy_timeout))?;
+        connection.pragma_update(None, "synchronous", "normal")?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+
+        Ok(Rc::new(connection))
+    }
+
+    /// Ensures all migrations are up-to-date.
+    pub fn sync_migrations(&self) -> Result<(), anyhow::Error> {
+        log::info!("Ensuring migrations are up-to-date");
+
+        let connection = self.connect()?;
+        let transaction = immediate(&connection)?;
+        let applied: usize = transaction.query_row("pragma user_version", [], |row| row.get(0))?;
+
+        for (tag, up) in MIGRATIONS.iter().skip(applied) {
+            log::info!("applying migration {tag}");
+            transaction.execute_batch(up)?;
+        }
+
+        transaction.pragma_update(None, "user_version", MIGRATIONS.len())?;
+        transaction.commit()?;
+
+        log::info!("everything up-to-date");
+
+        Ok(())
+    }
+}
+
+/// Starts a transaction that takes the write lock right away. Upgrading a
+/// read transaction to a write one fails immediately with `SQLITE_BUSY` if
+/// someone else wrote in between, regardless of the busy timeout.
+pub fn immediate(connection: &Connection) -> Result<Transaction<'_>, rusqlite::Error> {
+    Transaction::new_unchecked(connection, TransactionBehavior::Immediate)
+}
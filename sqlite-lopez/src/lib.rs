@@ -0,0 +1,394 @@
+//! A backend storing everything in a single SQLite file, for when running a
+//! PostgreSQL server is overkill.
+
+mod db;
+mod master;
+mod ranker;
+mod worker;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_derive::{Deserialize as DeriveDeserialize, Serialize as DeriveSerialize};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use lib_lopez::backend::{
+    async_trait, stream, typetag, Backend, ComparedPage, ExportedPage, LocalBoxStream,
+    MasterBackend, NaiveDateTime, PageSnapshot, StreamExt, TryStreamExt, Type, Url, Value,
+    WaveConfiguration, WaveExport, WaveRemoveReport, WaveStatus, WaveSummary, WorkerBackend,
+    WorkerBackendFactory,
+};
+
+use crate::db::{immediate, DbConfig};
+
+use self::master::SqliteMasterBackend;
+use self::ranker::SqlitePageRanker;
+use self::worker::SqliteWorkerBackend;
+
+const WAVE_SIZE: &str = include_str!("sql/wave_size.sql");
+const REMOVE_WAVE: &str = include_str!("sql/remove_wave.sql");
+const PAGES_GARBAGE_COLLECT: &str = include_str!("sql/pages_garbage_collect.sql");
+const FIND_WAVE: &str = include_str!("sql/find_wave.sql");
+const LIST_WAVES: &str = include_str!("sql/list_waves.sql");
+const COUNT_SEARCH_STATUS: &str = include_str!("sql/count_search_status.sql");
+const COUNT_STATUS_CODES: &str = include_str!("sql/count_status_codes.sql");
+const COUNT_DEPTHS: &str = include_str!("sql/count_depths.sql");
+const HAS_PAGE_RANK: &str = include_str!("sql/has_page_rank.sql");
+const NAMED_WAVE_CONFIGURATION: &str = include_str!("sql/named_wave_configuration.sql");
+const COMPARE_WAVES: &str = include_str!("sql/compare_waves.sql");
+const EXPORT_ANALYSES: &str = include_str!("sql/export_analyses.sql");
+const EXPORT_PAGES: &str = include_str!("sql/export_pages.sql");
+
+/// How many pages are read at a time when exporting.
+const EXPORT_CHUNK_SIZE: usize = 1024;
+
+pub struct SqliteBackend {
+    config: Arc<DbConfig>,
+    wave: String,
+}
+
+impl SqliteBackend {
+    fn connect(&self) -> Result<Rc<Connection>, anyhow::Error> {
+        self.config.connect()
+    }
+}
+
+/// Counts pages of a wave in buckets, given a query returning `bucket` and
+/// `n_pages`.
+fn histogram(
+    connection: &Connection,
+    query: &str,
+    wave_id: i32,
+) -> Result<BTreeMap<u16, usize>, anyhow::Error> {
+    let histogram = connection
+        .prepare(query)?
+        .query_map(params![wave_id], |row| {
+            Ok((row.get("bucket")?, row.get::<_, i64>("n_pages")? as usize))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(histogram)
+}
+
+/// Reads one side of a row of `compare_waves.sql`, whose columns are prefixed
+/// by `side`.
+fn page_snapshot(row: &rusqlite::Row, side: &str) -> Result<Option<PageSnapshot>, anyhow::Error> {
+    let status_code = if let Some(status_code) = row.get(&*format!("{side}_status_code"))? {
+        status_code
+    } else {
+        return Ok(None);
+    };
+    let redirect = row
+        .get::<_, Option<String>>(&*format!("{side}_redirect"))?
+        .map(|redirect| redirect.parse::<Url>())
+        .transpose()?;
+    let analyses = row.get::<_, String>(&*format!("{side}_analyses"))?;
+
+    Ok(Some(PageSnapshot {
+        status_code,
+        redirect,
+        analyses: serde_json::from_str(&analyses)?,
+    }))
+}
+
+/// Reads the next chunk of exported pages, after the page id `after`, if any.
+fn export_chunk(
+    connection: &Connection,
+    wave_id: i32,
+    after: Option<i64>,
+) -> Result<Vec<(i64, ExportedPage)>, anyhow::Error> {
+    let rows = connection
+        .prepare_cached(EXPORT_PAGES)?
+        .query_map(params![wave_id, after, EXPORT_CHUNK_SIZE], |row| {
+            Ok((
+                row.get::<_, i64>("page_id")?,
+                row.get::<_, String>("page_url")?,
+                row.get::<_, u16>("status_code")?,
+                row.get::<_, u16>("depth")?,
+                row.get::<_, Option<f64>>("rank")?,
+                row.get::<_, Option<String>>("analyses")?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    rows.into_iter()
+        .map(|(page_id, page_url, status_code, depth, rank, analyses)| {
+            let analyses = match analyses {
+                Some(analyses) => serde_json::from_str::<BTreeMap<String, Value>>(&analyses)?,
+                None => BTreeMap::new(),
+            };
+
+            Ok((
+                page_id,
+                ExportedPage {
+                    url: page_url.parse::<Url>()?,
+                    status_code,
+                    depth,
+                    rank,
+                    analyses,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[async_trait(?Send)]
+impl Backend for SqliteBackend {
+    type Config = DbConfig;
+    type Ranker = SqlitePageRanker;
+
+    async fn init(config: Self::Config, wave: &str) -> Result<Self, anyhow::Error> {
+        // Make sure db exists and is up to date:
+        config
+            .sync_migrations()
+            .map_err(|err| anyhow::anyhow!("Migration error: {err}"))?;
+
+        Ok(SqliteBackend {
+            config: Arc::new(config),
+            wave: wave.to_owned(),
+        })
+    }
+
+    async fn build_master(&mut self) -> Result<Box<dyn MasterBackend>, anyhow::Error> {
+        Ok(Box::new(SqliteMasterBackend::init(
+            self.connect()?,
+            &self.wave,
+        )?))
+    }
+
+    fn build_worker_factory(&mut self, wave_id: i32) -> Box<dyn WorkerBackendFactory> {
+        Box::new(SqliteWorkerFactory {
+            config: self.config.clone(),
+            wave_id,
+        })
+    }
+
+    async fn build_ranker(&mut self, wave_id: i32) -> Result<Self::Ranker, anyhow::Error> {
+        Ok(SqlitePageRanker::init(self.connect()?, wave_id))
+    }
+
+    async fn remove(&mut self) -> Result<WaveRemoveReport, anyhow::Error> {
+        let connection = self.connect()?;
+        let transaction = immediate(&connection)?;
+
+        let n_pages: i64 =
+            transaction.query_row(WAVE_SIZE, params![self.wave], |row| row.get("n_pages"))?;
+        let removed: Option<i32> = transaction
+            .query_row(REMOVE_WAVE, params![self.wave], |row| row.get("wave_id"))
+            .optional()?;
+
+        let report = if removed.is_some() {
+            transaction.execute(PAGES_GARBAGE_COLLECT, [])?;
+            WaveRemoveReport::removed(n_pages as usize)
+        } else {
+            WaveRemoveReport::not_removed()
+        };
+
+        transaction.commit()?;
+
+        Ok(report)
+    }
+
+    async fn list_waves(&mut self) -> Result<Vec<WaveSummary>, anyhow::Error> {
+        let waves = self
+            .connect()?
+            .prepare(LIST_WAVES)?
+            .query_map([], |row| {
+                Ok(WaveSummary {
+                    name: row.get("wave_name")?,
+                    started_at: row.get("started_at")?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(waves)
+    }
+
+    async fn wave_status(&mut self) -> Result<Option<WaveStatus>, anyhow::Error> {
+        let connection = self.connect()?;
+        let found = connection
+            .query_row(FIND_WAVE, params![self.wave], |row| {
+                Ok((row.get("wave_id")?, row.get("started_at")?))
+            })
+            .optional()?;
+        let (wave_id, started_at): (i32, NaiveDateTime) = if let Some(found) = found {
+            found
+        } else {
+            return Ok(None);
+        };
+
+        let mut status = WaveStatus {
+            name: self.wave.clone(),
+            started_at,
+            n_open: 0,
+            n_taken: 0,
+            n_closed: 0,
+            n_error: 0,
+            status_codes: histogram(&connection, COUNT_STATUS_CODES, wave_id)?,
+            depths: histogram(&connection, COUNT_DEPTHS, wave_id)?,
+            has_page_rank: connection.query_row(HAS_PAGE_RANK, params![wave_id], |row| {
+                row.get("has_page_rank")
+            })?,
+        };
+
+        let counts = connection
+            .prepare(COUNT_SEARCH_STATUS)?
+            .query_map(params![wave_id], |row| {
+                Ok((
+                    row.get::<_, String>("search_status")?,
+                    row.get::<_, i64>("n_pages")? as usize,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (search_status, n_pages) in counts {
+            match search_status.as_str() {
+                "open" => status.n_open = n_pages,
+                "taken" => status.n_taken = n_pages,
+                "closed" => status.n_closed = n_pages,
+                "error" => status.n_error = n_pages,
+                _ => anyhow::bail!("unknown search status `{search_status}`"),
+            }
+        }
+
+        Ok(Some(status))
+    }
+
+    async fn wave_configuration(&mut self) -> Result<Option<WaveConfiguration>, anyhow::Error> {
+        let stored = self
+            .connect()?
+            .query_row(NAMED_WAVE_CONFIGURATION, params![self.wave], |row| {
+                Ok((
+                    row.get("content_hash")?,
+                    row.get::<_, String>("configuration")?,
+                ))
+            })
+            .optional()?;
+
+        stored
+            .map(|(content_hash, configuration)| {
+                Ok(WaveConfiguration {
+                    content_hash,
+                    configuration: serde_json::from_str(&configuration)?,
+                })
+            })
+            .transpose()
+    }
+
+    async fn compare(
+        &mut self,
+        other_wave: &str,
+    ) -> Result<LocalBoxStream<'static, Result<ComparedPage, anyhow::Error>>, anyhow::Error> {
+        let connection = self.connect()?;
+        let find = |name: &str| {
+            connection
+                .query_row(FIND_WAVE, params![name], |row| row.get::<_, i32>("wave_id"))
+                .optional()?
+                .ok_or_else(|| anyhow::anyhow!("wave `{}` does not exist", name))
+        };
+        let (a, b) = (find(&self.wave)?, find(other_wave)?);
+
+        let mut statement = connection.prepare(COMPARE_WAVES)?;
+        let mut rows = statement.query(params![a, b])?;
+        let mut pages = vec![];
+
+        while let Some(row) = rows.next()? {
+            pages.push(Ok(ComparedPage {
+                url: row.get::<_, String>("page_url")?.parse()?,
+                a: page_snapshot(row, "a")?,
+                b: page_snapshot(row, "b")?,
+            }));
+        }
+
+        Ok(stream::iter(pages).boxed_local())
+    }
+
+    async fn export(&mut self) -> Result<Option<WaveExport>, anyhow::Error> {
+        let connection = self.connect()?;
+        let wave_id = connection
+            .query_row(FIND_WAVE, params![self.wave], |row| row.get("wave_id"))
+            .optional()?;
+        let wave_id: i32 = if let Some(wave_id) = wave_id {
+            wave_id
+        } else {
+            return Ok(None);
+        };
+
+        let analyses = connection
+            .prepare(EXPORT_ANALYSES)?
+            .query_map(params![wave_id], |row| {
+                Ok((row.get("analysis_name")?, row.get("result_type")?))
+            })?
+            .map(|row| {
+                let (name, result_type): (String, String) = row?;
+                let typ = result_type.parse::<Type>().map_err(|err| {
+                    anyhow::anyhow!("bad result type for analysis `{name}`: {err}")
+                })?;
+                Ok((name, typ))
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        // Statements borrow the connection, so pages are read in chunks. The
+        // state is the last page id read and whether there is more to read.
+        let pages = stream::try_unfold((None, true), move |(after, has_more)| {
+            let connection = connection.clone();
+            async move {
+                if !has_more {
+                    return Ok::<_, anyhow::Error>(None);
+                }
+
+                let chunk = export_chunk(&connection, wave_id, after)?;
+                let state = (
+                    chunk.last().map(|(page_id, _)| *page_id),
+                    chunk.len() == EXPORT_CHUNK_SIZE,
+                );
+                let pages = chunk.into_iter().map(|(_, page)| Ok(page));
+
+                Ok(Some((stream::iter(pages), state)))
+            }
+        })
+        .try_flatten()
+        .boxed_local();
+
+        Ok(Some(WaveExport { analyses, pages }))
+    }
+}
+
+#[derive(Debug, DeriveSerialize, DeriveDeserialize)]
+pub struct SqliteWorkerFactory {
+    config: Arc<DbConfig>,
+    wave_id: i32,
+}
+
+#[typetag::serde]
+#[async_trait(?Send)]
+impl WorkerBackendFactory for SqliteWorkerFactory {
+    async fn build(&self) -> Result<Box<dyn WorkerBackend>, anyhow::Error> {
+        Ok(Box::new(SqliteWorkerBackend::init(
+            self.config.connect()?,
+            self.wave_id,
+        )))
+    }
+}
+
+#[test]
+fn conformance_test() {
+    use lib_lopez::backend::conformance;
+    use structopt::StructOpt;
+
+    let db_path = std::env::temp_dir().join(format!(
+        "sqlite-lopez-conformance-{}.sqlite3",
+        std::process::id()
+    ));
+    let db_path = db_path.to_string_lossy().into_owned();
+
+    conformance::block_on(conformance::run_all(|wave| {
+        let config = DbConfig::from_iter(["test", "--db-path", &db_path]);
+        async move { SqliteBackend::init(config, &wave).await }
+    }))
+    .unwrap();
+
+    for suffix in ["", "-wal", "-shm"] {
+        std::fs::remove_file(format!("{db_path}{suffix}")).ok();
+    }
+}
@@ -0,0 +1,199 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::rc::Rc;
+
+use lib_lopez::backend::{async_trait, MasterBackend, SeedPriority, Type, Url, WaveConfiguration};
+use lib_lopez::hash;
+
+use crate::db::immediate;
+
+const ENSURE_WAVE: &str = include_str!("sql/ensure_wave.sql");
+const FIND_WAVE: &str = include_str!("sql/find_wave.sql");
+const ENSURE_STATUS: &str = include_str!("sql/ensure_status.sql");
+const ENSURE_NAMES: &str = include_str!("sql/ensure_names.sql");
+const CREATE_ANALYSES: &str = include_str!("sql/create_analyses.sql");
+const RESET_QUEUE: &str = include_str!("sql/reset_queue.sql");
+const FETCH: &str = include_str!("sql/fetch.sql");
+const COUNT_CRAWLED: &str = include_str!("sql/count_crawled.sql");
+const EXISTS_TAKEN: &str = include_str!("sql/exists_taken.sql");
+const ENSURE_CONFIGURATION: &str = include_str!("sql/ensure_configuration.sql");
+const WAVE_CONFIGURATION: &str = include_str!("sql/wave_configuration.sql");
+const SEED_FROM: &str = include_str!("sql/seed_from.sql");
+
+pub struct SqliteMasterBackend {
+    connection: Rc<Connection>,
+    wave_id: i32,
+}
+
+impl SqliteMasterBackend {
+    pub fn init(
+        connection: Rc<Connection>,
+        wave: &str,
+    ) -> Result<SqliteMasterBackend, anyhow::Error> {
+        // Find out current wave:
+        connection.execute(ENSURE_WAVE, params![wave])?;
+        let wave_id = connection.query_row(FIND_WAVE, params![wave], |row| row.get("wave_id"))?;
+
+        Ok(SqliteMasterBackend {
+            connection,
+            wave_id,
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl MasterBackend for SqliteMasterBackend {
+    fn wave_id(&mut self) -> i32 {
+        self.wave_id
+    }
+
+    async fn ensure_seeded(&mut self, seeds: &[Url]) -> Result<(), anyhow::Error> {
+        let transaction = immediate(&self.connection)?;
+
+        for seed in seeds {
+            let page_id = hash(&seed.as_str());
+
+            // Seeds are now a known page.
+            transaction
+                .prepare_cached(ENSURE_NAMES)?
+                .execute(params![page_id, seed.as_str()])?;
+
+            // Seeds are marked as visited.
+            transaction.prepare_cached(ENSURE_STATUS)?.execute(params![
+                self.wave_id,
+                page_id,
+                0i16
+            ])?;
+        }
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    async fn ensure_seeded_from(
+        &mut self,
+        wave: &str,
+        priority: Option<SeedPriority>,
+    ) -> Result<Option<usize>, anyhow::Error> {
+        let transaction = immediate(&self.connection)?;
+        let wave_id = transaction
+            .query_row(FIND_WAVE, params![wave], |row| row.get::<_, i32>("wave_id"))
+            .optional()?;
+        let n_seeded = if let Some(wave_id) = wave_id {
+            transaction.execute(
+                SEED_FROM,
+                params![
+                    self.wave_id,
+                    wave_id,
+                    priority.map(|priority| priority.as_str())
+                ],
+            )?
+        } else {
+            return Ok(None);
+        };
+        transaction.commit()?;
+
+        Ok(Some(n_seeded))
+    }
+
+    async fn create_analyses(
+        &mut self,
+        analysis_names: &[(String, Type)],
+    ) -> Result<(), anyhow::Error> {
+        let transaction = immediate(&self.connection)?;
+
+        for (name, typ) in analysis_names {
+            transaction
+                .prepare_cached(CREATE_ANALYSES)?
+                .execute(params![self.wave_id, name, typ.to_string()])?;
+        }
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    async fn count_crawled(&mut self) -> Result<usize, anyhow::Error> {
+        let crawled = self
+            .connection
+            .prepare_cached(COUNT_CRAWLED)?
+            .query_row(params![self.wave_id], |row| row.get::<_, i64>("crawled"))?;
+
+        Ok(crawled as usize)
+    }
+
+    async fn reset_queue(&mut self) -> Result<(), anyhow::Error> {
+        self.connection
+            .prepare_cached(RESET_QUEUE)?
+            .execute(params![self.wave_id])?;
+
+        Ok(())
+    }
+
+    async fn exists_taken(&mut self) -> Result<bool, anyhow::Error> {
+        let exists_taken = self
+            .connection
+            .prepare_cached(EXISTS_TAKEN)?
+            .query_row(params![self.wave_id], |row| row.get("exists_taken"))?;
+
+        Ok(exists_taken)
+    }
+
+    async fn fetch(
+        &mut self,
+        batch_size: i64,
+        max_depth: i16,
+    ) -> Result<Vec<(Url, u16)>, anyhow::Error> {
+        let transaction = immediate(&self.connection)?;
+        let batch = transaction
+            .prepare_cached(FETCH)?
+            .query_map(params![self.wave_id, batch_size, max_depth], |row| {
+                Ok((
+                    row.get::<_, String>("page_url")?,
+                    row.get::<_, u16>("depth")?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        transaction.commit()?;
+
+        let batch = batch
+            .into_iter()
+            .filter_map(|(page_url, depth)| Some((page_url.parse::<Url>().ok()?, depth)))
+            .collect::<Vec<_>>();
+
+        Ok(batch)
+    }
+
+    async fn ensure_configuration(
+        &mut self,
+        configuration: &WaveConfiguration,
+    ) -> Result<Option<WaveConfiguration>, anyhow::Error> {
+        let transaction = immediate(&self.connection)?;
+        transaction.execute(
+            ENSURE_CONFIGURATION,
+            params![
+                self.wave_id,
+                configuration.content_hash,
+                configuration.configuration.to_string(),
+            ],
+        )?;
+        let stored = transaction
+            .query_row(WAVE_CONFIGURATION, params![self.wave_id], |row| {
+                Ok((
+                    row.get("content_hash")?,
+                    row.get::<_, String>("configuration")?,
+                ))
+            })
+            .optional()?;
+        transaction.commit()?;
+
+        stored
+            .map(|(content_hash, configuration)| {
+                Ok(WaveConfiguration {
+                    content_hash,
+                    configuration: serde_json::from_str(&configuration)?,
+                })
+            })
+            .transpose()
+    }
+}
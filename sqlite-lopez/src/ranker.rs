@@ -0,0 +1,60 @@
+use rusqlite::{params, Connection};
+use std::rc::Rc;
+
+use lib_lopez::backend::{async_trait, PageRanker};
+
+use crate::db::immediate;
+
+const LINKAGE: &str = include_str!("sql/linkage.sql");
+const ENSURE_PAGE_RANK: &str = include_str!("sql/ensure_page_rank.sql");
+
+pub struct SqlitePageRanker {
+    connection: Rc<Connection>,
+    wave_id: i32,
+}
+
+impl SqlitePageRanker {
+    pub(super) fn init(connection: Rc<Connection>, wave_id: i32) -> SqlitePageRanker {
+        SqlitePageRanker {
+            connection,
+            wave_id,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl PageRanker for SqlitePageRanker {
+    type PageId = i64;
+
+    async fn linkage(
+        &mut self,
+    ) -> Result<Box<dyn Iterator<Item = (Self::PageId, Self::PageId)>>, anyhow::Error> {
+        // Statements borrow the connection, so the edges have to be collected:
+        let edges = self
+            .connection
+            .prepare_cached(LINKAGE)?
+            .query_map(params![self.wave_id], |row| {
+                Ok((row.get("from_page_id")?, row.get("to_page_id")?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Box::new(edges.into_iter()))
+    }
+
+    async fn push_page_ranks(
+        &mut self,
+        ranked: &[(Self::PageId, f64)],
+    ) -> Result<(), anyhow::Error> {
+        let transaction = immediate(&self.connection)?;
+
+        for (page_id, rank) in ranked {
+            transaction
+                .prepare_cached(ENSURE_PAGE_RANK)?
+                .execute(params![self.wave_id, page_id, rank])?;
+        }
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+-- Pages closed in either wave `?1` or `?2`, side by side. Columns of a side
+-- are null when the page was not crawled in that wave.
+with snapshots as (
+    select
+        wave_id,
+        page_id,
+        status_code,
+        (
+            select
+                page_url
+            from
+                linkage join pages on pages.page_id = linkage.to_page_id
+            where
+                linkage.wave_id = "status".wave_id
+                    and linkage.from_page_id = "status".page_id
+                    and reason = 'redirect'
+            order by
+                linkage.to_page_id
+            limit
+                1
+        ) as redirect,
+        (
+            select
+                json_group_object(analysis_name, json(result))
+            from
+                analysis_results join analyses using (wave_id, analysis_id)
+            where
+                analysis_results.wave_id = "status".wave_id
+                    and analysis_results.page_id = "status".page_id
+        ) as analyses
+    from
+        "status"
+    where
+        wave_id in (?1, ?2)
+            and search_status = 'closed'
+)
+select
+    page_url,
+    a.status_code as a_status_code,
+    a.redirect as a_redirect,
+    a.analyses as a_analyses,
+    b.status_code as b_status_code,
+    b.redirect as b_redirect,
+    b.analyses as b_analyses
+from
+    (select distinct page_id from snapshots) as compared
+        join pages using (page_id)
+        left join snapshots as a
+            on a.page_id = compared.page_id and a.wave_id = ?1
+        left join snapshots as b
+            on b.page_id = compared.page_id and b.wave_id = ?2
+order by
+    compared.page_id
@@ -0,0 +1,9 @@
+select
+    depth as bucket,
+    count(*) as n_pages
+from
+    "status"
+where
+    wave_id = ?1
+group by
+    depth
@@ -0,0 +1,9 @@
+select
+    search_status,
+    count(*) as n_pages
+from
+    "status"
+where
+    wave_id = ?1
+group by
+    search_status
@@ -0,0 +1,9 @@
+select
+    status_code as bucket,
+    count(*) as n_pages
+from
+    "status"
+where
+    wave_id = ?1 and search_status = 'closed'
+group by
+    status_code
@@ -0,0 +1,5 @@
+insert into
+    analyses (wave_id, analysis_name, result_type)
+values
+    (?1, ?2, ?3)
+on conflict (wave_id, analysis_name) do nothing;
@@ -0,0 +1,12 @@
+insert into
+    analysis_results (wave_id, page_id, analysis_id, result)
+select
+    ?1,
+    ?2,
+    analysis_id,
+    ?4
+from
+    analyses
+where
+    wave_id = ?1 and analysis_name = ?3
+on conflict do nothing;
@@ -0,0 +1,7 @@
+update
+    "status"
+set
+    status_code = ?3,
+    search_status = 'closed'
+where
+    wave_id = ?1 and page_id = ?2;
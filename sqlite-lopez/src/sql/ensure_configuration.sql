@@ -0,0 +1,7 @@
+update
+    waves
+set
+    content_hash = ?2,
+    configuration = ?3
+where
+    wave_id = ?1 and configuration is null
@@ -0,0 +1,6 @@
+update
+    "status"
+set
+    search_status = 'error'
+where
+    wave_id = ?1 and page_id = ?2;
@@ -0,0 +1,5 @@
+insert into
+    linkage (wave_id, from_page_id, to_page_id, reason)
+values
+    (?1, ?2, ?3, ?4)
+on conflict do nothing;
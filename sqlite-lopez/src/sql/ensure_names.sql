@@ -0,0 +1,5 @@
+insert into
+    pages (page_id, page_url)
+values
+    (?1, ?2)
+on conflict do nothing;
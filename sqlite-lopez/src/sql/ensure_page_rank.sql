@@ -0,0 +1,5 @@
+insert into
+    page_rank (wave_id, page_id, rank)
+values
+    (?1, ?2, ?3)
+on conflict do nothing;
@@ -0,0 +1,5 @@
+insert into
+    "status" (wave_id, page_id, search_status, depth)
+values
+    (?1, ?2, 'open', ?3)
+on conflict do nothing;
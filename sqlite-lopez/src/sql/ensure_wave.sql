@@ -0,0 +1,5 @@
+insert into
+    waves (wave_name)
+values
+    (?1)
+on conflict (wave_name) do nothing;
@@ -0,0 +1,9 @@
+select exists (
+    select
+        1
+    from
+        "status"
+    where
+        wave_id = ?1
+            and search_status = 'taken'
+) as exists_taken
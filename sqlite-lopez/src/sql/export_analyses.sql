@@ -0,0 +1,9 @@
+select
+    analysis_name,
+    result_type
+from
+    analyses
+where
+    wave_id = ?1
+order by
+    analysis_id
@@ -0,0 +1,28 @@
+-- Pages come in chunks, ordered by `page_id`, starting after `?2`.
+select
+    page_id,
+    page_url,
+    status_code,
+    depth,
+    rank,
+    (
+        select
+            json_group_object(analysis_name, json(result))
+        from
+            analysis_results join analyses using (wave_id, analysis_id)
+        where
+            analysis_results.wave_id = "status".wave_id
+                and analysis_results.page_id = "status".page_id
+    ) as analyses
+from
+    "status"
+        join pages using (page_id)
+        left join page_rank using (wave_id, page_id)
+where
+    "status".wave_id = ?1
+        and search_status = 'closed'
+        and (?2 is null or page_id > ?2)
+order by
+    page_id
+limit
+    ?3
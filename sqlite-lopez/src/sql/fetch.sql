@@ -0,0 +1,51 @@
+with open_pages as (
+    -- Get a bunch of open low-depth pages. No regexes in SQLite, so the host
+    -- is whatever lies between `://` and the next `/`.
+    select
+        page_id,
+        page_url,
+        depth,
+        priority,
+        substr(page_url, instr(page_url, '://') + 3) as rest
+    from
+        "status" join pages using (page_id)
+    where
+        wave_id = ?1
+            and search_status = 'open'
+            and depth <= ?3
+), numbered as (
+    select
+        page_id,
+        depth,
+        priority,
+        count(*) over (
+            partition by substr(rest, 1, instr(rest || '/', '/') - 1)
+            order by priority desc nulls last, depth
+        ) as count
+    from
+        open_pages
+), to_take as (
+    -- From the pages, get the ones that are low count. This ensures a
+    -- plurality of domanins in each batch.
+    select
+        page_id
+    from
+        numbered
+    order by
+        count,
+        priority desc nulls last,
+        depth
+    limit
+        ?2
+) update
+    "status"
+set
+    search_status = 'taken'
+from
+    to_take
+where
+    wave_id = ?1
+        and to_take.page_id = "status".page_id
+returning
+    (select page_url from pages where pages.page_id = "status".page_id) as page_url,
+    depth;
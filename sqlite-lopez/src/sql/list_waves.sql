@@ -0,0 +1,7 @@
+select
+    wave_name,
+    started_at
+from
+    waves
+order by
+    wave_id
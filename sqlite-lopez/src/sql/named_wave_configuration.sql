@@ -0,0 +1,7 @@
+select
+    content_hash,
+    configuration
+from
+    waves
+where
+    wave_name = ?1 and configuration is not null
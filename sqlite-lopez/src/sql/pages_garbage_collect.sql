@@ -0,0 +1,12 @@
+-- No statement triggers in SQLite, so this is run by hand after removing a
+-- wave.
+delete from
+    pages
+where
+    page_id not in (
+        select page_id from "status"
+        union
+        select from_page_id from linkage
+        union
+        select to_page_id from linkage
+    );
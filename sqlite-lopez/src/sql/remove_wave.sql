@@ -0,0 +1,6 @@
+delete from
+    waves
+where
+    wave_name = ?1
+returning
+    wave_id;
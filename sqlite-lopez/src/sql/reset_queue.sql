@@ -0,0 +1,6 @@
+update
+    "status"
+set
+    search_status = 'open'
+where
+    wave_id = ?1 and search_status in ('taken', 'error');
@@ -0,0 +1,59 @@
+-- Seeds wave `?1` with the pages closed in wave `?2`, at their depths there,
+-- with a priority given by `?3`: null, 'page_rank' or 'change_frequency'.
+with versions as (
+    -- Every version of these pages seen across all waves, as status code and
+    -- analyses. Only needed for 'change_frequency'.
+    select
+        page_id,
+        status_code || ' ' || (
+            select
+                json_group_object(analysis_name, json(result))
+            from (
+                select
+                    analysis_name,
+                    result
+                from
+                    analysis_results join analyses using (wave_id, analysis_id)
+                where
+                    analysis_results.wave_id = "status".wave_id
+                        and analysis_results.page_id = "status".page_id
+                order by
+                    analysis_name
+            )
+        ) as version
+    from
+        "status"
+    where
+        ?3 = 'change_frequency'
+            and search_status = 'closed'
+            and page_id in (
+                select page_id from "status" where wave_id = ?2 and search_status = 'closed'
+            )
+), change_frequency as (
+    select
+        page_id,
+        count(distinct version) as n_versions
+    from
+        versions
+    group by
+        page_id
+)
+insert into
+    "status" (wave_id, page_id, search_status, depth, priority)
+select
+    ?1,
+    page_id,
+    'open',
+    depth,
+    case ?3
+        when 'page_rank' then rank
+        when 'change_frequency' then n_versions
+    end
+from
+    "status"
+        left join page_rank using (wave_id, page_id)
+        left join change_frequency using (page_id)
+where
+    wave_id = ?2
+        and search_status = 'closed'
+on conflict do nothing;
@@ -0,0 +1,6 @@
+select
+    count(*) as n_pages
+from
+    "status" join waves using (wave_id)
+where
+    search_status = 'closed' and wave_name = ?1
@@ -0,0 +1,109 @@
+use rusqlite::{params, Connection};
+use std::rc::Rc;
+
+use lib_lopez::backend::{async_trait, Reason, StatusCode, Url, Value, WorkerBackend};
+use lib_lopez::hash;
+
+use crate::db::immediate;
+
+const ENSURE_LINKS: &str = include_str!("sql/ensure_links.sql");
+const ENSURE_ANALYZED: &str = include_str!("sql/ensure_analyzed.sql");
+const ENSURE_CLOSED: &str = include_str!("sql/ensure_closed.sql");
+const ENSURE_ERROR: &str = include_str!("sql/ensure_error.sql");
+const ENSURE_STATUS: &str = include_str!("sql/ensure_status.sql");
+const ENSURE_NAMES: &str = include_str!("sql/ensure_names.sql");
+
+pub struct SqliteWorkerBackend {
+    connection: Rc<Connection>,
+    wave_id: i32,
+}
+
+impl SqliteWorkerBackend {
+    pub(super) fn init(connection: Rc<Connection>, wave_id: i32) -> SqliteWorkerBackend {
+        SqliteWorkerBackend {
+            connection,
+            wave_id,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl WorkerBackend for SqliteWorkerBackend {
+    async fn ensure_active(&self, _url: &Url) -> Result<(), anyhow::Error> {
+        // TODO
+        Ok(())
+    }
+
+    async fn ensure_analyzed(
+        &self,
+        url: &Url,
+        analyses: Vec<(String, Value)>,
+    ) -> Result<(), anyhow::Error> {
+        let page_id = hash(&url.as_str());
+        let transaction = immediate(&self.connection)?;
+
+        for (analysis_name, result) in analyses {
+            transaction
+                .prepare_cached(ENSURE_ANALYZED)?
+                .execute(params![
+                    self.wave_id,
+                    page_id,
+                    analysis_name,
+                    serde_json::to_string(&result)?,
+                ])?;
+        }
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    async fn ensure_explored(
+        &self,
+        from_url: &Url,
+        status_code: StatusCode,
+        link_depth: u16,
+        links: Vec<(Reason, Url)>,
+    ) -> Result<(), anyhow::Error> {
+        let wave_id = self.wave_id;
+        let from_page_id = hash(&from_url.as_str());
+        let transaction = immediate(&self.connection)?;
+
+        for (reason, to_url) in links {
+            let to_page_id = hash(&to_url.as_str());
+
+            transaction.prepare_cached(ENSURE_LINKS)?.execute(params![
+                wave_id,
+                from_page_id,
+                to_page_id,
+                reason.to_string()
+            ])?;
+            transaction
+                .prepare_cached(ENSURE_NAMES)?
+                .execute(params![to_page_id, to_url.as_str()])?;
+            transaction
+                .prepare_cached(ENSURE_STATUS)?
+                .execute(params![wave_id, to_page_id, link_depth])?;
+        }
+
+        transaction.prepare_cached(ENSURE_CLOSED)?.execute(params![
+            wave_id,
+            from_page_id,
+            status_code.as_u16()
+        ])?;
+
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    async fn ensure_error(&self, url: &Url) -> Result<(), anyhow::Error> {
+        let page_id = hash(&url.as_str());
+
+        self.connection
+            .prepare_cached(ENSURE_ERROR)?
+            .execute(params![self.wave_id, page_id])?;
+
+        Ok(())
+    }
+}